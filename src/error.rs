@@ -13,6 +13,8 @@ pub enum Kind {
     Channel,
     Poll,
     IO,
+    Parse(usize),
+    Line(usize),
 }
 
 impl StdError for Kind {}
@@ -25,6 +27,10 @@ impl fmt::Display for Kind {
             Kind::Channel => write!(f, "Channel Error"),
             Kind::Poll => write!(f, "Poll Error"),
             Kind::IO => write!(f, "IO Error"),
+            Kind::Parse(position) => {
+                write!(f, "Parse Error at position {}", position)
+            }
+            Kind::Line(line) => write!(f, "Parse Error on line {}", line),
         }
     }
 }
@@ -2,6 +2,7 @@ pub mod convert;
 pub mod error;
 pub mod hwp;
 pub mod music;
+pub mod sequence;
 pub mod sound;
 
 use crate::convert::LossyFrom;
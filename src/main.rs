@@ -4,8 +4,10 @@ use alsa::{
 };
 use mpsc::{Receiver, Sender, SyncSender};
 use std::{
+    env,
     fmt::Debug,
     io,
+    path::Path,
     sync::{
         atomic::{AtomicBool, Ordering},
         mpsc, Arc, Barrier,
@@ -16,13 +18,15 @@ use std::{
 use zinnia::{
     convert::LossyFrom,
     hwp::{HardwareParams, HwpBuilder},
-    music::Note,
+    music::{tuning::EqualTemperament, Note},
+    sequence::{Sequence, SineInstrument},
     sound::{
         self,
         config::SoundConfigCollection,
-        filter::{LinearFadeIn, LinearFadeOut},
-        CachedPeriod, CachedSound, InputConfig, Sinusoid, Sound, Ticks, C4_PIANO_2_CH_SOUND,
-        SINE_PERIOD_2_CH,
+        envelope::{Curve, EnvelopeGenerator, Enveloped},
+        filter::{reverb::Reverb, Filter},
+        CachedPeriod, CachedSound, InputConfig, Sinusoid, Sound, Ticks, Timeline,
+        C4_PIANO_2_CH_SOUND, SINE_PERIOD_2_CH, UNTIL_RELEASED,
     },
     Result,
 };
@@ -32,6 +36,7 @@ fn generate<T>(
     hwp: &HardwareParams<T>,
     sound_rx: Receiver<Box<dyn Sound>>,
     period_tx: SyncSender<Vec<T>>,
+    mut master: Option<Reverb>,
 ) -> JoinHandle<Result<()>>
 where
     T: Send + 'static + IoFormat + LossyFrom<f32>,
@@ -43,16 +48,23 @@ where
         let size = period_size * channels as usize;
         let mut vals = Vec::<T>::with_capacity(size);
         let mut sounds = Vec::<Box<dyn Sound>>::new();
+        let mut tick: Ticks = 0;
         while running.load(Ordering::Relaxed) {
             if let Ok(sound) = sound_rx.try_recv() {
                 sounds.push(sound);
             }
 
             for channel in 0..channels {
-                vals.push(LossyFrom::lossy_from(sound::mix_fixed(
-                    &mut sounds,
-                    channel,
-                )));
+                let mut val = sound::mix_fixed(&mut sounds, channel);
+                // Master bus reverb over every sound, enabled with --reverb.
+                if let Some(reverb) = &mut master {
+                    val = reverb.apply(val, tick, channel);
+                }
+                vals.push(LossyFrom::lossy_from(val));
+            }
+            tick = tick.wrapping_add(1);
+            if let Some(reverb) = &mut master {
+                reverb.tick();
             }
 
             sounds.iter_mut().for_each(|s| s.tick());
@@ -121,8 +133,16 @@ where
         let amplitude_scale = sound::max_amplitude::<T>() as f32;
         let phase = 0.0;
         let duration_ticks = sound::duration_to_ticks(duration, params.rate());
-        let fade_in_ticks = (duration_ticks as f32 * 0.2) as Ticks;
-        let fade_out_ticks = (duration_ticks as f32 * 0.5) as Ticks;
+        let attack_ticks = (duration_ticks as f32 * 0.2) as Ticks;
+        let decay_ticks = (duration_ticks as f32 * 0.1) as Ticks;
+        let release_ticks = (duration_ticks as f32 * 0.5) as Ticks;
+        let envelope = EnvelopeGenerator::adsr(
+            attack_ticks,
+            decay_ticks,
+            0.8,
+            release_ticks,
+            Curve::Exponential,
+        );
         while running.load(Ordering::Relaxed) {
             let mut note = String::new();
             io::stdin().read_line(&mut note)?;
@@ -142,17 +162,15 @@ where
                             ]
                             .as_ref(),
                         );
-                        let mut sound = Box::new(Sinusoid::new(&config, duration, &params));
+                        let mut timeline = Timeline::new();
+                        let gap_ticks = (duration_ticks as f32 * 1.1) as Ticks;
 
-                        sound.add_filter(Box::new(LinearFadeIn::new(fade_in_ticks)));
-
-                        sound.add_filter(Box::new(LinearFadeOut::new(
-                            fade_out_ticks,
-                            duration_ticks,
-                        )));
+                        // Released so that the tail ends at `duration`.
+                        let release_at = duration_ticks - release_ticks;
 
-                        sound_tx.send(sound)?;
-                        thread::sleep(duration.mul_f32(1.1));
+                        let sound = Sinusoid::with_ticks(&config, UNTIL_RELEASED, params.rate());
+                        let sound = Enveloped::new(Box::new(sound), envelope.clone());
+                        timeline.schedule_release(0, release_at, Box::new(sound));
 
                         let config = SoundConfigCollection::with_configs(
                             [
@@ -162,22 +180,18 @@ where
                             .as_ref(),
                         );
 
-                        let mut sound = Box::new(CachedPeriod::new(
+                        let sound = CachedPeriod::with_ticks(
                             InputConfig::new(&SINE_PERIOD_2_CH[..], 2, 2),
                             &config,
-                            duration,
-                            &params,
-                        ));
-
-                        sound.add_filter(Box::new(LinearFadeIn::new(fade_in_ticks)));
-
-                        sound.add_filter(Box::new(LinearFadeOut::new(
-                            fade_out_ticks,
-                            duration_ticks,
-                        )));
-
-                        sound_tx.send(sound)?;
-                        thread::sleep(duration.mul_f32(1.1));
+                            UNTIL_RELEASED,
+                            params.rate(),
+                        );
+                        let sound = Enveloped::new(Box::new(sound), envelope.clone());
+                        timeline.schedule_release(
+                            gap_ticks,
+                            gap_ticks + release_at,
+                            Box::new(sound),
+                        );
 
                         let sound = Box::new(CachedSound::new(InputConfig::new(
                             &C4_PIANO_2_CH_SOUND[..],
@@ -185,14 +199,15 @@ where
                             1,
                         )));
 
-                        sound_tx.send(sound)?;
-                        thread::sleep(Duration::from_secs(5));
+                        timeline.schedule(2 * gap_ticks, sound);
+
+                        sound_tx.send(Box::new(timeline))?;
                     } else {
                         println!("Invalid Note!");
                     }
                 }
-                Err(_) => {
-                    println!("Failed to parse note!");
+                Err(err) => {
+                    println!("Failed to parse note! {}", err);
                 }
             }
         }
@@ -232,10 +247,26 @@ where
     drop(param_rx);
     println!("Initialized: {:?}", params);
 
-    let handle = generate(Arc::clone(&running), &params, sound_rx, period_tx);
+    let args: Vec<String> = env::args().skip(1).collect();
+    let master = args
+        .iter()
+        .any(|arg| arg == "--reverb")
+        .then(|| Reverb::new(params.rate()).room_size(0.6).mix(0.2));
+
+    let handle = generate(Arc::clone(&running), &params, sound_rx, period_tx, master);
 
     handles.push(handle);
 
+    if let Some(path) = args.iter().find(|arg| !arg.starts_with("--")) {
+        let sequence = match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some("abc") => Sequence::open_abc(path)?,
+            Some("musicxml") | Some("xml") => Sequence::open_musicxml(path)?,
+            _ => Sequence::open_midi(path)?,
+        };
+        let timeline = sequence.render(&SineInstrument, &EqualTemperament::default(), &params)?;
+        sound_tx.send(Box::new(timeline))?;
+    }
+
     let handle = input(Arc::clone(&running), sound_tx, params);
     handles.push(handle);
 
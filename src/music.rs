@@ -1,14 +1,73 @@
-use std::{convert::TryFrom, u32};
+pub mod chord;
+pub mod interval;
+pub mod rhythm;
+pub mod scala;
+pub mod scale;
+pub mod tuning;
+
+use std::{
+    convert::TryFrom,
+    fmt,
+    iter::{Enumerate, Peekable},
+    str::{Chars, FromStr},
+};
 
 use super::error::{Error, Kind};
 use super::Result;
 use std::result::Result as StdResult;
+use tuning::{EqualTemperament, Tuning};
 
 const KEYS_PER_OCTAVE: i32 = 12;
 const START_KEY_OFFSET: i32 = 8;
+const MAX_ACCIDENTALS: i32 = 2;
+const MAX_MIDI_NUMBER: i32 = 127;
+const A4_MIDI_NUMBER: i32 = 69;
+const A4_FREQ: f32 = 440.0;
+const CENTS_PER_SEMITONE: f32 = 100.0;
+
+const SHARP_SPELLINGS: [(Letter, Accidental); 12] = [
+    (Letter::C, Accidental::Natural),
+    (Letter::C, Accidental::Sharp),
+    (Letter::D, Accidental::Natural),
+    (Letter::D, Accidental::Sharp),
+    (Letter::E, Accidental::Natural),
+    (Letter::F, Accidental::Natural),
+    (Letter::F, Accidental::Sharp),
+    (Letter::G, Accidental::Natural),
+    (Letter::G, Accidental::Sharp),
+    (Letter::A, Accidental::Natural),
+    (Letter::A, Accidental::Sharp),
+    (Letter::B, Accidental::Natural),
+];
+
+const FLAT_SPELLINGS: [(Letter, Accidental); 12] = [
+    (Letter::C, Accidental::Natural),
+    (Letter::D, Accidental::Flat),
+    (Letter::D, Accidental::Natural),
+    (Letter::E, Accidental::Flat),
+    (Letter::E, Accidental::Natural),
+    (Letter::F, Accidental::Natural),
+    (Letter::G, Accidental::Flat),
+    (Letter::G, Accidental::Natural),
+    (Letter::A, Accidental::Flat),
+    (Letter::A, Accidental::Natural),
+    (Letter::B, Accidental::Flat),
+    (Letter::B, Accidental::Natural),
+];
 
-#[derive(Debug, Clone, Copy)]
+const LETTERS: [Letter; 7] = [
+    Letter::C,
+    Letter::D,
+    Letter::E,
+    Letter::F,
+    Letter::G,
+    Letter::A,
+    Letter::B,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Octave {
+    MinusOne = -1,
     Zero = 0,
     One = 1,
     Two = 2,
@@ -18,103 +77,347 @@ pub enum Octave {
     Six = 6,
     Seven = 7,
     Eight = 8,
+    Nine = 9,
 }
 
 impl TryFrom<char> for Octave {
     type Error = Error;
 
     fn try_from(value: char) -> StdResult<Self, Self::Error> {
+        match value.to_digit(10) {
+            Some(digit) => Octave::try_from(digit as i32),
+            None => Err(Error::new("Invalid Octave", Kind::Zinnia)),
+        }
+    }
+}
+
+impl TryFrom<i32> for Octave {
+    type Error = Error;
+
+    fn try_from(value: i32) -> StdResult<Self, Self::Error> {
         match value {
-            '0' => Ok(Octave::Zero),
-            '1' => Ok(Octave::One),
-            '2' => Ok(Octave::Two),
-            '3' => Ok(Octave::Three),
-            '4' => Ok(Octave::Four),
-            '5' => Ok(Octave::Five),
-            '6' => Ok(Octave::Six),
-            '7' => Ok(Octave::Seven),
-            '8' => Ok(Octave::Eight),
+            -1 => Ok(Octave::MinusOne),
+            0 => Ok(Octave::Zero),
+            1 => Ok(Octave::One),
+            2 => Ok(Octave::Two),
+            3 => Ok(Octave::Three),
+            4 => Ok(Octave::Four),
+            5 => Ok(Octave::Five),
+            6 => Ok(Octave::Six),
+            7 => Ok(Octave::Seven),
+            8 => Ok(Octave::Eight),
+            9 => Ok(Octave::Nine),
             _ => Err(Error::new("Invalid Octave", Kind::Zinnia)),
         }
     }
 }
 
-#[derive(Debug)]
+impl fmt::Display for Octave {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", *self as i32)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Letter {
+    C,
+    D,
+    E,
+    F,
+    G,
+    A,
+    B,
+}
+
+impl Letter {
+    /// Semitones above C of the natural note with this letter.
+    pub fn semitones(&self) -> i32 {
+        match self {
+            Letter::C => 0,
+            Letter::D => 2,
+            Letter::E => 4,
+            Letter::F => 5,
+            Letter::G => 7,
+            Letter::A => 9,
+            Letter::B => 11,
+        }
+    }
+
+    // Position of the letter counting up from C.
+    fn index(&self) -> i32 {
+        *self as i32
+    }
+
+    fn from_index(index: i32) -> Letter {
+        LETTERS[index.rem_euclid(LETTERS.len() as i32) as usize]
+    }
+
+    fn from_char(value: char) -> Option<Letter> {
+        match value.to_ascii_lowercase() {
+            'c' => Some(Letter::C),
+            'd' => Some(Letter::D),
+            'e' => Some(Letter::E),
+            'f' => Some(Letter::F),
+            'g' => Some(Letter::G),
+            'a' => Some(Letter::A),
+            'b' => Some(Letter::B),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Letter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Accidental {
+    DoubleFlat,
+    Flat,
+    Natural,
+    Sharp,
+    DoubleSharp,
+}
+
+impl Accidental {
+    pub fn semitones(&self) -> i32 {
+        match self {
+            Accidental::DoubleFlat => -2,
+            Accidental::Flat => -1,
+            Accidental::Natural => 0,
+            Accidental::Sharp => 1,
+            Accidental::DoubleSharp => 2,
+        }
+    }
+
+    fn from_semitones(semitones: i32) -> Option<Accidental> {
+        match semitones {
+            -2 => Some(Accidental::DoubleFlat),
+            -1 => Some(Accidental::Flat),
+            0 => Some(Accidental::Natural),
+            1 => Some(Accidental::Sharp),
+            2 => Some(Accidental::DoubleSharp),
+            _ => None,
+        }
+    }
+
+    /// Semitone alteration of a single accidental sign, accepting both the
+    /// ASCII (`#`, `b`, `x`) and unicode (`♯`, `♭`, `𝄪`, `𝄫`) forms.
+    fn sign_semitones(value: char) -> Option<i32> {
+        match value {
+            '#' | '♯' => Some(1),
+            'b' | 'B' | '♭' => Some(-1),
+            'x' | 'X' | '𝄪' => Some(2),
+            '𝄫' => Some(-2),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Accidental {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Accidental::DoubleFlat => write!(f, "bb"),
+            Accidental::Flat => write!(f, "b"),
+            Accidental::Natural => Ok(()),
+            Accidental::Sharp => write!(f, "#"),
+            Accidental::DoubleSharp => write!(f, "##"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Note {
+    ADoubleFlat(Octave),
+    AFlat(Octave),
     A(Octave),
     ASharp(Octave),
+    ADoubleSharp(Octave),
+    BDoubleFlat(Octave),
     BFlat(Octave),
     B(Octave),
+    BSharp(Octave),
+    BDoubleSharp(Octave),
+    CDoubleFlat(Octave),
+    CFlat(Octave),
     C(Octave),
     CSharp(Octave),
+    CDoubleSharp(Octave),
+    DDoubleFlat(Octave),
     DFlat(Octave),
     D(Octave),
     DSharp(Octave),
+    DDoubleSharp(Octave),
+    EDoubleFlat(Octave),
     EFlat(Octave),
     E(Octave),
+    ESharp(Octave),
+    EDoubleSharp(Octave),
+    FDoubleFlat(Octave),
+    FFlat(Octave),
     F(Octave),
     FSharp(Octave),
+    FDoubleSharp(Octave),
+    GDoubleFlat(Octave),
     GFlat(Octave),
     G(Octave),
     GSharp(Octave),
-    AFlat(Octave),
+    GDoubleSharp(Octave),
 }
 
-impl Note {
-    pub fn parse(symbol: &str) -> Result<Note> {
-        let symbol = symbol.trim().to_ascii_lowercase();
-
-        match symbol.chars().nth(0) {
-            Some(octave) => {
-                let octave = TryFrom::<char>::try_from(octave)?;
-                match symbol.chars().nth(1) {
-                    Some(note) => match note {
-                        'a' => Ok(Note::A(octave)),
-                        'b' => Ok(Note::B(octave)),
-                        'c' => Ok(Note::C(octave)),
-                        'd' => Ok(Note::D(octave)),
-                        'e' => Ok(Note::E(octave)),
-                        'f' => Ok(Note::F(octave)),
-                        'g' => Ok(Note::G(octave)),
-                        _ => Err(Error::new(
-                            "Failed to parse note",
-                            Kind::Zinnia,
-                        )),
-                    },
-                    None => {
-                        Err(Error::new("Failed to parse note", Kind::Zinnia))
-                    }
+macro_rules! impl_note_spelling {
+    ($($letter:ident: $dflat:ident $flat:ident $nat:ident $sharp:ident $dsharp:ident;)*) => {
+        impl Note {
+            pub fn new(
+                letter: Letter,
+                accidental: Accidental,
+                octave: Octave,
+            ) -> Note {
+                match (letter, accidental) {
+                    $(
+                        (Letter::$letter, Accidental::DoubleFlat) => Note::$dflat(octave),
+                        (Letter::$letter, Accidental::Flat) => Note::$flat(octave),
+                        (Letter::$letter, Accidental::Natural) => Note::$nat(octave),
+                        (Letter::$letter, Accidental::Sharp) => Note::$sharp(octave),
+                        (Letter::$letter, Accidental::DoubleSharp) => Note::$dsharp(octave),
+                    )*
+                }
+            }
+
+            fn parts(&self) -> (Letter, Accidental, Octave) {
+                match *self {
+                    $(
+                        Note::$dflat(octave) => (Letter::$letter, Accidental::DoubleFlat, octave),
+                        Note::$flat(octave) => (Letter::$letter, Accidental::Flat, octave),
+                        Note::$nat(octave) => (Letter::$letter, Accidental::Natural, octave),
+                        Note::$sharp(octave) => (Letter::$letter, Accidental::Sharp, octave),
+                        Note::$dsharp(octave) => (Letter::$letter, Accidental::DoubleSharp, octave),
+                    )*
                 }
             }
-            None => Err(Error::new("Failed to parse note", Kind::Zinnia)),
         }
+    };
+}
+
+impl_note_spelling! {
+    A: ADoubleFlat AFlat A ASharp ADoubleSharp;
+    B: BDoubleFlat BFlat B BSharp BDoubleSharp;
+    C: CDoubleFlat CFlat C CSharp CDoubleSharp;
+    D: DDoubleFlat DFlat D DSharp DDoubleSharp;
+    E: EDoubleFlat EFlat E ESharp EDoubleSharp;
+    F: FDoubleFlat FFlat F FSharp FDoubleSharp;
+    G: GDoubleFlat GFlat G GSharp GDoubleSharp;
+}
+
+impl Note {
+    /// Parses a note in scientific pitch notation.
+    ///
+    /// Accepts the letter first ("C#4", "Bb3", "F##2") or the octave first
+    /// ("4c#"), in any case, with `#`, `b`, `x` or the unicode `♯`, `♭`,
+    /// `𝄪`, `𝄫` accidentals. Errors are of kind `Kind::Parse` and carry the
+    /// character position of the offending input.
+    pub fn parse(symbol: &str) -> Result<Note> {
+        NoteParser::new(symbol).parse()
+    }
+
+    pub fn letter(&self) -> Letter {
+        self.parts().0
+    }
+
+    pub fn accidental(&self) -> Accidental {
+        self.parts().1
+    }
+
+    pub fn octave(&self) -> Octave {
+        self.parts().2
+    }
+
+    /// The same spelling in another octave.
+    pub fn with_octave(&self, octave: Octave) -> Note {
+        Note::new(self.letter(), self.accidental(), octave)
     }
 
+    /// Frequency in 12-tone equal temperament with A4 at 440 Hz. Use a
+    /// `tuning::Tuning` for any other reference pitch or temperament.
     pub fn freq(&self) -> Result<f32> {
-        let key_number = self.key_number()?;
-        Ok(2.0f32.powf((key_number as f32 - 49.0) / 12.0) * 440.0)
-    }
-
-    fn key_number(&self) -> Result<u32> {
-        let (key_offset, octave) = match self {
-            Note::C(octave) => (0, octave),
-            Note::CSharp(octave) | Note::DFlat(octave) => (1, octave),
-            Note::D(octave) => (2, octave),
-            Note::DSharp(octave) | Note::EFlat(octave) => (3, octave),
-            Note::E(octave) => (4, octave),
-            Note::F(octave) => (5, octave),
-            Note::FSharp(octave) | Note::GFlat(octave) => (6, octave),
-            Note::G(octave) => (7, octave),
-            Note::GSharp(octave) | Note::AFlat(octave) => (8, octave),
-            Note::A(octave) => (9, octave),
-            Note::ASharp(octave) | Note::BFlat(octave) => (10, octave),
-            Note::B(octave) => (11, octave),
+        EqualTemperament::default().freq(self)
+    }
+
+    /// MIDI note number, where C4 is 60 and A4 is 69.
+    pub fn midi_number(&self) -> Result<u8> {
+        let number = self.semitones();
+        if !(0..=MAX_MIDI_NUMBER).contains(&number) {
+            Err(Error::new("Invalid MIDI note", Kind::Zinnia))
+        } else {
+            Ok(number as u8)
+        }
+    }
+
+    /// Note for a MIDI note number, spelled with sharps.
+    pub fn from_midi(number: u8) -> Result<Note> {
+        if number as i32 > MAX_MIDI_NUMBER {
+            return Err(Error::new("Invalid MIDI note", Kind::Zinnia));
+        }
+        Note::spell(number as i32, &SHARP_SPELLINGS)
+    }
+
+    /// Nearest note to `freq` together with the deviation from that note
+    /// in cents.
+    pub fn from_freq(freq: f32) -> Result<(Note, f32)> {
+        if !freq.is_finite() || freq <= 0.0 {
+            return Err(Error::new("Invalid frequency", Kind::Zinnia));
+        }
+
+        let number = A4_MIDI_NUMBER as f32
+            + KEYS_PER_OCTAVE as f32 * (freq / A4_FREQ).log2();
+        let nearest = number.round();
+        let note = Note::spell(nearest as i32, &SHARP_SPELLINGS)?;
+
+        Ok((note, (number - nearest) * CENTS_PER_SEMITONE))
+    }
+
+    // Semitones above C-1, which coincides with the MIDI note number for
+    // notes within the MIDI range.
+    pub(crate) fn semitones(&self) -> i32 {
+        let (letter, accidental, octave) = self.parts();
+        (octave as i32 + 1) * KEYS_PER_OCTAVE
+            + letter.semitones()
+            + accidental.semitones()
+    }
+
+    /// Moves the note by `semitones`, spelling the result with flats if
+    /// this note is flat and with sharps otherwise.
+    pub fn transpose(&self, semitones: i32) -> Result<Note> {
+        let spellings = if self.accidental().semitones() < 0 {
+            &FLAT_SPELLINGS
+        } else {
+            &SHARP_SPELLINGS
         };
+        Note::spell(self.semitones() + semitones, spellings)
+    }
+
+    fn spell(
+        semitones: i32,
+        spellings: &[(Letter, Accidental); 12],
+    ) -> Result<Note> {
+        let octave =
+            Octave::try_from(semitones.div_euclid(KEYS_PER_OCTAVE) - 1)?;
+        let (letter, accidental) =
+            spellings[semitones.rem_euclid(KEYS_PER_OCTAVE) as usize];
+        Ok(Note::new(letter, accidental, octave))
+    }
+
+    /// Piano key number, from 1 for A0 to 88 for C8.
+    pub fn key_number(&self) -> Result<u32> {
+        let (letter, accidental, octave) = self.parts();
+        let key_offset = letter.semitones() + accidental.semitones();
 
         let key: i32 =
-            *octave as i32 * KEYS_PER_OCTAVE + key_offset - START_KEY_OFFSET;
+            octave as i32 * KEYS_PER_OCTAVE + key_offset - START_KEY_OFFSET;
 
-        if key < 1 || key > 88 {
+        if !(1..=88).contains(&key) {
             Err(Error::new("Invalid Note", Kind::Zinnia))
         } else {
             Ok(key as u32)
@@ -122,6 +425,168 @@ impl Note {
     }
 }
 
+impl FromStr for Note {
+    type Err = Error;
+
+    fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+        Note::parse(s)
+    }
+}
+
+impl fmt::Display for Note {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (letter, accidental, octave) = self.parts();
+        write!(f, "{}{}{}", letter, accidental, octave)
+    }
+}
+
+// Positions are counted in characters rather than bytes so that unicode
+// accidentals don't skew the reported error position.
+struct NoteParser<'a> {
+    chars: Peekable<Enumerate<Chars<'a>>>,
+    end: usize,
+}
+
+impl<'a> NoteParser<'a> {
+    fn new(symbol: &'a str) -> Self {
+        NoteParser {
+            chars: symbol.chars().enumerate().peekable(),
+            end: symbol.chars().count(),
+        }
+    }
+
+    fn parse(mut self) -> Result<Note> {
+        self.skip_whitespace();
+
+        let note = match self.peek() {
+            Some((_, c)) if c.is_ascii_digit() || c == '-' => {
+                let octave = self.octave()?;
+                let letter = self.letter()?;
+                let accidental = self.accidental()?;
+                Note::new(letter, accidental, octave)
+            }
+            _ => {
+                let letter = self.letter()?;
+                let accidental = self.accidental()?;
+                let octave = self.octave()?;
+                Note::new(letter, accidental, octave)
+            }
+        };
+
+        self.skip_whitespace();
+        match self.peek() {
+            Some((position, _)) => {
+                Err(Error::new("Unexpected character", Kind::Parse(position)))
+            }
+            None => Ok(note),
+        }
+    }
+
+    fn skip(&mut self, count: usize) {
+        for _ in 0..count {
+            self.chars.next();
+        }
+    }
+
+    // Position of the next character, or the length of the input once it
+    // has all been read.
+    fn position(&mut self) -> usize {
+        let end = self.end;
+        self.peek().map_or(end, |(position, _)| position)
+    }
+
+    fn peek(&mut self) -> Option<(usize, char)> {
+        self.chars.peek().copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some((_, c)) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.chars.next();
+        }
+    }
+
+    fn letter(&mut self) -> Result<Letter> {
+        match self.chars.next() {
+            Some((position, c)) => Letter::from_char(c).ok_or_else(|| {
+                Error::new("Expected a note letter", Kind::Parse(position))
+            }),
+            None => {
+                Err(Error::new("Expected a note letter", Kind::Parse(self.end)))
+            }
+        }
+    }
+
+    fn accidental(&mut self) -> Result<Accidental> {
+        let mut semitones = 0;
+        while let Some((position, c)) = self.peek() {
+            let step = match Accidental::sign_semitones(c) {
+                Some(step) => step,
+                None => break,
+            };
+            if semitones * step < 0 {
+                return Err(Error::new(
+                    "Cannot mix sharps and flats",
+                    Kind::Parse(position),
+                ));
+            }
+            semitones += step;
+            if semitones.abs() > MAX_ACCIDENTALS {
+                return Err(Error::new(
+                    "Too many accidentals",
+                    Kind::Parse(position),
+                ));
+            }
+            self.chars.next();
+        }
+        Ok(Accidental::from_semitones(semitones).unwrap())
+    }
+
+    fn octave(&mut self) -> Result<Octave> {
+        let (start, sign) = match self.peek() {
+            Some((position, '-')) => {
+                self.chars.next();
+                (position, -1)
+            }
+            Some((position, _)) => (position, 1),
+            None => (self.end, 1),
+        };
+
+        match self.peek() {
+            Some((_, c)) if c.is_ascii_digit() => (),
+            Some((position, _)) => {
+                return Err(Error::new(
+                    "Expected an octave",
+                    Kind::Parse(position),
+                ))
+            }
+            None => {
+                return Err(Error::new(
+                    "Expected an octave",
+                    Kind::Parse(self.end),
+                ))
+            }
+        }
+
+        let mut value = 0i32;
+        while let Some((_, c)) = self.peek() {
+            match c.to_digit(10) {
+                Some(digit) => {
+                    value =
+                        value.saturating_mul(10).saturating_add(digit as i32)
+                }
+                None => break,
+            }
+            self.chars.next();
+        }
+
+        Octave::try_from(sign * value)
+            .map_err(|_| Error::new("Invalid Octave", Kind::Parse(start)))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -170,4 +635,126 @@ mod tests {
             _ => Err(Error::new("Expected an error", Kind::Zinnia)),
         }
     }
+    #[test]
+    fn parse_letter_first_ok() -> Result<()> {
+        assert_eq!(Note::parse("C#4")?, Note::CSharp(Octave::Four));
+        assert_eq!(Note::parse("Bb3")?, Note::BFlat(Octave::Three));
+        assert_eq!(Note::parse("F##2")?, Note::FDoubleSharp(Octave::Two));
+        assert_eq!(Note::parse("Cb5")?, Note::CFlat(Octave::Five));
+        assert_eq!(Note::parse(" e4 ")?, Note::E(Octave::Four));
+        Ok(())
+    }
+    #[test]
+    fn parse_octave_first_ok() -> Result<()> {
+        assert_eq!(Note::parse("4c#")?, Note::CSharp(Octave::Four));
+        assert_eq!(Note::parse("4c")?, Note::C(Octave::Four));
+        assert_eq!(Note::parse("3BB")?, Note::BFlat(Octave::Three));
+        Ok(())
+    }
+    #[test]
+    fn parse_unicode_ok() -> Result<()> {
+        assert_eq!(Note::parse("G♯3")?, Note::GSharp(Octave::Three));
+        assert_eq!(Note::parse("e♭5")?, Note::EFlat(Octave::Five));
+        assert_eq!(Note::parse("D𝄫2")?, Note::DDoubleFlat(Octave::Two));
+        Ok(())
+    }
+    #[test]
+    fn parse_display_round_trip_ok() -> Result<()> {
+        for symbol in &["C4", "C#4", "Dbb1", "Ex7", "B#3"] {
+            let note = Note::parse(symbol)?;
+            assert_eq!(Note::parse(&note.to_string())?, note);
+        }
+        Ok(())
+    }
+    #[test]
+    fn parse_error_positions() {
+        let position = |symbol| Note::parse(symbol).unwrap_err().kind();
+        assert_eq!(position(""), Kind::Parse(0));
+        assert_eq!(position("H4"), Kind::Parse(0));
+        assert_eq!(position("C#b4"), Kind::Parse(2));
+        assert_eq!(position("C###4"), Kind::Parse(3));
+        assert_eq!(position("C♯?4"), Kind::Parse(2));
+        assert_eq!(position("C"), Kind::Parse(1));
+        assert_eq!(position("C10"), Kind::Parse(1));
+        assert_eq!(position("C-2"), Kind::Parse(1));
+        assert_eq!(position("C-"), Kind::Parse(2));
+        assert_eq!(position("C4x"), Kind::Parse(2));
+    }
+    #[test]
+    fn enharmonic_key_numbers_ok() {
+        assert_eq!(Note::BSharp(Octave::Three).key_number().unwrap(), 40);
+        assert_eq!(Note::CFlat(Octave::Five).key_number().unwrap(), 51);
+        assert_eq!(
+            Note::FDoubleSharp(Octave::Two).key_number().unwrap(),
+            Note::G(Octave::Two).key_number().unwrap()
+        );
+    }
+    #[test]
+    fn parse_extreme_octaves_ok() -> Result<()> {
+        assert_eq!(Note::parse("C-1")?, Note::C(Octave::MinusOne));
+        assert_eq!(Note::parse("-1c#")?, Note::CSharp(Octave::MinusOne));
+        assert_eq!(Note::parse("G9")?, Note::G(Octave::Nine));
+        Ok(())
+    }
+    #[test]
+    fn midi_number_ok() -> Result<()> {
+        assert_eq!(Note::C(Octave::MinusOne).midi_number()?, 0);
+        assert_eq!(Note::C(Octave::Four).midi_number()?, 60);
+        assert_eq!(Note::A(Octave::Four).midi_number()?, 69);
+        assert_eq!(Note::BSharp(Octave::Three).midi_number()?, 60);
+        assert_eq!(Note::G(Octave::Nine).midi_number()?, 127);
+        Ok(())
+    }
+    #[test]
+    fn midi_number_fail() {
+        assert!(Note::CFlat(Octave::MinusOne).midi_number().is_err());
+        assert!(Note::GSharp(Octave::Nine).midi_number().is_err());
+        assert!(Note::from_midi(128).is_err());
+    }
+    #[test]
+    fn midi_round_trip_ok() -> Result<()> {
+        for number in 0..=127 {
+            assert_eq!(Note::from_midi(number)?.midi_number()?, number);
+        }
+        assert_eq!(Note::from_midi(61)?, Note::CSharp(Octave::Four));
+        Ok(())
+    }
+    #[test]
+    fn freq_outside_piano_ok() -> Result<()> {
+        assert!((Note::C(Octave::MinusOne).freq()? - 8.175_799).abs() < 1e-4);
+        assert!((Note::A(Octave::Four).freq()? - 440.0).abs() < 1e-4);
+        Ok(())
+    }
+    #[test]
+    fn from_freq_ok() -> Result<()> {
+        let (note, cents) = Note::from_freq(440.0)?;
+        assert_eq!(note, Note::A(Octave::Four));
+        assert!(cents.abs() < 1e-3);
+
+        let (note, cents) = Note::from_freq(445.0)?;
+        assert_eq!(note, Note::A(Octave::Four));
+        assert!((cents - 19.56).abs() < 0.01);
+
+        let (note, cents) = Note::from_freq(270.0)?;
+        assert_eq!(note, Note::CSharp(Octave::Four));
+        assert!((cents + 45.45).abs() < 0.01);
+        Ok(())
+    }
+    #[test]
+    fn from_freq_round_trip_ok() -> Result<()> {
+        for number in 0..=127 {
+            let note = Note::from_midi(number)?;
+            let (nearest, cents) = Note::from_freq(note.freq()?)?;
+            assert_eq!(nearest, note);
+            assert!(cents.abs() < 0.01);
+        }
+        Ok(())
+    }
+    #[test]
+    fn from_freq_fail() {
+        assert!(Note::from_freq(0.0).is_err());
+        assert!(Note::from_freq(-440.0).is_err());
+        assert!(Note::from_freq(f32::NAN).is_err());
+        assert!(Note::from_freq(20_000.0).is_err());
+    }
 }
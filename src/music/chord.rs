@@ -0,0 +1,458 @@
+use std::{convert::TryFrom, time::Duration};
+
+use alsa::pcm::IoFormat;
+
+use super::{
+    interval::{Interval, Quality},
+    tuning::Tuning,
+    Error, Kind, Note, NoteParser, Octave, Result,
+};
+use crate::{
+    hwp::HardwareParams,
+    sound::{config::SoundConfigCollection, MultiSound, Sinusoid, Sound},
+};
+
+const MAJOR_WORDS: [&str; 4] = ["maj", "Maj", "MA", "M"];
+const MINOR_MAJOR_WORDS: [&str; 3] = ["mMaj", "mMA", "mM"];
+const MINOR_WORDS: [&str; 3] = ["min", "m", "-"];
+const DIMINISHED_WORDS: [&str; 3] = ["dim", "°", "o"];
+const AUGMENTED_WORDS: [&str; 2] = ["aug", "+"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Voicing {
+    /// Chord tones stacked as closely as possible above the root.
+    Close,
+    /// Close voicing with every second note above the lowest raised an
+    /// octave.
+    Open,
+    /// Close voicing with the second highest note dropped an octave.
+    Drop2,
+    /// Close voicing with the lowest `n` notes raised an octave.
+    Inversion(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chord {
+    root: Note,
+    intervals: Vec<Interval>,
+    bass: Option<Note>,
+}
+
+impl Chord {
+    /// Chord with the given intervals above `root`. The root itself is
+    /// always part of the chord.
+    pub fn new(root: Note, intervals: &[Interval]) -> Chord {
+        let mut chord = Chord {
+            root,
+            intervals: vec![Interval::UNISON],
+            bass: None,
+        };
+        for interval in intervals {
+            chord.set(*interval);
+        }
+        chord
+    }
+
+    /// Parses a chord symbol such as "Cmaj7", "F#m7b5", "Bbsus4/F" or
+    /// "G13", with the root in `octave`. A slash bass is placed below the
+    /// rest of the chord.
+    pub fn parse(symbol: &str, octave: Octave) -> Result<Chord> {
+        ChordParser::new(symbol, octave).parse()
+    }
+
+    pub fn root(&self) -> Note {
+        self.root
+    }
+
+    pub fn bass(&self) -> Option<Note> {
+        self.bass
+    }
+
+    pub fn intervals(&self) -> &[Interval] {
+        &self.intervals
+    }
+
+    /// Notes of the chord from lowest to highest.
+    pub fn notes(&self, voicing: Voicing) -> Result<Vec<Note>> {
+        let mut notes = self
+            .intervals
+            .iter()
+            .map(|interval| self.root + *interval)
+            .collect::<Result<Vec<Note>>>()?;
+
+        match voicing {
+            Voicing::Close => (),
+            Voicing::Open => {
+                for note in notes.iter_mut().skip(1).step_by(2) {
+                    *note = shift_octave(note, 1)?;
+                }
+            }
+            Voicing::Drop2 => {
+                if notes.len() > 1 {
+                    let idx = notes.len() - 2;
+                    notes[idx] = shift_octave(&notes[idx], -1)?;
+                }
+            }
+            Voicing::Inversion(n) => {
+                for note in notes.iter_mut().take(n) {
+                    *note = shift_octave(note, 1)?;
+                }
+            }
+        }
+
+        notes.sort_by_key(|note| note.semitones());
+
+        if let Some(bass) = self.bass {
+            let mut bass = bass;
+            while bass.semitones() >= notes[0].semitones() {
+                bass = shift_octave(&bass, -1)?;
+            }
+            notes.insert(0, bass);
+        }
+
+        Ok(notes)
+    }
+
+    /// The chord as a `MultiSound` with one `Sinusoid` per note, each
+    /// playing at `amplitude` on every channel of `hwp`.
+    pub fn to_sound<T>(
+        &self,
+        voicing: Voicing,
+        tuning: &dyn Tuning,
+        amplitude: f32,
+        duration: Duration,
+        hwp: &HardwareParams<T>,
+    ) -> Result<MultiSound>
+    where
+        T: IoFormat,
+    {
+        let mut sounds = Vec::<Box<dyn Sound>>::new();
+        for note in self.notes(voicing)? {
+            let freq = tuning.freq(&note)?;
+            let mut config = SoundConfigCollection::new();
+            for _ in 0..hwp.channels() {
+                config.add_config(freq, 0.0, amplitude);
+            }
+            sounds.push(Box::new(Sinusoid::new(&config, duration, hwp)));
+        }
+        Ok(MultiSound::with_sounds(&mut sounds))
+    }
+
+    // Adds `interval`, replacing any interval with the same number so that
+    // alterations such as "b5" override the chord's natural fifth.
+    fn set(&mut self, interval: Interval) {
+        self.remove(interval.number());
+        self.intervals.push(interval);
+        self.intervals.sort_by_key(|i| i.semitones());
+    }
+
+    fn remove(&mut self, number: u8) {
+        self.intervals.retain(|i| i.number() != number);
+    }
+}
+
+struct ChordParser<'a> {
+    symbol: &'a str,
+    rest: &'a str,
+    octave: Octave,
+}
+
+impl<'a> ChordParser<'a> {
+    fn new(symbol: &'a str, octave: Octave) -> Self {
+        let symbol = symbol.trim_end();
+        ChordParser {
+            symbol,
+            rest: symbol,
+            octave,
+        }
+    }
+
+    fn parse(mut self) -> Result<Chord> {
+        let root = self.note()?;
+        let mut chord =
+            Chord::new(root, &[Interval::MAJOR_THIRD, Interval::PERFECT_FIFTH]);
+
+        let seventh = self.quality(&mut chord);
+        self.extension(&mut chord, seventh);
+
+        while !self.rest.is_empty() && !self.rest.starts_with('/') {
+            if !self.modifier(&mut chord)? {
+                return Err(self.error("Unexpected character in chord"));
+            }
+        }
+
+        if self.eat("/") {
+            chord.bass = Some(self.note()?);
+            if !self.rest.is_empty() {
+                return Err(self.error("Unexpected character after bass"));
+            }
+        }
+
+        Ok(chord)
+    }
+
+    fn note(&mut self) -> Result<Note> {
+        let mut parser = NoteParser::new(self.symbol);
+        parser.skip(self.position());
+        let letter = parser.letter()?;
+        let accidental = parser.accidental()?;
+
+        let idx = self
+            .symbol
+            .char_indices()
+            .nth(parser.position())
+            .map_or(self.symbol.len(), |(idx, _)| idx);
+        self.rest = &self.symbol[idx..];
+
+        Ok(Note::new(letter, accidental, self.octave))
+    }
+
+    // Reads the chord quality, returning the seventh that any following
+    // extension implies.
+    fn quality(&mut self, chord: &mut Chord) -> Interval {
+        if self.eat_any(&MINOR_MAJOR_WORDS) {
+            chord.set(Interval::MINOR_THIRD);
+            Interval::MAJOR_SEVENTH
+        } else if self.eat_any(&MAJOR_WORDS) {
+            Interval::MAJOR_SEVENTH
+        } else if self.eat("Δ") {
+            if !self.starts_with_digit() {
+                chord.set(Interval::MAJOR_SEVENTH);
+            }
+            Interval::MAJOR_SEVENTH
+        } else if self.eat_any(&MINOR_WORDS) {
+            chord.set(Interval::MINOR_THIRD);
+            Interval::MINOR_SEVENTH
+        } else if self.eat_any(&DIMINISHED_WORDS) {
+            chord.set(Interval::MINOR_THIRD);
+            chord.set(Interval::DIMINISHED_FIFTH);
+            Interval::DIMINISHED_SEVENTH
+        } else if self.eat_any(&AUGMENTED_WORDS) {
+            chord.set(Interval::AUGMENTED_FIFTH);
+            Interval::MINOR_SEVENTH
+        } else if self.eat("ø") {
+            chord.set(Interval::MINOR_THIRD);
+            chord.set(Interval::DIMINISHED_FIFTH);
+            chord.set(Interval::MINOR_SEVENTH);
+            Interval::MINOR_SEVENTH
+        } else {
+            Interval::MINOR_SEVENTH
+        }
+    }
+
+    fn extension(&mut self, chord: &mut Chord, seventh: Interval) {
+        if self.eat("69") {
+            chord.set(Interval::MAJOR_SIXTH);
+            chord.set(ninth(Quality::Major));
+        } else if self.eat("13") {
+            chord.set(seventh);
+            chord.set(ninth(Quality::Major));
+            chord.set(thirteenth(Quality::Major));
+        } else if self.eat("11") {
+            chord.set(seventh);
+            chord.set(ninth(Quality::Major));
+            chord.set(eleventh(Quality::Perfect));
+        } else if self.eat("9") {
+            chord.set(seventh);
+            chord.set(ninth(Quality::Major));
+        } else if self.eat("7") {
+            chord.set(seventh);
+        } else if self.eat("6") {
+            chord.set(Interval::MAJOR_SIXTH);
+        } else if self.eat("5") {
+            chord.remove(3);
+        }
+    }
+
+    // Reads one suspension, added tone or alteration, returning false if
+    // none is found.
+    fn modifier(&mut self, chord: &mut Chord) -> Result<bool> {
+        if self.eat_any(&["(", ")", ",", " "]) {
+            return Ok(true);
+        }
+
+        if self.eat("sus2") {
+            chord.remove(3);
+            chord.set(Interval::MAJOR_SECOND);
+        } else if self.eat_any(&["sus4", "sus"]) {
+            chord.remove(3);
+            chord.set(Interval::PERFECT_FOURTH);
+        } else if self.eat("add") {
+            let interval = match self.number() {
+                Some(2) => Interval::MAJOR_SECOND,
+                Some(4) => Interval::PERFECT_FOURTH,
+                Some(6) => Interval::MAJOR_SIXTH,
+                Some(9) => ninth(Quality::Major),
+                Some(11) => eleventh(Quality::Perfect),
+                Some(13) => thirteenth(Quality::Major),
+                _ => return Err(self.error("Invalid added tone")),
+            };
+            chord.set(interval);
+        } else if self.eat_any(&["b", "♭", "-"]) {
+            let interval = match self.number() {
+                Some(5) => Interval::DIMINISHED_FIFTH,
+                Some(9) => ninth(Quality::Minor),
+                Some(13) => thirteenth(Quality::Minor),
+                _ => return Err(self.error("Invalid alteration")),
+            };
+            chord.set(interval);
+        } else if self.eat_any(&["#", "♯", "+"]) {
+            let interval = match self.number() {
+                Some(5) => Interval::AUGMENTED_FIFTH,
+                Some(9) => ninth(Quality::Augmented),
+                Some(11) => eleventh(Quality::Augmented),
+                _ => return Err(self.error("Invalid alteration")),
+            };
+            chord.set(interval);
+        } else if self.eat_any(&MAJOR_WORDS) || self.eat("Δ") {
+            // A major seventh written after the quality, as in "m(maj7)".
+            if !self.eat("7") {
+                return Err(self.error("Expected a major seventh"));
+            }
+            chord.set(Interval::MAJOR_SEVENTH);
+        } else {
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    fn number(&mut self) -> Option<u8> {
+        let digits = self
+            .rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(self.rest.len());
+        let number = self.rest[..digits].parse().ok();
+        self.rest = &self.rest[digits..];
+        number
+    }
+
+    fn starts_with_digit(&self) -> bool {
+        self.rest.starts_with(|c: char| c.is_ascii_digit())
+    }
+
+    fn eat(&mut self, word: &str) -> bool {
+        match self.rest.strip_prefix(word) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn eat_any(&mut self, words: &[&str]) -> bool {
+        words.iter().any(|word| self.eat(word))
+    }
+
+    fn position(&self) -> usize {
+        self.symbol.chars().count() - self.rest.chars().count()
+    }
+
+    fn error(&self, message: &'static str) -> Error {
+        Error::new(message, Kind::Parse(self.position()))
+    }
+}
+
+fn ninth(quality: Quality) -> Interval {
+    compound(quality, 2)
+}
+
+fn eleventh(quality: Quality) -> Interval {
+    compound(quality, 4)
+}
+
+fn thirteenth(quality: Quality) -> Interval {
+    compound(quality, 6)
+}
+
+fn compound(quality: Quality, number: u8) -> Interval {
+    Interval::new(quality, number)
+        .and_then(|interval| interval.add_octaves(1))
+        .unwrap()
+}
+
+fn shift_octave(note: &Note, octaves: i32) -> Result<Note> {
+    Ok(note.with_octave(Octave::try_from(note.octave() as i32 + octaves)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notes(symbol: &str, voicing: Voicing) -> Vec<String> {
+        Chord::parse(symbol, Octave::Four)
+            .unwrap()
+            .notes(voicing)
+            .unwrap()
+            .iter()
+            .map(|note| note.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn triads_ok() {
+        assert_eq!(notes("C", Voicing::Close), ["C4", "E4", "G4"]);
+        assert_eq!(notes("Ebm", Voicing::Close), ["Eb4", "Gb4", "Bb4"]);
+        assert_eq!(notes("Bdim", Voicing::Close), ["B4", "D5", "F5"]);
+        assert_eq!(notes("Caug", Voicing::Close), ["C4", "E4", "G#4"]);
+        assert_eq!(notes("D5", Voicing::Close), ["D4", "A4"]);
+    }
+    #[test]
+    fn sevenths_ok() {
+        assert_eq!(notes("Cmaj7", Voicing::Close), ["C4", "E4", "G4", "B4"]);
+        assert_eq!(notes("F#m7b5", Voicing::Close), ["F#4", "A4", "C5", "E5"]);
+        assert_eq!(notes("Cø", Voicing::Close), ["C4", "Eb4", "Gb4", "Bb4"]);
+        assert_eq!(
+            notes("Cdim7", Voicing::Close),
+            ["C4", "Eb4", "Gb4", "Bbb4"]
+        );
+        assert_eq!(
+            notes("Am(maj7)", Voicing::Close),
+            ["A4", "C5", "E5", "G#5"]
+        );
+        assert_eq!(notes("AmM7", Voicing::Close), ["A4", "C5", "E5", "G#5"]);
+    }
+    #[test]
+    fn extensions_ok() {
+        assert_eq!(
+            notes("G13", Voicing::Close),
+            ["G4", "B4", "D5", "F5", "A5", "E6"]
+        );
+        assert_eq!(
+            notes("C7b9#11", Voicing::Close),
+            ["C4", "E4", "G4", "Bb4", "Db5", "F#5"]
+        );
+        assert_eq!(notes("Cadd9", Voicing::Close), ["C4", "E4", "G4", "D5"]);
+        assert_eq!(
+            notes("C69", Voicing::Close),
+            ["C4", "E4", "G4", "A4", "D5"]
+        );
+    }
+    #[test]
+    fn slash_and_sus_ok() {
+        assert_eq!(
+            notes("Bbsus4/F", Voicing::Close),
+            ["F4", "Bb4", "Eb5", "F5"]
+        );
+        assert_eq!(notes("Dsus2", Voicing::Close), ["D4", "E4", "A4"]);
+        assert_eq!(notes("C/E", Voicing::Close), ["E3", "C4", "E4", "G4"]);
+    }
+    #[test]
+    fn voicings_ok() {
+        assert_eq!(notes("Cmaj7", Voicing::Drop2), ["G3", "C4", "E4", "B4"]);
+        assert_eq!(notes("C", Voicing::Open), ["C4", "G4", "E5"]);
+        assert_eq!(notes("C", Voicing::Inversion(1)), ["E4", "G4", "C5"]);
+        assert_eq!(notes("C", Voicing::Inversion(2)), ["G4", "C5", "E5"]);
+    }
+    #[test]
+    fn parse_fail() {
+        let position =
+            |symbol| Chord::parse(symbol, Octave::Four).unwrap_err().kind();
+        assert_eq!(position("H7"), Kind::Parse(0));
+        assert_eq!(position("Cmaj7?"), Kind::Parse(5));
+        assert_eq!(position("Cadd3"), Kind::Parse(5));
+        assert_eq!(position("C/Q"), Kind::Parse(2));
+        assert_eq!(position("C#b"), Kind::Parse(2));
+    }
+}
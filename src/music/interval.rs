@@ -0,0 +1,312 @@
+use std::{
+    convert::TryFrom,
+    fmt,
+    ops::{Add, Sub},
+};
+
+use super::{
+    Accidental, Error, Kind, Letter, Note, Octave, Result, KEYS_PER_OCTAVE,
+    LETTERS,
+};
+
+const MAJOR_SCALE_SEMITONES: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quality {
+    Diminished,
+    Minor,
+    Perfect,
+    Major,
+    Augmented,
+}
+
+impl fmt::Display for Quality {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Quality::Diminished => write!(f, "d"),
+            Quality::Minor => write!(f, "m"),
+            Quality::Perfect => write!(f, "P"),
+            Quality::Major => write!(f, "M"),
+            Quality::Augmented => write!(f, "A"),
+        }
+    }
+}
+
+/// An ascending interval such as a major third (`M3`) or a perfect twelfth
+/// (`P12`). The number counts letter names, so 1 is a unison and 8 an
+/// octave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+    quality: Quality,
+    number: u8,
+}
+
+impl Interval {
+    pub const UNISON: Interval = Interval::simple(Quality::Perfect, 1);
+    pub const MINOR_SECOND: Interval = Interval::simple(Quality::Minor, 2);
+    pub const MAJOR_SECOND: Interval = Interval::simple(Quality::Major, 2);
+    pub const MINOR_THIRD: Interval = Interval::simple(Quality::Minor, 3);
+    pub const MAJOR_THIRD: Interval = Interval::simple(Quality::Major, 3);
+    pub const PERFECT_FOURTH: Interval = Interval::simple(Quality::Perfect, 4);
+    pub const AUGMENTED_FOURTH: Interval =
+        Interval::simple(Quality::Augmented, 4);
+    pub const DIMINISHED_FIFTH: Interval =
+        Interval::simple(Quality::Diminished, 5);
+    pub const PERFECT_FIFTH: Interval = Interval::simple(Quality::Perfect, 5);
+    pub const AUGMENTED_FIFTH: Interval =
+        Interval::simple(Quality::Augmented, 5);
+    pub const MINOR_SIXTH: Interval = Interval::simple(Quality::Minor, 6);
+    pub const MAJOR_SIXTH: Interval = Interval::simple(Quality::Major, 6);
+    pub const DIMINISHED_SEVENTH: Interval =
+        Interval::simple(Quality::Diminished, 7);
+    pub const MINOR_SEVENTH: Interval = Interval::simple(Quality::Minor, 7);
+    pub const MAJOR_SEVENTH: Interval = Interval::simple(Quality::Major, 7);
+    pub const OCTAVE: Interval = Interval::simple(Quality::Perfect, 8);
+
+    pub fn new(quality: Quality, number: u8) -> Result<Interval> {
+        let valid = number > 0
+            && match quality {
+                Quality::Diminished => number != 1,
+                Quality::Augmented => true,
+                Quality::Perfect => is_perfect_number(number),
+                Quality::Major | Quality::Minor => !is_perfect_number(number),
+            };
+
+        if valid {
+            Ok(Interval { quality, number })
+        } else {
+            Err(Error::new("Invalid interval", Kind::Zinnia))
+        }
+    }
+
+    const fn simple(quality: Quality, number: u8) -> Interval {
+        Interval { quality, number }
+    }
+
+    pub fn quality(&self) -> Quality {
+        self.quality
+    }
+
+    pub fn number(&self) -> u8 {
+        self.number
+    }
+
+    pub fn semitones(&self) -> i32 {
+        let steps = self.number as i32 - 1;
+        let octaves = steps / LETTERS.len() as i32;
+        let base =
+            MAJOR_SCALE_SEMITONES[(steps % LETTERS.len() as i32) as usize];
+
+        let alteration = match (self.quality, is_perfect_number(self.number)) {
+            (Quality::Perfect, _) | (Quality::Major, _) => 0,
+            (Quality::Minor, _) => -1,
+            (Quality::Augmented, _) => 1,
+            (Quality::Diminished, true) => -1,
+            (Quality::Diminished, false) => -2,
+        };
+
+        octaves * KEYS_PER_OCTAVE + base + alteration
+    }
+
+    /// The same interval one or more octaves higher. Fails if the number
+    /// no longer fits.
+    pub fn add_octaves(&self, octaves: u8) -> Result<Interval> {
+        let number = octaves
+            .checked_mul(LETTERS.len() as u8)
+            .and_then(|steps| self.number.checked_add(steps))
+            .ok_or_else(|| Error::new("Interval too large", Kind::Zinnia))?;
+        Ok(Interval {
+            quality: self.quality,
+            number,
+        })
+    }
+
+    /// Interval spanning `steps` letter names above the lower note and
+    /// `semitones` semitones, so that (2, 5) is an augmented third.
+    pub fn from_steps(steps: i32, semitones: i32) -> Result<Interval> {
+        if steps < 0 {
+            return Err(Error::new("Descending interval", Kind::Zinnia));
+        }
+
+        let number = steps as u8 + 1;
+        let octaves = steps / LETTERS.len() as i32;
+        let base = octaves * KEYS_PER_OCTAVE
+            + MAJOR_SCALE_SEMITONES[(steps % LETTERS.len() as i32) as usize];
+
+        let quality = match (semitones - base, is_perfect_number(number)) {
+            (0, true) => Quality::Perfect,
+            (0, false) => Quality::Major,
+            (-1, false) => Quality::Minor,
+            (1, _) => Quality::Augmented,
+            (-1, true) | (-2, false) => Quality::Diminished,
+            _ => {
+                return Err(Error::new(
+                    "Unsupported interval quality",
+                    Kind::Zinnia,
+                ))
+            }
+        };
+
+        Interval::new(quality, number)
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.quality, self.number)
+    }
+}
+
+impl Add<Interval> for Note {
+    type Output = Result<Note>;
+
+    /// The note `interval` above this one, spelled by counting letter names
+    /// so that, for example, a major third above D is F# and not Gb.
+    fn add(self, interval: Interval) -> Self::Output {
+        let letter_steps = self.letter().index() + interval.number as i32 - 1;
+        let letter = Letter::from_index(letter_steps);
+        let octave = Octave::try_from(
+            self.octave() as i32 + letter_steps / LETTERS.len() as i32,
+        )?;
+
+        let natural = Note::new(letter, Accidental::Natural, octave);
+        let alteration =
+            self.semitones() + interval.semitones() - natural.semitones();
+
+        match Accidental::from_semitones(alteration) {
+            Some(accidental) => Ok(Note::new(letter, accidental, octave)),
+            None => Err(Error::new("Accidental out of range", Kind::Zinnia)),
+        }
+    }
+}
+
+impl Sub<Note> for Note {
+    type Output = Result<Interval>;
+
+    /// The interval from `other` up to this note.
+    fn sub(self, other: Note) -> Self::Output {
+        Interval::from_steps(
+            self.diatonic_steps() - other.diatonic_steps(),
+            self.semitones() - other.semitones(),
+        )
+    }
+}
+
+impl Note {
+    // Letter names above C-1.
+    fn diatonic_steps(&self) -> i32 {
+        (self.octave() as i32 + 1) * LETTERS.len() as i32
+            + self.letter().index()
+    }
+}
+
+fn is_perfect_number(number: u8) -> bool {
+    matches!((number as i32 - 1) % LETTERS.len() as i32, 0 | 3 | 4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(symbol: &str) -> Note {
+        Note::parse(symbol).unwrap()
+    }
+
+    #[test]
+    fn semitones_ok() {
+        assert_eq!(Interval::UNISON.semitones(), 0);
+        assert_eq!(Interval::MINOR_SECOND.semitones(), 1);
+        assert_eq!(Interval::AUGMENTED_FOURTH.semitones(), 6);
+        assert_eq!(Interval::DIMINISHED_FIFTH.semitones(), 6);
+        assert_eq!(Interval::DIMINISHED_SEVENTH.semitones(), 9);
+        assert_eq!(Interval::OCTAVE.semitones(), 12);
+        assert_eq!(
+            Interval::MAJOR_THIRD.add_octaves(1).unwrap().semitones(),
+            16
+        );
+        assert_eq!(Interval::new(Quality::Major, 9).unwrap().semitones(), 14);
+        assert_eq!(
+            Interval::new(Quality::Perfect, 15).unwrap().semitones(),
+            24
+        );
+    }
+    #[test]
+    fn new_fail() {
+        assert!(Interval::new(Quality::Major, 5).is_err());
+        assert!(Interval::new(Quality::Perfect, 3).is_err());
+        assert!(Interval::new(Quality::Diminished, 1).is_err());
+        assert!(Interval::new(Quality::Perfect, 0).is_err());
+        assert!(Interval::OCTAVE.add_octaves(36).is_err());
+        assert!(Interval::OCTAVE.add_octaves(u8::MAX).is_err());
+    }
+    #[test]
+    fn add_respects_spelling_ok() -> Result<()> {
+        assert_eq!((note("D4") + Interval::MAJOR_THIRD)?, note("F#4"));
+        assert_eq!((note("Eb4") + Interval::PERFECT_FIFTH)?, note("Bb4"));
+        assert_eq!((note("B3") + Interval::MINOR_SECOND)?, note("C4"));
+        assert_eq!((note("C4") + Interval::AUGMENTED_FOURTH)?, note("F#4"));
+        assert_eq!((note("C4") + Interval::DIMINISHED_FIFTH)?, note("Gb4"));
+        assert_eq!((note("G#4") + Interval::MAJOR_THIRD)?, note("B#4"));
+        assert_eq!((note("A4") + Interval::OCTAVE)?, note("A5"));
+        assert_eq!(
+            (note("C4") + Interval::MAJOR_SECOND.add_octaves(1)?)?,
+            note("D5")
+        );
+        Ok(())
+    }
+    #[test]
+    fn add_fail() {
+        assert!((note("Fbb4") + Interval::MINOR_SECOND).is_err());
+        assert!((note("G9") + Interval::OCTAVE).is_err());
+    }
+    #[test]
+    fn sub_ok() -> Result<()> {
+        assert_eq!((note("F#4") - note("D4"))?, Interval::MAJOR_THIRD);
+        assert_eq!((note("Gb4") - note("C4"))?, Interval::DIMINISHED_FIFTH);
+        assert_eq!((note("F#4") - note("C4"))?, Interval::AUGMENTED_FOURTH);
+        assert_eq!((note("C5") - note("C4"))?, Interval::OCTAVE);
+        assert_eq!((note("C4") - note("B3"))?, Interval::MINOR_SECOND);
+        assert_eq!((note("C4") - note("C4"))?, Interval::UNISON);
+        assert_eq!(
+            (note("E5") - note("C4"))?,
+            Interval::MAJOR_THIRD.add_octaves(1)?
+        );
+        Ok(())
+    }
+    #[test]
+    fn sub_fail() {
+        assert!((note("C4") - note("D4")).is_err());
+        assert!((note("C4") - note("C#4")).is_err());
+    }
+    #[test]
+    fn add_sub_round_trip_ok() -> Result<()> {
+        let root = note("Ab3");
+        for number in 1..=15 {
+            for quality in &[
+                Quality::Diminished,
+                Quality::Minor,
+                Quality::Perfect,
+                Quality::Major,
+                Quality::Augmented,
+            ] {
+                if let Ok(interval) = Interval::new(*quality, number) {
+                    if let Ok(top) = root + interval {
+                        assert_eq!((top - root)?, interval);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+    #[test]
+    fn transpose_ok() -> Result<()> {
+        assert_eq!(note("C4").transpose(1)?, note("C#4"));
+        assert_eq!(note("Bb3").transpose(1)?, note("B3"));
+        assert_eq!(note("Eb4").transpose(3)?, note("Gb4"));
+        assert_eq!(note("F#4").transpose(-1)?, note("F4"));
+        assert_eq!(note("A4").transpose(-13)?, note("G#3"));
+        assert_eq!(note("Db4").transpose(12)?, note("Db5"));
+        assert!(note("C-1").transpose(-1).is_err());
+        Ok(())
+    }
+}
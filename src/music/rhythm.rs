@@ -0,0 +1,498 @@
+use super::{Error, Kind, Result};
+use crate::sound::Ticks;
+
+const SECONDS_PER_MINUTE: f64 = 60.0;
+const QUARTERS_PER_WHOLE: f64 = 4.0;
+const DEFAULT_BPM: f64 = 120.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value {
+    DoubleWhole,
+    Whole,
+    Half,
+    Quarter,
+    Eighth,
+    Sixteenth,
+    ThirtySecond,
+    SixtyFourth,
+}
+
+impl Value {
+    /// Length in quarter notes.
+    pub fn quarters(&self) -> f64 {
+        match self {
+            Value::DoubleWhole => 8.0,
+            Value::Whole => 4.0,
+            Value::Half => 2.0,
+            Value::Quarter => 1.0,
+            Value::Eighth => 0.5,
+            Value::Sixteenth => 0.25,
+            Value::ThirtySecond => 0.125,
+            Value::SixtyFourth => 0.0625,
+        }
+    }
+}
+
+/// `actual` notes played in the time of `normal`, as in a triplet where 3
+/// eighths take the time of 2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tuplet {
+    actual: u32,
+    normal: u32,
+}
+
+impl Tuplet {
+    pub fn new(actual: u32, normal: u32) -> Result<Tuplet> {
+        if actual == 0 || normal == 0 {
+            Err(Error::new("Invalid tuplet", Kind::Zinnia))
+        } else {
+            Ok(Tuplet { actual, normal })
+        }
+    }
+
+    pub fn triplet() -> Tuplet {
+        Tuplet {
+            actual: 3,
+            normal: 2,
+        }
+    }
+
+    fn scale(&self) -> f64 {
+        self.normal as f64 / self.actual as f64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoteValue {
+    value: Value,
+    dots: u8,
+    tuplet: Option<Tuplet>,
+}
+
+impl NoteValue {
+    pub fn new(value: Value) -> NoteValue {
+        NoteValue {
+            value,
+            dots: 0,
+            tuplet: None,
+        }
+    }
+
+    pub fn dots(mut self, dots: u8) -> Self {
+        self.dots = dots;
+        self
+    }
+
+    pub fn tuplet(mut self, tuplet: Tuplet) -> Self {
+        self.tuplet = Some(tuplet);
+        self
+    }
+
+    /// Length in quarter notes.
+    pub fn quarters(&self) -> f64 {
+        let base = self.value.quarters();
+        let dotted = base * (2.0 - 0.5f64.powi(self.dots as i32));
+        match self.tuplet {
+            Some(tuplet) => dotted * tuplet.scale(),
+            None => dotted,
+        }
+    }
+
+    pub fn tie(self, other: NoteValue) -> Tie {
+        Tie {
+            values: vec![self, other],
+        }
+    }
+
+    /// Number of ticks the value lasts when it starts `start` quarter notes
+    /// into a piece.
+    pub fn ticks(&self, tempo: &Tempo, start: f64, rate: Ticks) -> Ticks {
+        tempo.ticks_between(start, start + self.quarters(), rate)
+    }
+}
+
+/// Note values tied together into one sounding note.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tie {
+    values: Vec<NoteValue>,
+}
+
+impl Tie {
+    pub fn new(values: &[NoteValue]) -> Tie {
+        Tie {
+            values: values.to_vec(),
+        }
+    }
+
+    pub fn tie(mut self, other: NoteValue) -> Tie {
+        self.values.push(other);
+        self
+    }
+
+    pub fn quarters(&self) -> f64 {
+        self.values.iter().map(|value| value.quarters()).sum()
+    }
+
+    pub fn ticks(&self, tempo: &Tempo, start: f64, rate: Ticks) -> Ticks {
+        tempo.ticks_between(start, start + self.quarters(), rate)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Change {
+    Jump,
+    Ramp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TempoPoint {
+    quarter: f64,
+    bpm: f64,
+    change: Change,
+}
+
+/// A tempo map in beats per minute. Positions are measured in quarter
+/// notes from the start of the piece, whatever the beat unit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tempo {
+    beat: f64,
+    points: Vec<TempoPoint>,
+}
+
+impl Tempo {
+    /// Constant tempo with a quarter note beat. Fails unless `bpm` is
+    /// positive, as do the other constructors and changes.
+    pub fn new(bpm: f64) -> Result<Tempo> {
+        Tempo::with_beat(bpm, NoteValue::new(Value::Quarter))
+    }
+
+    /// Constant tempo counting `beat` notes per minute, such as dotted
+    /// quarters in 6/8.
+    pub fn with_beat(bpm: f64, beat: NoteValue) -> Result<Tempo> {
+        check_bpm(bpm)?;
+        Ok(Tempo::constant(bpm, beat))
+    }
+
+    fn constant(bpm: f64, beat: NoteValue) -> Tempo {
+        Tempo {
+            beat: beat.quarters(),
+            points: vec![TempoPoint {
+                quarter: 0.0,
+                bpm,
+                change: Change::Jump,
+            }],
+        }
+    }
+
+    /// Changes abruptly to `bpm` at `quarter`.
+    pub fn set(mut self, quarter: f64, bpm: f64) -> Result<Self> {
+        check_bpm(bpm)?;
+        self.insert(quarter, bpm, Change::Jump);
+        Ok(self)
+    }
+
+    /// Changes smoothly from the tempo at `from` to `bpm` at `to`. A change
+    /// already at `from`, such as the end of another ramp, is kept.
+    pub fn ramp(mut self, from: f64, to: f64, bpm: f64) -> Result<Self> {
+        check_bpm(bpm)?;
+        if !self.points.iter().any(|point| point.quarter == from) {
+            let start = self.bpm(from);
+            self.insert(from, start, Change::Jump);
+        }
+        self.insert(to, bpm, Change::Ramp);
+        Ok(self)
+    }
+
+    /// Beats per minute at `quarter`.
+    pub fn bpm(&self, quarter: f64) -> f64 {
+        let idx = self.segment(quarter);
+        let point = &self.points[idx];
+        match self.points.get(idx + 1) {
+            Some(next) if next.change == Change::Ramp => {
+                let progress =
+                    (quarter - point.quarter) / (next.quarter - point.quarter);
+                point.bpm + (next.bpm - point.bpm) * progress
+            }
+            _ => point.bpm,
+        }
+    }
+
+    /// Quarter notes per minute at `quarter`, whatever the beat unit.
+    pub fn quarters_per_minute(&self, quarter: f64) -> f64 {
+        self.bpm(quarter) * self.beat
+    }
+
+    /// Positions at which the tempo changes, each paired with whether the
+    /// tempo ramps towards it rather than jumping there.
+    pub fn changes(&self) -> impl Iterator<Item = (f64, bool)> + '_ {
+        self.points
+            .iter()
+            .map(|point| (point.quarter, point.change == Change::Ramp))
+    }
+
+    /// Seconds from the start of the piece to `quarter`.
+    pub fn seconds(&self, quarter: f64) -> f64 {
+        let mut seconds = 0.0;
+        for (idx, point) in self.points.iter().enumerate() {
+            if point.quarter >= quarter {
+                break;
+            }
+            let next = self.points.get(idx + 1);
+            let end = next.map_or(quarter, |next| next.quarter.min(quarter));
+            seconds += self.segment_seconds(point, next, end);
+        }
+        seconds
+    }
+
+    /// Tick at which `quarter` falls, at the sample rate `rate`.
+    pub fn ticks(&self, quarter: f64, rate: Ticks) -> Ticks {
+        (self.seconds(quarter) * rate as f64).round() as Ticks
+    }
+
+    /// Ticks from `start` to `end`, or 0 if `end` comes first. Taking the
+    /// difference of absolute tick positions keeps consecutive notes from
+    /// drifting through rounding.
+    pub fn ticks_between(&self, start: f64, end: f64, rate: Ticks) -> Ticks {
+        self.ticks(end, rate)
+            .saturating_sub(self.ticks(start, rate))
+    }
+
+    fn insert(&mut self, quarter: f64, bpm: f64, change: Change) {
+        self.points.retain(|point| point.quarter != quarter);
+        let idx = self
+            .points
+            .iter()
+            .position(|point| point.quarter > quarter)
+            .unwrap_or(self.points.len());
+        self.points.insert(
+            idx,
+            TempoPoint {
+                quarter,
+                bpm,
+                change,
+            },
+        );
+    }
+
+    fn segment(&self, quarter: f64) -> usize {
+        self.points
+            .iter()
+            .rposition(|point| point.quarter <= quarter)
+            .unwrap_or(0)
+    }
+
+    // Seconds from `point` to `end`, where `end` is no later than `next`.
+    fn segment_seconds(
+        &self,
+        point: &TempoPoint,
+        next: Option<&TempoPoint>,
+        end: f64,
+    ) -> f64 {
+        let quarters_per_second =
+            |bpm: f64| bpm * self.beat / SECONDS_PER_MINUTE;
+        let length = end - point.quarter;
+
+        match next {
+            Some(next) if next.change == Change::Ramp => {
+                // The tempo rises linearly with position, so time is the
+                // integral of 1 / (a + b * q), which is logarithmic.
+                let slope =
+                    (next.bpm - point.bpm) / (next.quarter - point.quarter);
+                if slope.abs() < f64::EPSILON {
+                    length / quarters_per_second(point.bpm)
+                } else {
+                    let end_bpm = point.bpm + slope * length;
+                    (end_bpm / point.bpm).ln()
+                        / (slope * self.beat / SECONDS_PER_MINUTE)
+                }
+            }
+            _ => length / quarters_per_second(point.bpm),
+        }
+    }
+}
+
+impl Default for Tempo {
+    fn default() -> Self {
+        Tempo::constant(DEFAULT_BPM, NoteValue::new(Value::Quarter))
+    }
+}
+
+fn check_bpm(bpm: f64) -> Result<()> {
+    if bpm > 0.0 && bpm.is_finite() {
+        Ok(())
+    } else {
+        Err(Error::new("Invalid tempo", Kind::Zinnia))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeSignature {
+    beats: u32,
+    unit: u32,
+}
+
+impl TimeSignature {
+    pub fn new(beats: u32, unit: u32) -> Result<TimeSignature> {
+        if beats == 0 || unit == 0 || !unit.is_power_of_two() {
+            Err(Error::new("Invalid time signature", Kind::Zinnia))
+        } else {
+            Ok(TimeSignature { beats, unit })
+        }
+    }
+
+    /// Parses a time signature written as "3/4", "6/8" and so on.
+    pub fn parse(symbol: &str) -> Result<TimeSignature> {
+        let symbol = symbol.trim();
+        let slash = symbol
+            .find('/')
+            .ok_or_else(|| Error::new("Expected '/'", Kind::Parse(0)))?;
+        let beats = symbol[..slash]
+            .trim()
+            .parse()
+            .map_err(|_| Error::new("Invalid beat count", Kind::Parse(0)))?;
+        let unit = symbol[slash + 1..].trim().parse().map_err(|_| {
+            Error::new("Invalid beat unit", Kind::Parse(slash + 1))
+        })?;
+        TimeSignature::new(beats, unit)
+    }
+
+    pub fn beats(&self) -> u32 {
+        self.beats
+    }
+
+    pub fn unit(&self) -> u32 {
+        self.unit
+    }
+
+    /// Compound meters such as 6/8, 6/4 and 12/8 group their units in
+    /// threes, whatever the unit.
+    pub fn is_compound(&self) -> bool {
+        self.beats > 3 && self.beats.is_multiple_of(3)
+    }
+
+    /// Length of one bar in quarter notes.
+    pub fn bar_quarters(&self) -> f64 {
+        self.beats as f64 * QUARTERS_PER_WHOLE / self.unit as f64
+    }
+
+    /// Position in quarter notes of the start of `bar`, counting from 0.
+    pub fn bar_start(&self, bar: u32) -> f64 {
+        bar as f64 * self.bar_quarters()
+    }
+
+    /// Ticks taken by `bar` at `tempo`.
+    pub fn bar_ticks(&self, bar: u32, tempo: &Tempo, rate: Ticks) -> Ticks {
+        tempo.ticks_between(self.bar_start(bar), self.bar_start(bar + 1), rate)
+    }
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        TimeSignature { beats: 4, unit: 4 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: Ticks = 8000;
+
+    #[test]
+    fn note_values_ok() {
+        let quarter = NoteValue::new(Value::Quarter);
+        assert_eq!(quarter.quarters(), 1.0);
+        assert_eq!(NoteValue::new(Value::Half).dots(1).quarters(), 3.0);
+        assert_eq!(NoteValue::new(Value::Quarter).dots(2).quarters(), 1.75);
+        let triplet = NoteValue::new(Value::Eighth).tuplet(Tuplet::triplet());
+        assert!((triplet.quarters() - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(quarter.tie(NoteValue::new(Value::Eighth)).quarters(), 1.5);
+    }
+    #[test]
+    fn constant_tempo_ticks_ok() -> Result<()> {
+        let tempo = Tempo::new(120.0)?;
+        let quarter = NoteValue::new(Value::Quarter);
+        assert_eq!(quarter.ticks(&tempo, 0.0, RATE), 4000);
+        assert_eq!(tempo.ticks(4.0, RATE), 16000);
+
+        let dotted =
+            Tempo::with_beat(60.0, NoteValue::new(Value::Quarter).dots(1))?;
+        assert_eq!(dotted.ticks(1.5, RATE), 8000);
+        assert_eq!(dotted.quarters_per_minute(0.0), 90.0);
+        Ok(())
+    }
+    #[test]
+    fn triplets_do_not_drift() -> Result<()> {
+        let tempo = Tempo::new(100.0)?;
+        let triplet = NoteValue::new(Value::Eighth).tuplet(Tuplet::triplet());
+        let mut start = 0.0;
+        let mut total = 0;
+        for _ in 0..300 {
+            total += triplet.ticks(&tempo, start, RATE);
+            start += triplet.quarters();
+        }
+        assert_eq!(total, tempo.ticks(100.0, RATE));
+        Ok(())
+    }
+    #[test]
+    fn tempo_changes_ok() -> Result<()> {
+        let tempo = Tempo::new(60.0)?.set(4.0, 120.0)?;
+        assert_eq!(tempo.bpm(3.9), 60.0);
+        assert_eq!(tempo.bpm(4.0), 120.0);
+        assert!((tempo.seconds(8.0) - 6.0).abs() < 1e-9);
+        assert_eq!(tempo.ticks_between(4.0, 4.0, RATE), 0);
+        assert_eq!(tempo.ticks_between(8.0, 4.0, RATE), 0);
+        Ok(())
+    }
+    #[test]
+    fn tempo_ramp_ok() -> Result<()> {
+        let tempo = Tempo::new(60.0)?.ramp(0.0, 4.0, 120.0)?;
+        assert!((tempo.bpm(2.0) - 90.0).abs() < 1e-9);
+        assert_eq!(tempo.bpm(6.0), 120.0);
+        let expected = 4.0 * 2.0f64.ln();
+        assert!((tempo.seconds(4.0) - expected).abs() < 1e-9);
+        assert!((tempo.seconds(6.0) - expected - 1.0).abs() < 1e-9);
+        let changes: Vec<(f64, bool)> = tempo.changes().collect();
+        assert_eq!(changes, [(0.0, false), (4.0, true)]);
+        Ok(())
+    }
+    #[test]
+    fn chained_ramps_ok() -> Result<()> {
+        let tempo = Tempo::new(60.0)?
+            .ramp(0.0, 4.0, 120.0)?
+            .ramp(4.0, 8.0, 60.0)?;
+        assert!((tempo.bpm(2.0) - 90.0).abs() < 1e-9);
+        assert_eq!(tempo.bpm(4.0), 120.0);
+        assert!((tempo.bpm(6.0) - 90.0).abs() < 1e-9);
+        let changes: Vec<(f64, bool)> = tempo.changes().collect();
+        assert_eq!(changes, [(0.0, false), (4.0, true), (8.0, true)]);
+        let expected = 8.0 * 2.0f64.ln();
+        assert!((tempo.seconds(8.0) - expected).abs() < 1e-9);
+        Ok(())
+    }
+    #[test]
+    fn invalid_tempo() {
+        assert!(Tempo::new(0.0).is_err());
+        assert!(Tempo::new(-60.0).is_err());
+        assert!(Tempo::new(f64::NAN).is_err());
+        assert!(Tempo::default().set(4.0, 0.0).is_err());
+        assert!(Tempo::default().ramp(0.0, 4.0, -10.0).is_err());
+    }
+    #[test]
+    fn time_signatures_ok() -> Result<()> {
+        let waltz = TimeSignature::parse("3/4")?;
+        assert_eq!(waltz.bar_quarters(), 3.0);
+        assert!(!waltz.is_compound());
+
+        let jig = TimeSignature::parse("6/8")?;
+        assert_eq!(jig.bar_quarters(), 3.0);
+        assert!(jig.is_compound());
+        assert!(TimeSignature::parse("6/4")?.is_compound());
+        assert!(!TimeSignature::parse("3/8")?.is_compound());
+        assert_eq!(jig.bar_ticks(2, &Tempo::new(90.0)?, RATE), 16000);
+
+        assert!(TimeSignature::parse("3/5").is_err());
+        assert!(TimeSignature::parse("34").is_err());
+        Ok(())
+    }
+}
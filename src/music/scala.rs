@@ -0,0 +1,413 @@
+use std::{fs, iter::Enumerate, path::Path, str::Lines};
+
+use super::{tuning::Tuning, Error, Kind, Note, Result, MAX_MIDI_NUMBER};
+
+const CENTS_PER_OCTAVE: f32 = 1200.0;
+const MIDDLE_C_MIDI_NUMBER: i32 = 60;
+const MIDDLE_C_FREQ: f32 = 261.625_58;
+
+/// A scale read from a Scala `.scl` file. Pitches are held in cents above
+/// the first degree, the last one being the period the scale repeats at.
+#[derive(Debug, Clone)]
+pub struct ScalaScale {
+    description: String,
+    pitches: Vec<f32>,
+}
+
+impl ScalaScale {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<ScalaScale> {
+        ScalaScale::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<ScalaScale> {
+        let mut lines = ScalaLines::new(text);
+
+        // The description may legitimately be blank, so it is the one line
+        // that is read without skipping empty lines.
+        let description = match lines.next_raw() {
+            Some((_, line)) => line.trim().to_string(),
+            None => {
+                return Err(Error::new(
+                    "Missing scale description",
+                    Kind::Line(lines.last_line()),
+                ))
+            }
+        };
+
+        let (line_number, line) = lines.expect("Missing note count")?;
+        let count: usize = first_token(line).parse().map_err(|_| {
+            Error::new("Invalid note count", Kind::Line(line_number))
+        })?;
+
+        let mut pitches = Vec::with_capacity(count);
+        for _ in 0..count {
+            let (line_number, line) = lines.expect("Missing pitch")?;
+            pitches.push(parse_pitch(first_token(line), line_number)?);
+        }
+
+        if let Some((line_number, _)) = lines.next() {
+            return Err(Error::new(
+                "More pitches than the note count",
+                Kind::Line(line_number),
+            ));
+        }
+
+        Ok(ScalaScale {
+            description,
+            pitches,
+        })
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// Number of degrees before the scale repeats.
+    pub fn len(&self) -> usize {
+        self.pitches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pitches.is_empty()
+    }
+
+    /// Cents above degree zero of any degree, repeating the scale at its
+    /// period in both directions.
+    pub fn cents(&self, degree: i32) -> f32 {
+        let size = self.pitches.len() as i32;
+        if size == 0 {
+            return 0.0;
+        }
+
+        let period = self.pitches[self.pitches.len() - 1];
+        let step = degree.rem_euclid(size);
+        let cents = if step == 0 {
+            0.0
+        } else {
+            self.pitches[step as usize - 1]
+        };
+        degree.div_euclid(size) as f32 * period + cents
+    }
+}
+
+/// A Scala `.kbm` keyboard mapping, deciding which scale degree each MIDI
+/// key plays and which key is tuned to the reference frequency.
+#[derive(Debug, Clone)]
+pub struct KeyboardMapping {
+    first_key: i32,
+    last_key: i32,
+    middle_key: i32,
+    reference_key: i32,
+    reference_freq: f32,
+    octave_degree: i32,
+    mapping: Vec<Option<i32>>,
+}
+
+impl KeyboardMapping {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<KeyboardMapping> {
+        KeyboardMapping::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<KeyboardMapping> {
+        let mut lines = ScalaLines::new(text);
+
+        let size = lines.integer("Invalid map size")?;
+        if size < 0 {
+            return Err(Error::new(
+                "Invalid map size",
+                Kind::Line(lines.last_line()),
+            ));
+        }
+        let first_key = lines.key("Invalid first key")?;
+        let last_key = lines.key("Invalid last key")?;
+        let middle_key = lines.key("Invalid middle key")?;
+        let reference_key = lines.key("Invalid reference key")?;
+
+        let (line_number, line) =
+            lines.expect("Missing reference frequency")?;
+        let reference_freq: f32 = match first_token(line).parse() {
+            Ok(freq) if freq > 0.0 => freq,
+            _ => {
+                return Err(Error::new(
+                    "Invalid reference frequency",
+                    Kind::Line(line_number),
+                ))
+            }
+        };
+
+        let octave_degree = lines.integer("Invalid octave degree")?;
+
+        // Trailing entries may be left out, in which case they are unmapped.
+        let mut mapping = Vec::with_capacity(size as usize);
+        for _ in 0..size {
+            match lines.next() {
+                Some((line_number, line)) => {
+                    let token = first_token(line);
+                    if token.eq_ignore_ascii_case("x") {
+                        mapping.push(None);
+                    } else {
+                        let degree = token.parse().map_err(|_| {
+                            Error::new(
+                                "Invalid mapping entry",
+                                Kind::Line(line_number),
+                            )
+                        })?;
+                        mapping.push(Some(degree));
+                    }
+                }
+                None => mapping.push(None),
+            }
+        }
+
+        if let Some((line_number, _)) = lines.next() {
+            return Err(Error::new(
+                "More mapping entries than the map size",
+                Kind::Line(line_number),
+            ));
+        }
+
+        Ok(KeyboardMapping {
+            first_key,
+            last_key,
+            middle_key,
+            reference_key,
+            reference_freq,
+            octave_degree,
+            mapping,
+        })
+    }
+
+    fn degree(&self, key: i32) -> Result<i32> {
+        if key < self.first_key || key > self.last_key {
+            return Err(Error::new("Key is not retuned", Kind::Zinnia));
+        }
+
+        let steps = key - self.middle_key;
+        if self.mapping.is_empty() {
+            return Ok(steps);
+        }
+
+        let size = self.mapping.len() as i32;
+        match self.mapping[steps.rem_euclid(size) as usize] {
+            Some(degree) => {
+                Ok(degree + steps.div_euclid(size) * self.octave_degree)
+            }
+            None => Err(Error::new("Key is unmapped", Kind::Zinnia)),
+        }
+    }
+}
+
+/// Linear mapping of every key to consecutive degrees, with degree zero on
+/// middle C tuned as in 12-tone equal temperament.
+impl Default for KeyboardMapping {
+    fn default() -> Self {
+        KeyboardMapping {
+            first_key: 0,
+            last_key: MAX_MIDI_NUMBER,
+            middle_key: MIDDLE_C_MIDI_NUMBER,
+            reference_key: MIDDLE_C_MIDI_NUMBER,
+            reference_freq: MIDDLE_C_FREQ,
+            octave_degree: 0,
+            mapping: Vec::new(),
+        }
+    }
+}
+
+pub struct ScalaTuning {
+    scale: ScalaScale,
+    mapping: KeyboardMapping,
+}
+
+impl ScalaTuning {
+    pub fn new(scale: ScalaScale, mapping: KeyboardMapping) -> Self {
+        ScalaTuning { scale, mapping }
+    }
+
+    pub fn with_scale(scale: ScalaScale) -> Self {
+        ScalaTuning::new(scale, KeyboardMapping::default())
+    }
+
+    /// Frequency of a MIDI key. Scales without 12 degrees to the octave are
+    /// more naturally addressed by key than by note name.
+    pub fn key_freq(&self, key: u8) -> Result<f32> {
+        let degree = self.mapping.degree(key as i32)?;
+        let reference = self.mapping.degree(self.mapping.reference_key)?;
+        let cents = self.scale.cents(degree) - self.scale.cents(reference);
+        Ok(self.mapping.reference_freq * 2.0f32.powf(cents / CENTS_PER_OCTAVE))
+    }
+}
+
+impl Tuning for ScalaTuning {
+    fn freq(&self, note: &Note) -> Result<f32> {
+        self.key_freq(note.midi_number()?)
+    }
+}
+
+// Non-comment lines of a Scala file, numbered from 1.
+struct ScalaLines<'a> {
+    lines: Enumerate<Lines<'a>>,
+    last_line: usize,
+}
+
+impl<'a> ScalaLines<'a> {
+    fn new(text: &'a str) -> Self {
+        ScalaLines {
+            lines: text.lines().enumerate(),
+            last_line: 0,
+        }
+    }
+
+    fn last_line(&self) -> usize {
+        self.last_line
+    }
+
+    fn next_raw(&mut self) -> Option<(usize, &'a str)> {
+        for (index, line) in &mut self.lines {
+            self.last_line = index + 1;
+            if !line.starts_with('!') {
+                return Some((index + 1, line));
+            }
+        }
+        None
+    }
+
+    fn expect(&mut self, message: &'static str) -> Result<(usize, &'a str)> {
+        match self.next() {
+            Some(line) => Ok(line),
+            None => Err(Error::new(message, Kind::Line(self.last_line + 1))),
+        }
+    }
+
+    fn integer(&mut self, message: &'static str) -> Result<i32> {
+        let (line_number, line) = self.expect(message)?;
+        first_token(line)
+            .parse()
+            .map_err(|_| Error::new(message, Kind::Line(line_number)))
+    }
+
+    fn key(&mut self, message: &'static str) -> Result<i32> {
+        let key = self.integer(message)?;
+        if !(0..=MAX_MIDI_NUMBER).contains(&key) {
+            Err(Error::new(message, Kind::Line(self.last_line)))
+        } else {
+            Ok(key)
+        }
+    }
+}
+
+impl<'a> Iterator for ScalaLines<'a> {
+    type Item = (usize, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((line_number, line)) = self.next_raw() {
+            if !line.trim().is_empty() {
+                return Some((line_number, line));
+            }
+        }
+        None
+    }
+}
+
+fn first_token(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or("")
+}
+
+// A pitch containing a period is in cents, anything else is a ratio such
+// as "3/2" or a whole number such as "2".
+fn parse_pitch(token: &str, line_number: usize) -> Result<f32> {
+    let invalid = || Error::new("Invalid pitch", Kind::Line(line_number));
+
+    if token.contains('.') {
+        return token.parse().map_err(|_| invalid());
+    }
+
+    let (num, den) = match token.find('/') {
+        Some(idx) => (&token[..idx], &token[idx + 1..]),
+        None => (token, "1"),
+    };
+    let num: u64 = num.parse().map_err(|_| invalid())?;
+    let den: u64 = den.parse().map_err(|_| invalid())?;
+    if num == 0 || den == 0 {
+        return Err(invalid());
+    }
+
+    Ok(CENTS_PER_OCTAVE * (num as f64 / den as f64).log2() as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::{tuning::EqualTemperament, Octave};
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 0.01,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn scl_cents_ok() -> Result<()> {
+        let scale = ScalaScale::open("data/scala/12tet.scl")?;
+        assert_eq!(scale.description(), "12 tone equal temperament");
+        assert_eq!(scale.len(), 12);
+        assert_close(scale.cents(7), 700.0);
+        assert_close(scale.cents(12), 1200.0);
+        assert_close(scale.cents(-1), -100.0);
+        Ok(())
+    }
+    #[test]
+    fn scl_ratios_ok() -> Result<()> {
+        let scale = ScalaScale::open("data/scala/pythagorean.scl")?;
+        assert_eq!(scale.len(), 12);
+        assert_close(scale.cents(7), 701.955);
+        assert_close(scale.cents(1), 90.225);
+        assert_close(scale.cents(12), 1200.0);
+        Ok(())
+    }
+    #[test]
+    fn default_mapping_matches_equal_temperament() -> Result<()> {
+        let tuning =
+            ScalaTuning::with_scale(ScalaScale::open("data/scala/12tet.scl")?);
+        let equal = EqualTemperament::default();
+        for key in 0..=127 {
+            let note = Note::from_midi(key)?;
+            assert_close(tuning.freq(&note)?, equal.freq(&note)?);
+        }
+        Ok(())
+    }
+    #[test]
+    fn kbm_white_keys_ok() -> Result<()> {
+        let tuning = ScalaTuning::new(
+            ScalaScale::open("data/scala/just_major.scl")?,
+            KeyboardMapping::open("data/scala/white_keys.kbm")?,
+        );
+        assert_close(tuning.freq(&Note::A(Octave::Four))?, 440.0);
+        assert_close(tuning.freq(&Note::C(Octave::Four))?, 264.0);
+        assert_close(tuning.freq(&Note::E(Octave::Four))?, 330.0);
+        assert_close(tuning.freq(&Note::C(Octave::Five))?, 528.0);
+        assert_close(tuning.freq(&Note::B(Octave::Three))?, 247.5);
+        assert!(tuning.freq(&Note::CSharp(Octave::Four)).is_err());
+        Ok(())
+    }
+    #[test]
+    fn scl_error_lines() {
+        let line = |text| ScalaScale::parse(text).unwrap_err().kind();
+        assert_eq!(line("! only a comment\n"), Kind::Line(1));
+        assert_eq!(line("desc\n two\n"), Kind::Line(2));
+        assert_eq!(line("desc\n!\n 2\n 3/2\n 0/1\n"), Kind::Line(5));
+        assert_eq!(line("desc\n 2\n 100.0\n"), Kind::Line(4));
+        assert_eq!(line("desc\n 1\n 2/1\n 3/2\n"), Kind::Line(4));
+        assert_eq!(line("desc\n 1\n 1.2.3\n"), Kind::Line(3));
+    }
+    #[test]
+    fn kbm_error_lines() {
+        let line = |text| KeyboardMapping::parse(text).unwrap_err().kind();
+        assert_eq!(line("0\n0\n128\n"), Kind::Line(3));
+        assert_eq!(line("0\n0\n127\n60\n69\n-440\n"), Kind::Line(6));
+        assert_eq!(line("1\n0\n127\n60\n69\n440\n1\n?\n"), Kind::Line(8));
+        assert_eq!(line("1\n0\n127\n60\n69\n440\n1\n0\n1\n"), Kind::Line(9));
+    }
+}
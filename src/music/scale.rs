@@ -0,0 +1,562 @@
+use std::convert::TryFrom;
+
+use super::{
+    interval::{Interval, Quality},
+    Accidental, Error, Kind, Letter, Note, NoteParser, Octave, Result,
+    KEYS_PER_OCTAVE, LETTERS,
+};
+
+// Letters in the order sharps are added to a key signature. Flats are added
+// in the reverse order.
+const SHARP_ORDER: [Letter; 7] = [
+    Letter::F,
+    Letter::C,
+    Letter::G,
+    Letter::D,
+    Letter::A,
+    Letter::E,
+    Letter::B,
+];
+
+// Position of each natural letter on the circle of fifths, counting from C.
+const LETTER_FIFTHS: [i32; 7] = [0, 2, 4, -1, 1, 3, 5];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleKind {
+    Major,
+    NaturalMinor,
+    HarmonicMinor,
+    MelodicMinor,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian,
+    MajorPentatonic,
+    MinorPentatonic,
+    Blues,
+    WholeTone,
+    /// Octatonic scale starting with a whole step.
+    WholeHalfDiminished,
+    /// Octatonic scale starting with a half step.
+    HalfWholeDiminished,
+}
+
+impl ScaleKind {
+    fn intervals(&self) -> Vec<Interval> {
+        use Quality::*;
+
+        let qualities: &[(Quality, u8)] = match self {
+            ScaleKind::Major => &[
+                (Perfect, 1),
+                (Major, 2),
+                (Major, 3),
+                (Perfect, 4),
+                (Perfect, 5),
+                (Major, 6),
+                (Major, 7),
+            ],
+            ScaleKind::NaturalMinor => &[
+                (Perfect, 1),
+                (Major, 2),
+                (Minor, 3),
+                (Perfect, 4),
+                (Perfect, 5),
+                (Minor, 6),
+                (Minor, 7),
+            ],
+            ScaleKind::HarmonicMinor => &[
+                (Perfect, 1),
+                (Major, 2),
+                (Minor, 3),
+                (Perfect, 4),
+                (Perfect, 5),
+                (Minor, 6),
+                (Major, 7),
+            ],
+            ScaleKind::MelodicMinor => &[
+                (Perfect, 1),
+                (Major, 2),
+                (Minor, 3),
+                (Perfect, 4),
+                (Perfect, 5),
+                (Major, 6),
+                (Major, 7),
+            ],
+            ScaleKind::Dorian => &[
+                (Perfect, 1),
+                (Major, 2),
+                (Minor, 3),
+                (Perfect, 4),
+                (Perfect, 5),
+                (Major, 6),
+                (Minor, 7),
+            ],
+            ScaleKind::Phrygian => &[
+                (Perfect, 1),
+                (Minor, 2),
+                (Minor, 3),
+                (Perfect, 4),
+                (Perfect, 5),
+                (Minor, 6),
+                (Minor, 7),
+            ],
+            ScaleKind::Lydian => &[
+                (Perfect, 1),
+                (Major, 2),
+                (Major, 3),
+                (Augmented, 4),
+                (Perfect, 5),
+                (Major, 6),
+                (Major, 7),
+            ],
+            ScaleKind::Mixolydian => &[
+                (Perfect, 1),
+                (Major, 2),
+                (Major, 3),
+                (Perfect, 4),
+                (Perfect, 5),
+                (Major, 6),
+                (Minor, 7),
+            ],
+            ScaleKind::Locrian => &[
+                (Perfect, 1),
+                (Minor, 2),
+                (Minor, 3),
+                (Perfect, 4),
+                (Diminished, 5),
+                (Minor, 6),
+                (Minor, 7),
+            ],
+            ScaleKind::MajorPentatonic => &[
+                (Perfect, 1),
+                (Major, 2),
+                (Major, 3),
+                (Perfect, 5),
+                (Major, 6),
+            ],
+            ScaleKind::MinorPentatonic => &[
+                (Perfect, 1),
+                (Minor, 3),
+                (Perfect, 4),
+                (Perfect, 5),
+                (Minor, 7),
+            ],
+            ScaleKind::Blues => &[
+                (Perfect, 1),
+                (Minor, 3),
+                (Perfect, 4),
+                (Diminished, 5),
+                (Perfect, 5),
+                (Minor, 7),
+            ],
+            ScaleKind::WholeTone => &[
+                (Perfect, 1),
+                (Major, 2),
+                (Major, 3),
+                (Augmented, 4),
+                (Augmented, 5),
+                (Minor, 7),
+            ],
+            ScaleKind::WholeHalfDiminished => &[
+                (Perfect, 1),
+                (Major, 2),
+                (Minor, 3),
+                (Perfect, 4),
+                (Diminished, 5),
+                (Minor, 6),
+                (Major, 6),
+                (Major, 7),
+            ],
+            ScaleKind::HalfWholeDiminished => &[
+                (Perfect, 1),
+                (Minor, 2),
+                (Minor, 3),
+                (Major, 3),
+                (Augmented, 4),
+                (Perfect, 5),
+                (Major, 6),
+                (Minor, 7),
+            ],
+        };
+
+        qualities
+            .iter()
+            .map(|(quality, number)| Interval::new(*quality, *number).unwrap())
+            .collect()
+    }
+}
+
+/// The notes of a scale, repeating every octave above and below the tonic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scale {
+    tonic: Note,
+    intervals: Vec<Interval>,
+}
+
+impl Scale {
+    pub fn new(tonic: Note, kind: ScaleKind) -> Scale {
+        Scale {
+            tonic,
+            intervals: kind.intervals(),
+        }
+    }
+
+    /// Scale built from a pattern of semitone steps that must add up to an
+    /// octave, such as `[2, 2, 1, 2, 2, 2, 1]` for the major scale.
+    /// Seven note patterns get one letter name per degree where the
+    /// intervals allow it; any other pattern is spelled with the tonic's
+    /// sharps or flats.
+    pub fn from_steps(tonic: Note, steps: &[i32]) -> Result<Scale> {
+        if steps.is_empty()
+            || steps.iter().any(|step| *step <= 0)
+            || steps.iter().sum::<i32>() != KEYS_PER_OCTAVE
+        {
+            return Err(Error::new(
+                "Scale steps must add up to an octave",
+                Kind::Zinnia,
+            ));
+        }
+
+        // Semitones from the tonic to each degree.
+        let degrees: Vec<i32> = steps[..steps.len() - 1]
+            .iter()
+            .scan(0, |semitones, step| {
+                *semitones += step;
+                Some(*semitones)
+            })
+            .collect();
+
+        let lettered = || {
+            degrees
+                .iter()
+                .enumerate()
+                .map(|(degree, semitones)| {
+                    Interval::from_steps(degree as i32 + 1, *semitones)
+                })
+                .collect::<Result<Vec<Interval>>>()
+        };
+        let chromatic = || {
+            degrees
+                .iter()
+                .map(|semitones| tonic.transpose(*semitones)? - tonic)
+                .collect::<Result<Vec<Interval>>>()
+        };
+
+        let spelled = if steps.len() == LETTERS.len() {
+            lettered().or_else(|_| chromatic())
+        } else {
+            chromatic()
+        }?;
+        let mut intervals = vec![Interval::UNISON];
+        intervals.extend(spelled);
+
+        Ok(Scale { tonic, intervals })
+    }
+
+    pub fn tonic(&self) -> Note {
+        self.tonic
+    }
+
+    /// Number of notes before the scale repeats.
+    pub fn len(&self) -> usize {
+        self.intervals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    /// The note `index` scale steps from the tonic, where 0 is the tonic
+    /// itself, `len()` is the tonic an octave higher and negative indices
+    /// go below it.
+    pub fn note(&self, index: i32) -> Result<Note> {
+        let len = self.intervals.len() as i32;
+        let note =
+            (self.tonic + self.intervals[index.rem_euclid(len) as usize])?;
+        let octave = note.octave() as i32 + index.div_euclid(len);
+        Ok(note.with_octave(Octave::try_from(octave)?))
+    }
+
+    /// Notes from the tonic upward, ending at the top of the note range.
+    pub fn ascending(&self) -> impl Iterator<Item = Note> + '_ {
+        (0..).map_while(move |index| self.note(index).ok())
+    }
+
+    /// Notes from the tonic downward, ending at the bottom of the note
+    /// range.
+    pub fn descending(&self) -> impl Iterator<Item = Note> + '_ {
+        (0..).map_while(move |index| self.note(-index).ok())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Major,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Minor,
+    Locrian,
+}
+
+impl Mode {
+    // Fifths from the tonic of the mode up to that of its relative major.
+    fn fifths_from_major(&self) -> i32 {
+        match self {
+            Mode::Lydian => 1,
+            Mode::Major => 0,
+            Mode::Mixolydian => -1,
+            Mode::Dorian => -2,
+            Mode::Minor => -3,
+            Mode::Phrygian => -4,
+            Mode::Locrian => -5,
+        }
+    }
+
+    fn scale_kind(&self) -> ScaleKind {
+        match self {
+            Mode::Major => ScaleKind::Major,
+            Mode::Dorian => ScaleKind::Dorian,
+            Mode::Phrygian => ScaleKind::Phrygian,
+            Mode::Lydian => ScaleKind::Lydian,
+            Mode::Mixolydian => ScaleKind::Mixolydian,
+            Mode::Minor => ScaleKind::NaturalMinor,
+            Mode::Locrian => ScaleKind::Locrian,
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Mode> {
+        let name = name.to_ascii_lowercase();
+        match name.as_str() {
+            "" | "maj" | "major" | "ion" | "ionian" => Some(Mode::Major),
+            "m" | "min" | "minor" | "aeo" | "aeolian" => Some(Mode::Minor),
+            "dor" | "dorian" => Some(Mode::Dorian),
+            "phr" | "phrygian" => Some(Mode::Phrygian),
+            "lyd" | "lydian" => Some(Mode::Lydian),
+            "mix" | "mixolydian" => Some(Mode::Mixolydian),
+            "loc" | "locrian" => Some(Mode::Locrian),
+            _ => None,
+        }
+    }
+}
+
+/// A key, which decides the key signature and how chromatic notes are
+/// spelled. The octave of the tonic is only used for `scale`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    tonic: Note,
+    mode: Mode,
+}
+
+impl Key {
+    pub fn new(tonic: Note, mode: Mode) -> Key {
+        Key { tonic, mode }
+    }
+
+    /// Parses a key name such as "G", "F#m", "Bb minor" or "D mix", with
+    /// the tonic in octave 4.
+    pub fn parse(name: &str) -> Result<Key> {
+        let mut parser = NoteParser::new(name);
+        parser.skip_whitespace();
+        let letter = parser.letter()?;
+        let accidental = parser.accidental()?;
+        let start = parser.position();
+
+        let rest: String = name.chars().skip(start).collect();
+        match Mode::from_name(rest.trim()) {
+            Some(mode) => {
+                Ok(Key::new(Note::new(letter, accidental, Octave::Four), mode))
+            }
+            None => Err(Error::new("Invalid mode", Kind::Parse(start))),
+        }
+    }
+
+    pub fn tonic(&self) -> Note {
+        self.tonic
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Number of sharps in the key signature, negative for flats. Keys
+    /// beyond seven sharps or flats use double accidentals.
+    pub fn signature(&self) -> i32 {
+        LETTER_FIFTHS[self.tonic.letter().index() as usize]
+            + self.tonic.accidental().semitones() * LETTERS.len() as i32
+            + self.mode.fifths_from_major()
+    }
+
+    /// Accidental the key signature gives to `letter`.
+    pub fn accidental(&self, letter: Letter) -> Accidental {
+        let signature = self.signature();
+        let count = if signature >= 0 {
+            SHARP_ORDER.iter().position(|l| *l == letter).unwrap() as i32
+        } else {
+            SHARP_ORDER.iter().rev().position(|l| *l == letter).unwrap() as i32
+        };
+        let alteration = (signature.abs() - count + LETTERS.len() as i32 - 1)
+            / LETTERS.len() as i32;
+        Accidental::from_semitones(alteration.min(2) * signature.signum())
+            .unwrap()
+    }
+
+    pub fn scale(&self) -> Scale {
+        Scale::new(self.tonic, self.mode.scale_kind())
+    }
+
+    /// Spells a MIDI-style semitone number in this key: with a letter from
+    /// the key signature where one fits, otherwise with an accidental in the
+    /// direction of the key signature.
+    pub fn spell(&self, semitones: i32) -> Result<Note> {
+        let pitch_class = semitones.rem_euclid(KEYS_PER_OCTAVE);
+        let direction = if self.signature() < 0 { -1 } else { 1 };
+
+        for alteration in &[0, direction] {
+            for letter in LETTERS.iter() {
+                let accidental = match Accidental::from_semitones(
+                    self.accidental(*letter).semitones() + alteration,
+                ) {
+                    Some(accidental) => accidental,
+                    None => continue,
+                };
+                let offset = letter.semitones() + accidental.semitones();
+                if offset.rem_euclid(KEYS_PER_OCTAVE) == pitch_class {
+                    let octave =
+                        (semitones - offset).div_euclid(KEYS_PER_OCTAVE) - 1;
+                    return Ok(Note::new(
+                        *letter,
+                        accidental,
+                        Octave::try_from(octave)?,
+                    ));
+                }
+            }
+        }
+
+        Err(Error::new("Cannot spell note in key", Kind::Zinnia))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(symbol: &str) -> Note {
+        Note::parse(symbol).unwrap()
+    }
+
+    fn names(notes: impl Iterator<Item = Note>) -> Vec<String> {
+        notes.map(|note| note.to_string()).collect()
+    }
+
+    #[test]
+    fn major_spelling_ok() {
+        let scale = Scale::new(note("F#4"), ScaleKind::Major);
+        assert_eq!(
+            names(scale.ascending().take(8)),
+            ["F#4", "G#4", "A#4", "B4", "C#5", "D#5", "E#5", "F#5"]
+        );
+    }
+    #[test]
+    fn minor_scales_ok() {
+        let harmonic = Scale::new(note("Eb4"), ScaleKind::HarmonicMinor);
+        assert_eq!(
+            names(harmonic.ascending().take(7)),
+            ["Eb4", "F4", "Gb4", "Ab4", "Bb4", "Cb5", "D5"]
+        );
+        let melodic = Scale::new(note("A3"), ScaleKind::MelodicMinor);
+        assert_eq!(names(melodic.ascending().skip(5).take(2)), ["F#4", "G#4"]);
+    }
+    #[test]
+    fn modes_ok() {
+        let dorian = Scale::new(note("D4"), ScaleKind::Dorian);
+        assert_eq!(
+            names(dorian.ascending().take(7)),
+            ["D4", "E4", "F4", "G4", "A4", "B4", "C5"]
+        );
+        let locrian = Scale::new(note("B3"), ScaleKind::Locrian);
+        assert_eq!(locrian.note(4).unwrap(), note("F4"));
+    }
+    #[test]
+    fn other_scales_ok() {
+        let blues = Scale::new(note("A3"), ScaleKind::Blues);
+        assert_eq!(
+            names(blues.ascending().take(7)),
+            ["A3", "C4", "D4", "Eb4", "E4", "G4", "A4"]
+        );
+        let whole = Scale::new(note("C4"), ScaleKind::WholeTone);
+        assert_eq!(whole.len(), 6);
+        assert_eq!(whole.note(6).unwrap(), note("C5"));
+        let diminished = Scale::new(note("C4"), ScaleKind::HalfWholeDiminished);
+        assert_eq!(diminished.len(), 8);
+        assert_eq!(diminished.note(4).unwrap(), note("F#4"));
+    }
+    #[test]
+    fn across_octaves_ok() {
+        let scale = Scale::new(note("C4"), ScaleKind::MajorPentatonic);
+        assert_eq!(scale.note(5).unwrap(), note("C5"));
+        assert_eq!(scale.note(-1).unwrap(), note("A3"));
+        assert_eq!(names(scale.descending().take(3)), ["C4", "A3", "G3"]);
+        assert_eq!(scale.ascending().last().unwrap(), note("A9"));
+    }
+    #[test]
+    fn from_steps_ok() -> Result<()> {
+        let major = Scale::from_steps(note("Db4"), &[2, 2, 1, 2, 2, 2, 1])?;
+        assert_eq!(major, Scale::new(note("Db4"), ScaleKind::Major));
+
+        let hirajoshi = Scale::from_steps(note("Bb3"), &[2, 1, 4, 1, 4])?;
+        assert_eq!(
+            names(hirajoshi.ascending().take(5)),
+            ["Bb3", "C4", "Db4", "F4", "Gb4"]
+        );
+
+        // Too crowded for one letter per degree.
+        let cluster = Scale::from_steps(note("C4"), &[1, 1, 1, 1, 1, 1, 6])?;
+        assert_eq!(
+            names(cluster.ascending().take(8)),
+            ["C4", "C#4", "D4", "D#4", "E4", "F4", "F#4", "C5"]
+        );
+
+        assert!(Scale::from_steps(note("C4"), &[2, 2, 2]).is_err());
+        Ok(())
+    }
+    #[test]
+    fn signatures_ok() -> Result<()> {
+        assert_eq!(Key::parse("C")?.signature(), 0);
+        assert_eq!(Key::parse("Bb")?.signature(), -2);
+        assert_eq!(Key::parse("F#m")?.signature(), 3);
+        assert_eq!(Key::parse("C#")?.signature(), 7);
+        assert_eq!(Key::parse("D mix")?.signature(), 1);
+        assert_eq!(Key::parse("E Phrygian")?.signature(), 0);
+        assert_eq!(Key::parse("G#")?.signature(), 8);
+        Ok(())
+    }
+    #[test]
+    fn key_accidentals_ok() -> Result<()> {
+        let key = Key::parse("Eb")?;
+        assert_eq!(key.accidental(Letter::B), Accidental::Flat);
+        assert_eq!(key.accidental(Letter::A), Accidental::Flat);
+        assert_eq!(key.accidental(Letter::D), Accidental::Natural);
+
+        let key = Key::parse("G#")?;
+        assert_eq!(key.accidental(Letter::F), Accidental::DoubleSharp);
+        assert_eq!(key.accidental(Letter::C), Accidental::Sharp);
+        Ok(())
+    }
+    #[test]
+    fn spell_in_key_ok() -> Result<()> {
+        assert_eq!(Key::parse("F")?.spell(70)?, note("Bb4"));
+        assert_eq!(Key::parse("F")?.spell(61)?, note("Db4"));
+        assert_eq!(Key::parse("D")?.spell(61)?, note("C#4"));
+        assert_eq!(Key::parse("F#")?.spell(65)?, note("E#4"));
+        assert_eq!(Key::parse("Gb")?.spell(71)?, note("Cb5"));
+        Ok(())
+    }
+    #[test]
+    fn parse_fail() {
+        assert_eq!(Key::parse("Q").unwrap_err().kind(), Kind::Parse(0));
+        assert_eq!(Key::parse("Cfoo").unwrap_err().kind(), Kind::Parse(1));
+    }
+}
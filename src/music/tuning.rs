@@ -0,0 +1,207 @@
+use super::{Note, Result, A4_FREQ, A4_MIDI_NUMBER, KEYS_PER_OCTAVE};
+
+const CENTS_PER_OCTAVE: f32 = 1200.0;
+
+const JUST_RATIOS: [(u32, u32); 12] = [
+    (1, 1),
+    (16, 15),
+    (9, 8),
+    (6, 5),
+    (5, 4),
+    (4, 3),
+    (45, 32),
+    (3, 2),
+    (8, 5),
+    (5, 3),
+    (9, 5),
+    (15, 8),
+];
+
+// Position of each chromatic degree along the chain of fifths from the
+// tonic, as used by Pythagorean tuning (Db to F#) and meantone (Eb to G#).
+const PYTHAGOREAN_FIFTHS: [i32; 12] = [0, -5, 2, -3, 4, -1, 6, 1, -4, 3, -2, 5];
+const MEANTONE_FIFTHS: [i32; 12] = [0, 7, 2, -3, 4, -1, 6, 1, 8, 3, -2, 5];
+
+const WERCKMEISTER_III_CENTS: [f32; 12] = [
+    0.0, 90.225, 192.18, 294.135, 390.225, 498.045, 588.27, 696.09, 792.18,
+    888.27, 996.09, 1092.18,
+];
+
+pub trait Tuning: Send {
+    fn freq(&self, note: &Note) -> Result<f32>;
+}
+
+/// Twelve-tone equal temperament with A4 at `reference` Hz.
+#[derive(Debug, Clone, Copy)]
+pub struct EqualTemperament {
+    reference: f32,
+}
+
+impl EqualTemperament {
+    pub fn new(reference: f32) -> Self {
+        EqualTemperament { reference }
+    }
+}
+
+impl Default for EqualTemperament {
+    fn default() -> Self {
+        EqualTemperament::new(A4_FREQ)
+    }
+}
+
+impl Tuning for EqualTemperament {
+    fn freq(&self, note: &Note) -> Result<f32> {
+        let midi_number = note.midi_number()? as i32;
+        Ok(2.0f32.powf(
+            (midi_number - A4_MIDI_NUMBER) as f32 / KEYS_PER_OCTAVE as f32,
+        ) * self.reference)
+    }
+}
+
+/// A twelve note tuning given as cents above the tonic for each chromatic
+/// degree, repeating at the octave. A4 sounds at `reference` Hz whatever
+/// the tonic, so the tonic only decides where the table starts.
+#[derive(Debug, Clone, Copy)]
+pub struct CentTable {
+    cents: [f32; 12],
+    tonic: i32,
+    reference: f32,
+}
+
+impl CentTable {
+    pub fn new(cents: [f32; 12], reference: f32) -> Self {
+        CentTable {
+            cents,
+            tonic: 0,
+            reference,
+        }
+    }
+
+    /// 5-limit just intonation.
+    pub fn just_intonation(reference: f32) -> Self {
+        let mut cents = [0.0; 12];
+        for (c, (num, den)) in cents.iter_mut().zip(JUST_RATIOS.iter()) {
+            *c = ratio_cents(*num as f32 / *den as f32);
+        }
+        CentTable::new(cents, reference)
+    }
+
+    /// Pure 3:2 fifths from Db to F#.
+    pub fn pythagorean(reference: f32) -> Self {
+        CentTable::from_fifths(ratio_cents(1.5), &PYTHAGOREAN_FIFTHS, reference)
+    }
+
+    /// Quarter-comma meantone, with pure major thirds from Eb to G#.
+    pub fn quarter_comma_meantone(reference: f32) -> Self {
+        CentTable::from_fifths(
+            ratio_cents(5.0f32.powf(0.25)),
+            &MEANTONE_FIFTHS,
+            reference,
+        )
+    }
+
+    /// Werckmeister III well temperament.
+    pub fn werckmeister_iii(reference: f32) -> Self {
+        CentTable::new(WERCKMEISTER_III_CENTS, reference)
+    }
+
+    /// Starts the table at `tonic` instead of C. The octave of `tonic` is
+    /// ignored.
+    pub fn tonic(mut self, tonic: &Note) -> Self {
+        self.tonic = tonic.semitones().rem_euclid(KEYS_PER_OCTAVE);
+        self
+    }
+
+    fn from_fifths(fifth: f32, fifths: &[i32; 12], reference: f32) -> Self {
+        let mut cents = [0.0; 12];
+        for (c, n) in cents.iter_mut().zip(fifths.iter()) {
+            *c = (*n as f32 * fifth).rem_euclid(CENTS_PER_OCTAVE);
+        }
+        CentTable::new(cents, reference)
+    }
+
+    fn cents_above_tonic(&self, semitones: i32) -> f32 {
+        let steps = semitones - self.tonic;
+        steps.div_euclid(KEYS_PER_OCTAVE) as f32 * CENTS_PER_OCTAVE
+            + self.cents[steps.rem_euclid(KEYS_PER_OCTAVE) as usize]
+    }
+}
+
+impl Tuning for CentTable {
+    fn freq(&self, note: &Note) -> Result<f32> {
+        let cents = self.cents_above_tonic(note.midi_number()? as i32)
+            - self.cents_above_tonic(A4_MIDI_NUMBER);
+        Ok(self.reference * 2.0f32.powf(cents / CENTS_PER_OCTAVE))
+    }
+}
+
+fn ratio_cents(ratio: f32) -> f32 {
+    CENTS_PER_OCTAVE * ratio.log2()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::Octave;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 0.01,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn equal_temperament_415_ok() -> Result<()> {
+        let tuning = EqualTemperament::new(415.0);
+        assert_close(tuning.freq(&Note::A(Octave::Four))?, 415.0);
+        assert_close(tuning.freq(&Note::A(Octave::Five))?, 830.0);
+        assert_close(
+            tuning.freq(&Note::C(Octave::Four))?,
+            Note::C(Octave::Four).freq()? * 415.0 / 440.0,
+        );
+        Ok(())
+    }
+    #[test]
+    fn just_intonation_ratios_ok() -> Result<()> {
+        let tuning = CentTable::just_intonation(440.0);
+        let c = tuning.freq(&Note::C(Octave::Four))?;
+        assert_close(tuning.freq(&Note::A(Octave::Four))?, 440.0);
+        assert_close(tuning.freq(&Note::E(Octave::Four))? / c, 1.25);
+        assert_close(tuning.freq(&Note::G(Octave::Four))? / c, 1.5);
+        assert_close(tuning.freq(&Note::C(Octave::Five))? / c, 2.0);
+        Ok(())
+    }
+    #[test]
+    fn just_intonation_tonic_ok() -> Result<()> {
+        let tuning =
+            CentTable::just_intonation(440.0).tonic(&Note::parse("D4")?);
+        let d = tuning.freq(&Note::D(Octave::Four))?;
+        assert_close(tuning.freq(&Note::FSharp(Octave::Four))? / d, 1.25);
+        assert_close(tuning.freq(&Note::CSharp(Octave::Five))? / d, 1.875);
+        Ok(())
+    }
+    #[test]
+    fn pythagorean_fifth_ok() -> Result<()> {
+        let tuning = CentTable::pythagorean(440.0);
+        let d = tuning.freq(&Note::D(Octave::Four))?;
+        assert_close(tuning.freq(&Note::A(Octave::Four))? / d, 1.5);
+        Ok(())
+    }
+    #[test]
+    fn meantone_third_ok() -> Result<()> {
+        let tuning = CentTable::quarter_comma_meantone(440.0);
+        let e_flat = tuning.freq(&Note::EFlat(Octave::Four))?;
+        assert_close(tuning.freq(&Note::G(Octave::Four))? / e_flat, 1.25);
+        Ok(())
+    }
+    #[test]
+    fn werckmeister_baroque_pitch_ok() -> Result<()> {
+        let tuning = CentTable::werckmeister_iii(415.0);
+        assert_close(tuning.freq(&Note::A(Octave::Four))?, 415.0);
+        assert_close(tuning.freq(&Note::A(Octave::Three))?, 207.5);
+        Ok(())
+    }
+}
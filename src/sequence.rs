@@ -0,0 +1,571 @@
+pub mod abc;
+pub mod midi;
+pub mod musicxml;
+
+use crate::{
+    hwp::HardwareParams,
+    music::{
+        rhythm::{Tempo, TimeSignature},
+        tuning::Tuning,
+        Note,
+    },
+    sound::{
+        self,
+        config::SoundConfigCollection,
+        filter::{LinearFadeIn, LinearFadeOut},
+        Sinusoid, Sound, Ticks, Timeline,
+    },
+    Result,
+};
+use alsa::pcm::IoFormat;
+use std::cmp::Ordering;
+
+pub const MAX_VELOCITY: u8 = 127;
+const FADE_TICKS_PER_SECOND: Ticks = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Parameter {
+    /// Scales the amplitude of following notes, from 0.0 to 1.0.
+    Volume(f32),
+    /// Places following notes between the left (-1.0) and right (1.0)
+    /// channels.
+    Pan(f32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    NoteOn { note: Note, velocity: u8 },
+    NoteOff { note: Note },
+    Rest { length: f64 },
+    Parameter(Parameter),
+}
+
+impl Event {
+    // Order of events sharing a position: a note ends before the same pitch
+    // starts again, and parameter changes apply to notes starting with them.
+    fn rank(&self) -> u8 {
+        match self {
+            Event::NoteOff { .. } => 0,
+            Event::Parameter(_) => 1,
+            Event::Rest { .. } => 2,
+            Event::NoteOn { .. } => 3,
+        }
+    }
+}
+
+/// An event `position` quarter notes from the start of the sequence.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimedEvent {
+    position: f64,
+    event: Event,
+}
+
+impl TimedEvent {
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+
+    pub fn event(&self) -> Event {
+        self.event
+    }
+
+    fn cmp_order(&self, other: &TimedEvent) -> Ordering {
+        self.position
+            .partial_cmp(&other.position)
+            .unwrap_or(Ordering::Equal)
+            .then(self.event.rank().cmp(&other.event.rank()))
+    }
+}
+
+/// A note between its on and off events, with the track settings in effect
+/// when it started.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoteSpan {
+    start: f64,
+    length: f64,
+    note: Note,
+    velocity: u8,
+    volume: f32,
+    pan: f32,
+}
+
+impl NoteSpan {
+    pub fn start(&self) -> f64 {
+        self.start
+    }
+
+    pub fn length(&self) -> f64 {
+        self.length
+    }
+
+    pub fn note(&self) -> Note {
+        self.note
+    }
+
+    pub fn velocity(&self) -> u8 {
+        self.velocity
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    pub fn pan(&self) -> f32 {
+        self.pan
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+    name: String,
+    events: Vec<TimedEvent>,
+}
+
+impl Track {
+    pub fn new(name: &str) -> Track {
+        Track {
+            name: name.to_string(),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn events(&self) -> &[TimedEvent] {
+        &self.events
+    }
+
+    /// Adds `event` at `position`, after any events already there.
+    pub fn push(&mut self, position: f64, event: Event) {
+        let timed = TimedEvent { position, event };
+        let idx = self
+            .events
+            .iter()
+            .rposition(|e| e.cmp_order(&timed) != Ordering::Greater)
+            .map_or(0, |idx| idx + 1);
+        self.events.insert(idx, timed);
+    }
+
+    pub fn note(
+        &mut self,
+        position: f64,
+        length: f64,
+        note: Note,
+        velocity: u8,
+    ) {
+        self.push(position, Event::NoteOn { note, velocity });
+        self.push(position + length, Event::NoteOff { note });
+    }
+
+    pub fn rest(&mut self, position: f64, length: f64) {
+        self.push(position, Event::Rest { length });
+    }
+
+    pub fn parameter(&mut self, position: f64, parameter: Parameter) {
+        self.push(position, Event::Parameter(parameter));
+    }
+
+    /// Position in quarter notes at which the last event finishes.
+    pub fn end(&self) -> f64 {
+        self.events
+            .iter()
+            .map(|e| match e.event {
+                Event::Rest { length } => e.position + length,
+                _ => e.position,
+            })
+            .fold(0.0, f64::max)
+    }
+
+    /// Pairs note on and off events. A note off ends the earliest sounding
+    /// note of the same pitch, and notes never turned off last until the
+    /// end of the track.
+    pub fn notes(&self) -> Vec<NoteSpan> {
+        let mut volume = 1.0;
+        let mut pan = 0.0;
+        let mut open = Vec::<NoteSpan>::new();
+        let mut spans = Vec::new();
+
+        for timed in &self.events {
+            match timed.event {
+                Event::NoteOn { note, velocity } => open.push(NoteSpan {
+                    start: timed.position,
+                    length: 0.0,
+                    note,
+                    velocity,
+                    volume,
+                    pan,
+                }),
+                Event::NoteOff { note } => {
+                    // Spelling doesn't matter, so Db4 ends a C#4.
+                    let pitch = note.semitones();
+                    if let Some(idx) =
+                        open.iter().position(|s| s.note.semitones() == pitch)
+                    {
+                        let mut span = open.remove(idx);
+                        span.length = timed.position - span.start;
+                        spans.push(span);
+                    }
+                }
+                Event::Parameter(Parameter::Volume(value)) => volume = value,
+                Event::Parameter(Parameter::Pan(value)) => pan = value,
+                Event::Rest { .. } => (),
+            }
+        }
+
+        let end = self.end();
+        for mut span in open {
+            span.length = end - span.start;
+            spans.push(span);
+        }
+        spans.sort_by(|a, b| {
+            a.start.partial_cmp(&b.start).unwrap_or(Ordering::Equal)
+        });
+        spans
+    }
+}
+
+/// Everything an `Instrument` needs to build the sound of one note.
+pub struct VoiceConfig {
+    freq: f32,
+    amplitudes: Vec<f32>,
+    ticks: Ticks,
+    rate: Ticks,
+}
+
+impl VoiceConfig {
+    pub fn new(
+        freq: f32,
+        amplitudes: &[f32],
+        ticks: Ticks,
+        rate: Ticks,
+    ) -> VoiceConfig {
+        VoiceConfig {
+            freq,
+            amplitudes: amplitudes.to_vec(),
+            ticks,
+            rate,
+        }
+    }
+
+    pub fn freq(&self) -> f32 {
+        self.freq
+    }
+
+    /// Peak amplitude for each channel, with velocity, volume and pan
+    /// already applied.
+    pub fn amplitudes(&self) -> &[f32] {
+        &self.amplitudes
+    }
+
+    pub fn ticks(&self) -> Ticks {
+        self.ticks
+    }
+
+    pub fn rate(&self) -> Ticks {
+        self.rate
+    }
+
+    /// One `SoundConfigCollection` entry per channel.
+    pub fn sound_config(&self) -> SoundConfigCollection {
+        let mut config = SoundConfigCollection::new();
+        for amplitude in &self.amplitudes {
+            config.add_config(self.freq, 0.0, *amplitude);
+        }
+        config
+    }
+}
+
+/// Makes the sound of each note. Voices are released at note-off, so one
+/// with a release phase can outlast `VoiceConfig::ticks`; others should
+/// last exactly that long.
+pub trait Instrument: Send {
+    fn voice(&self, config: &VoiceConfig) -> Box<dyn Sound>;
+}
+
+/// Plain sine tones with short fades to avoid clicks.
+pub struct SineInstrument;
+
+impl Instrument for SineInstrument {
+    fn voice(&self, config: &VoiceConfig) -> Box<dyn Sound> {
+        let fade = (config.rate / FADE_TICKS_PER_SECOND)
+            .min(config.ticks / 2)
+            .max(1);
+        let mut sound = Sinusoid::with_ticks(
+            &config.sound_config(),
+            config.ticks,
+            config.rate,
+        );
+        sound.add_filter(Box::new(LinearFadeIn::new(fade)));
+        if config.ticks >= fade {
+            sound.add_filter(Box::new(LinearFadeOut::new(fade, config.ticks)));
+        }
+        Box::new(sound)
+    }
+}
+
+/// Tracks of timed events sharing a tempo map and time signature.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Sequence {
+    tracks: Vec<Track>,
+    tempo: Tempo,
+    time_signature: TimeSignature,
+}
+
+impl Sequence {
+    pub fn new(tempo: Tempo, time_signature: TimeSignature) -> Sequence {
+        Sequence {
+            tracks: Vec::new(),
+            tempo,
+            time_signature,
+        }
+    }
+
+    pub fn add_track(&mut self, track: Track) {
+        self.tracks.push(track);
+    }
+
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    pub fn tempo(&self) -> &Tempo {
+        &self.tempo
+    }
+
+    pub fn set_tempo(&mut self, tempo: Tempo) {
+        self.tempo = tempo;
+    }
+
+    pub fn time_signature(&self) -> TimeSignature {
+        self.time_signature
+    }
+
+    pub fn set_time_signature(&mut self, time_signature: TimeSignature) {
+        self.time_signature = time_signature;
+    }
+
+    pub fn end(&self) -> f64 {
+        self.tracks.iter().map(Track::end).fold(0.0, f64::max)
+    }
+
+    /// Builds a timeline with every note starting at its exact tick.
+    pub fn render<T>(
+        &self,
+        instrument: &dyn Instrument,
+        tuning: &dyn Tuning,
+        hwp: &HardwareParams<T>,
+    ) -> Result<Timeline>
+    where
+        T: IoFormat,
+    {
+        let rate = hwp.rate();
+        let max_amplitude = sound::max_amplitude::<T>() as f32;
+        let mut timeline = Timeline::new();
+
+        for track in &self.tracks {
+            for span in track.notes() {
+                let start = self.tempo.ticks(span.start, rate);
+                let end = self.tempo.ticks(span.start + span.length, rate);
+                if end <= start {
+                    continue;
+                }
+
+                let amplitude =
+                    max_amplitude * span.volume * span.velocity as f32
+                        / MAX_VELOCITY as f32;
+                let amplitudes: Vec<f32> = (0..hwp.channels())
+                    .map(|channel| amplitude * balance(span.pan, channel))
+                    .collect();
+
+                let config = VoiceConfig::new(
+                    tuning.freq(&span.note)?,
+                    &amplitudes,
+                    end - start,
+                    rate,
+                );
+                timeline.schedule_release(
+                    start,
+                    end,
+                    instrument.voice(&config),
+                );
+            }
+        }
+        Ok(timeline)
+    }
+}
+
+// Gain of `channel` for `pan`, cutting only the opposite side so that
+// centred notes play at full level on both.
+fn balance(pan: f32, channel: u32) -> f32 {
+    let pan = pan.clamp(-1.0, 1.0);
+    match channel {
+        0 => (1.0 - pan).min(1.0),
+        1 => (1.0 + pan).min(1.0),
+        _ => 1.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hwp::HwpBuilder, music::tuning::EqualTemperament};
+
+    const RATE: Ticks = 8000;
+
+    // Outputs its first channel amplitude for exactly its length.
+    struct Constant {
+        amplitudes: Vec<f32>,
+        remaining: Ticks,
+    }
+
+    impl Sound for Constant {
+        fn generate(&mut self, channel: u32) -> f32 {
+            self.amplitudes[channel as usize]
+        }
+
+        fn tick(&mut self) {
+            self.remaining -= 1;
+        }
+
+        fn is_complete(&self) -> bool {
+            self.remaining == 0
+        }
+    }
+
+    struct ConstantInstrument;
+
+    impl Instrument for ConstantInstrument {
+        fn voice(&self, config: &VoiceConfig) -> Box<dyn Sound> {
+            Box::new(Constant {
+                amplitudes: config.amplitudes().to_vec(),
+                remaining: config.ticks(),
+            })
+        }
+    }
+
+    fn note(symbol: &str) -> Note {
+        Note::parse(symbol).unwrap()
+    }
+
+    fn render(sequence: &Sequence) -> Vec<(f32, f32)> {
+        let hwp = HwpBuilder::<i16>::new(25000, 5000, 2).rate(RATE).build();
+        let mut timeline = sequence
+            .render(&ConstantInstrument, &EqualTemperament::default(), &hwp)
+            .unwrap();
+
+        let mut output = Vec::new();
+        while !timeline.is_complete() {
+            output.push((timeline.generate(0), timeline.generate(1)));
+            timeline.tick();
+        }
+        output
+    }
+
+    #[test]
+    fn events_sorted_ok() {
+        let mut track = Track::new("melody");
+        track.note(1.0, 1.0, note("D4"), 100);
+        track.note(0.0, 1.0, note("C4"), 100);
+        track.parameter(1.0, Parameter::Volume(0.5));
+
+        let ranks: Vec<u8> =
+            track.events().iter().map(|e| e.event().rank()).collect();
+        assert_eq!(ranks, [3, 0, 1, 3, 0]);
+        assert_eq!(track.events()[3].position(), 1.0);
+        assert_eq!(track.end(), 2.0);
+    }
+    #[test]
+    fn notes_pair_on_and_off_ok() {
+        let mut track = Track::new("melody");
+        track.note(0.0, 2.0, note("C4"), 90);
+        track.note(2.0, 1.0, note("C4"), 80);
+        track.parameter(3.0, Parameter::Pan(-1.0));
+        track.push(
+            3.0,
+            Event::NoteOn {
+                note: note("E4"),
+                velocity: 70,
+            },
+        );
+        track.rest(3.0, 2.0);
+
+        let notes = track.notes();
+        assert_eq!(notes.len(), 3);
+        assert_eq!((notes[0].start(), notes[0].length()), (0.0, 2.0));
+        assert_eq!((notes[1].start(), notes[1].velocity()), (2.0, 80));
+        assert_eq!(notes[2].note(), note("E4"));
+        assert_eq!(notes[2].length(), 2.0);
+        assert_eq!(notes[2].pan(), -1.0);
+    }
+    #[test]
+    fn notes_pair_enharmonics() {
+        let mut track = Track::new("melody");
+        track.push(
+            0.0,
+            Event::NoteOn {
+                note: note("C#4"),
+                velocity: 90,
+            },
+        );
+        track.push(1.0, Event::NoteOff { note: note("Db4") });
+        track.rest(1.0, 3.0);
+
+        let notes = track.notes();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].note(), note("C#4"));
+        assert_eq!(notes[0].length(), 1.0);
+    }
+    #[test]
+    fn render_starts_on_exact_ticks() -> Result<()> {
+        let mut track = Track::new("melody");
+        track.note(0.0, 1.0, note("C4"), MAX_VELOCITY);
+        track.note(1.0, 0.5, note("E4"), 64);
+        track.rest(1.5, 0.5);
+
+        let mut sequence =
+            Sequence::new(Tempo::new(120.0)?, Default::default());
+        sequence.add_track(track);
+
+        let output = render(&sequence);
+        let max = sound::max_amplitude::<i16>() as f32;
+        let soft = max * 64.0 / MAX_VELOCITY as f32;
+        assert_eq!(output.len(), 6000);
+        assert!(output[..4000].iter().all(|(left, _)| *left == max));
+        assert!(output[4000..].iter().all(|(left, _)| *left == soft));
+        Ok(())
+    }
+    #[test]
+    fn render_applies_parameters() -> Result<()> {
+        let mut track = Track::new("melody");
+        track.parameter(0.0, Parameter::Volume(0.5));
+        track.parameter(0.0, Parameter::Pan(1.0));
+        track.note(0.0, 1.0, note("C4"), MAX_VELOCITY);
+
+        let mut sequence = Sequence::new(Tempo::new(60.0)?, Default::default());
+        sequence.add_track(track);
+
+        let output = render(&sequence);
+        let half = sound::max_amplitude::<i16>() as f32 * 0.5;
+        assert_eq!(output.len(), RATE as usize);
+        assert_eq!(output[0], (0.0, half));
+        Ok(())
+    }
+    #[test]
+    fn overlapping_tracks_mix() {
+        let mut sequence = Sequence::default();
+        for (name, start) in &[("one", 0.0), ("two", 0.5)] {
+            let mut track = Track::new(name);
+            track.note(*start, 1.0, note("A4"), MAX_VELOCITY);
+            sequence.add_track(track);
+        }
+
+        let output = render(&sequence);
+        let max = sound::max_amplitude::<i16>() as f32;
+        assert_eq!(output.len(), 6000);
+        assert_eq!(output[1999].0, max);
+        assert_eq!(output[2000].0, 2.0 * max);
+        assert_eq!(output[4000].0, max);
+    }
+}
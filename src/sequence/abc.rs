@@ -0,0 +1,826 @@
+use super::{Sequence, Track};
+use crate::{
+    error::{Error, Kind},
+    music::{
+        rhythm::{Tempo, TimeSignature},
+        scale::Key,
+        Accidental, Letter, Note, Octave,
+    },
+    Result,
+};
+use std::{convert::TryFrom, fs, mem, path::Path};
+
+const DEFAULT_VELOCITY: u8 = 100;
+const QUARTERS_PER_WHOLE: f64 = 4.0;
+// Meters shorter than this default to sixteenth notes rather than eighths.
+const SHORT_METER: f64 = 0.75;
+
+impl Sequence {
+    pub fn open_abc<P: AsRef<Path>>(path: P) -> Result<Sequence> {
+        Sequence::parse_abc(&fs::read_to_string(path)?)
+    }
+
+    /// Reads the first tune in `text`, written in ABC notation, into a
+    /// single track. Repeats and first and second endings are played out in
+    /// full. Errors are of kind `Kind::Line`.
+    pub fn parse_abc(text: &str) -> Result<Sequence> {
+        let mut parser = AbcParser::new();
+        for (idx, line) in text.lines().enumerate() {
+            parser.line = idx + 1;
+            if !parser.parse_line(line)? {
+                break;
+            }
+        }
+        parser.into_sequence()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Element {
+    Notes {
+        notes: Vec<Note>,
+        length: f64,
+        tied: bool,
+    },
+    Rest {
+        length: f64,
+    },
+    Tempo(f64),
+}
+
+impl Element {
+    fn scale(&mut self, factor: f64) {
+        match self {
+            Element::Notes { length, .. } | Element::Rest { length } => {
+                *length *= factor
+            }
+            Element::Tempo(_) => (),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Preamble,
+    Header,
+    Body,
+}
+
+struct AbcParser {
+    line: usize,
+    state: State,
+    title: Option<String>,
+    unit: Option<f64>,
+    meter: TimeSignature,
+    key: Key,
+    tempo: Option<f64>,
+    elements: Vec<Element>,
+    bar_accidentals: Vec<(Letter, Octave, Accidental)>,
+    repeat_start: usize,
+    ending_start: Option<usize>,
+    tuplet: Option<(f64, u32)>,
+    broken: Option<f64>,
+}
+
+impl AbcParser {
+    fn new() -> AbcParser {
+        AbcParser {
+            line: 0,
+            state: State::Preamble,
+            title: None,
+            unit: None,
+            meter: TimeSignature::default(),
+            key: Key::parse("C").unwrap(),
+            tempo: None,
+            elements: Vec::new(),
+            bar_accidentals: Vec::new(),
+            repeat_start: 0,
+            ending_start: None,
+            tuplet: None,
+            broken: None,
+        }
+    }
+
+    fn error(&self, msg: &'static str) -> Error {
+        Error::new(msg, Kind::Line(self.line))
+    }
+
+    // Returns false once the tune has ended.
+    fn parse_line(&mut self, line: &str) -> Result<bool> {
+        let trimmed = line.trim();
+        if trimmed.starts_with('%') {
+            return Ok(true);
+        }
+
+        let field = field(trimmed);
+        match self.state {
+            State::Preamble => {
+                if let Some((name, value)) = field {
+                    self.state = State::Header;
+                    self.field(name, value)?;
+                }
+            }
+            State::Header => match field {
+                Some((name, value)) => self.field(name, value)?,
+                None if trimmed.is_empty() => (),
+                None => {
+                    self.state = State::Body;
+                    self.music(trimmed)?;
+                }
+            },
+            State::Body => match field {
+                _ if trimmed.is_empty() => return Ok(false),
+                Some(('X', _)) => return Ok(false),
+                Some((name, value)) => self.field(name, value)?,
+                None => self.music(trimmed)?,
+            },
+        }
+        Ok(true)
+    }
+
+    fn field(&mut self, name: char, value: &str) -> Result<()> {
+        match name {
+            'T' if self.title.is_none() => self.title = Some(value.to_string()),
+            'M' => self.meter = self.meter(value)?,
+            'L' => self.unit = Some(self.fraction(value)? * QUARTERS_PER_WHOLE),
+            'Q' => {
+                if let Some(tempo) = self.tempo(value)? {
+                    match self.state {
+                        State::Body => {
+                            self.elements.push(Element::Tempo(tempo))
+                        }
+                        _ => self.tempo = Some(tempo),
+                    }
+                }
+            }
+            'K' => {
+                self.key = self.key(value)?;
+                self.state = State::Body;
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    // Default note length in quarter notes.
+    fn unit(&self) -> f64 {
+        self.unit.unwrap_or_else(|| {
+            let meter = self.meter.bar_quarters() / QUARTERS_PER_WHOLE;
+            if meter < SHORT_METER {
+                0.25
+            } else {
+                0.5
+            }
+        })
+    }
+
+    fn meter(&self, value: &str) -> Result<TimeSignature> {
+        match value.trim() {
+            "" | "none" => Ok(TimeSignature::default()),
+            "C" => TimeSignature::new(4, 4),
+            "C|" => TimeSignature::new(2, 2),
+            value => TimeSignature::parse(value)
+                .map_err(|_| self.error("Invalid meter")),
+        }
+    }
+
+    // A fraction of a whole note such as "1/8".
+    fn fraction(&self, value: &str) -> Result<f64> {
+        let value = value.trim();
+        let (numerator, denominator) = match value.find('/') {
+            Some(slash) => (&value[..slash], &value[slash + 1..]),
+            None => (value, "1"),
+        };
+        match (
+            numerator.trim().parse::<u32>(),
+            denominator.trim().parse::<u32>(),
+        ) {
+            (Ok(numerator), Ok(denominator)) if denominator > 0 => {
+                Ok(numerator as f64 / denominator as f64)
+            }
+            _ => Err(self.error("Invalid note length")),
+        }
+    }
+
+    // Tempo in quarter notes per minute, written as "1/4=120", "3/8=60" or
+    // a bare count of default lengths. Text in quotes is ignored.
+    fn tempo(&self, value: &str) -> Result<Option<f64>> {
+        let value: String = value.split('"').step_by(2).collect();
+        let value = value.trim();
+        if value.is_empty() {
+            return Ok(None);
+        }
+
+        let (beat, bpm) = match value.find('=') {
+            Some(equals) => {
+                let mut beat = 0.0;
+                for part in value[..equals].split_whitespace() {
+                    beat += self.fraction(part)? * QUARTERS_PER_WHOLE;
+                }
+                (beat, &value[equals + 1..])
+            }
+            None => (self.unit(), value),
+        };
+        match bpm.trim().parse::<f64>() {
+            Ok(bpm) if bpm > 0.0 && beat > 0.0 => Ok(Some(bpm * beat)),
+            _ => Err(self.error("Invalid tempo")),
+        }
+    }
+
+    // Key names may be followed by clef and other settings, which are
+    // ignored.
+    fn key(&self, value: &str) -> Result<Key> {
+        let tokens: Vec<&str> = value
+            .split('%')
+            .next()
+            .unwrap_or("")
+            .split_whitespace()
+            .filter(|token| !token.contains('='))
+            .collect();
+
+        match tokens.first() {
+            None | Some(&"none") => Ok(Key::parse("C")?),
+            Some(first) => tokens
+                .get(1)
+                .and_then(|mode| {
+                    Key::parse(&format!("{} {}", first, mode)).ok()
+                })
+                .map_or_else(|| Key::parse(first), Ok)
+                .map_err(|_| self.error("Invalid key")),
+        }
+    }
+
+    fn music(&mut self, line: &str) -> Result<()> {
+        let mut cursor = Cursor::new(line);
+        while let Some(c) = cursor.peek(0) {
+            match c {
+                '%' => break,
+                '"' | '!' | '+' => self.skip_past(&mut cursor, c)?,
+                '{' => self.skip_past(&mut cursor, '}')?,
+                '(' if cursor.peek(1).is_some_and(|c| c.is_ascii_digit()) => {
+                    self.tuplet(&mut cursor)
+                }
+                '-' => {
+                    cursor.next();
+                    if let Some(Element::Notes { tied, .. }) =
+                        self.elements.last_mut()
+                    {
+                        *tied = true;
+                    }
+                }
+                '>' | '<' => self.broken(&mut cursor),
+                '|' | ':' => self.bar(&mut cursor, String::new()),
+                '[' => match cursor.peek(1) {
+                    Some('|') => {
+                        cursor.next();
+                        self.bar(&mut cursor, "[".to_string());
+                    }
+                    Some(c) if c.is_ascii_digit() => {
+                        cursor.next();
+                        self.ending(&mut cursor);
+                    }
+                    Some(c)
+                        if c.is_ascii_alphabetic()
+                            && cursor.peek(2) == Some(':') =>
+                    {
+                        let start = cursor.idx + 1;
+                        self.skip_past(&mut cursor, ']')?;
+                        let inline: String = cursor.chars
+                            [start..cursor.idx - 1]
+                            .iter()
+                            .collect();
+                        if let Some((name, value)) = field(&inline) {
+                            self.field(name, value)?;
+                        }
+                    }
+                    _ => self.chord(&mut cursor)?,
+                },
+                'z' | 'x' => {
+                    cursor.next();
+                    let length = self.unit() * cursor.multiplier();
+                    self.push(Element::Rest { length });
+                }
+                'Z' | 'X' => {
+                    cursor.next();
+                    let bars = cursor.number().unwrap_or(1);
+                    self.elements.push(Element::Rest {
+                        length: bars as f64 * self.meter.bar_quarters(),
+                    });
+                }
+                '^' | '_' | '=' | 'A'..='G' | 'a'..='g' => {
+                    let (note, length) = self.note(&mut cursor)?;
+                    self.push(Element::Notes {
+                        notes: vec![note],
+                        length,
+                        tied: false,
+                    });
+                }
+                ' ' | '\t' | '\\' | '`' | 'y' | '(' | ')' | '~' | '.' | 'H'
+                | 'L' | 'M' | 'O' | 'P' | 'S' | 'T' | 'u' | 'v' => {
+                    cursor.next();
+                }
+                _ => return Err(self.error("Unexpected character")),
+            }
+        }
+        Ok(())
+    }
+
+    fn skip_past(&self, cursor: &mut Cursor, close: char) -> Result<()> {
+        if cursor.skip_past(close) {
+            Ok(())
+        } else {
+            Err(self.error("Unterminated text"))
+        }
+    }
+
+    fn push(&mut self, mut element: Element) {
+        if let Some((factor, remaining)) = self.tuplet {
+            element.scale(factor);
+            self.tuplet = match remaining {
+                1 => None,
+                _ => Some((factor, remaining - 1)),
+            };
+        }
+        if let Some(factor) = self.broken.take() {
+            element.scale(factor);
+        }
+        self.elements.push(element);
+    }
+
+    // Reads a note with its accidental, octave marks and length, applying
+    // the key signature and any accidental earlier in the bar.
+    fn note(&mut self, cursor: &mut Cursor) -> Result<(Note, f64)> {
+        let explicit = match cursor.peek(0) {
+            Some('^') if cursor.peek(1) == Some('^') => {
+                Some(Accidental::DoubleSharp)
+            }
+            Some('_') if cursor.peek(1) == Some('_') => {
+                Some(Accidental::DoubleFlat)
+            }
+            Some('^') => Some(Accidental::Sharp),
+            Some('_') => Some(Accidental::Flat),
+            Some('=') => Some(Accidental::Natural),
+            _ => None,
+        };
+        if let Some(accidental) = explicit {
+            let width = match accidental {
+                Accidental::DoubleSharp | Accidental::DoubleFlat => 2,
+                _ => 1,
+            };
+            for _ in 0..width {
+                cursor.next();
+            }
+        }
+
+        let c = cursor.next().ok_or_else(|| self.error("Expected a note"))?;
+        let letter = letter(c).ok_or_else(|| self.error("Expected a note"))?;
+        let mut octave = if c.is_ascii_lowercase() { 5 } else { 4 };
+        while let Some(mark) = cursor.peek(0) {
+            match mark {
+                '\'' => octave += 1,
+                ',' => octave -= 1,
+                _ => break,
+            }
+            cursor.next();
+        }
+        let octave = Octave::try_from(octave)
+            .map_err(|_| self.error("Octave out of range"))?;
+
+        let accidental = match explicit {
+            Some(accidental) => {
+                self.bar_accidentals
+                    .retain(|(l, o, _)| *l != letter || *o != octave);
+                self.bar_accidentals.push((letter, octave, accidental));
+                accidental
+            }
+            None => self
+                .bar_accidentals
+                .iter()
+                .find(|(l, o, _)| *l == letter && *o == octave)
+                .map_or_else(|| self.key.accidental(letter), |(_, _, a)| *a),
+        };
+
+        let length = self.unit() * cursor.multiplier();
+        Ok((Note::new(letter, accidental, octave), length))
+    }
+
+    // A chord lasts as long as its first note, scaled by any length after
+    // the closing bracket.
+    fn chord(&mut self, cursor: &mut Cursor) -> Result<()> {
+        cursor.next();
+        let mut notes = Vec::new();
+        let mut length = None;
+        let mut tied = false;
+        loop {
+            match cursor.peek(0) {
+                Some(']') => {
+                    cursor.next();
+                    break;
+                }
+                Some('-') => {
+                    cursor.next();
+                    tied = true;
+                }
+                Some(' ') => {
+                    cursor.next();
+                }
+                Some(_) => {
+                    let (note, note_length) = self.note(cursor)?;
+                    notes.push(note);
+                    length.get_or_insert(note_length);
+                }
+                None => return Err(self.error("Unterminated chord")),
+            }
+        }
+
+        let length = length.ok_or_else(|| self.error("Empty chord"))?;
+        self.push(Element::Notes {
+            notes,
+            length: length * cursor.multiplier(),
+            tied,
+        });
+        Ok(())
+    }
+
+    // Written "(p:q:r": p notes in the time of q for the next r notes.
+    fn tuplet(&mut self, cursor: &mut Cursor) {
+        cursor.next();
+        let p = cursor.number().unwrap_or(3).max(1);
+        let mut q = None;
+        let mut r = None;
+        if cursor.peek(0) == Some(':') {
+            cursor.next();
+            q = cursor.number();
+            if cursor.peek(0) == Some(':') {
+                cursor.next();
+                r = cursor.number();
+            }
+        }
+
+        let q = q.unwrap_or(match p {
+            2 | 4 | 8 => 3,
+            3 | 6 => 2,
+            _ if self.meter.is_compound() => 3,
+            _ => 2,
+        });
+        self.tuplet = Some((q as f64 / p as f64, r.unwrap_or(p).max(1)));
+    }
+
+    // "A>B" dots the first note and halves the second, and "A<B" the
+    // reverse. Doubled signs double dot.
+    fn broken(&mut self, cursor: &mut Cursor) {
+        let sign = cursor.next();
+        let mut count = 1;
+        while cursor.peek(0) == sign {
+            cursor.next();
+            count += 1;
+        }
+
+        let short = 0.5f64.powi(count);
+        let long = 2.0 - short;
+        let (previous, next) = match sign {
+            Some('>') => (long, short),
+            _ => (short, long),
+        };
+        if let Some(element) = self.elements.last_mut() {
+            element.scale(previous);
+        }
+        self.broken = Some(next);
+    }
+
+    fn bar(&mut self, cursor: &mut Cursor, mut token: String) {
+        while let Some(c) = cursor.peek(0) {
+            match c {
+                '|' | ':' => token.push(c),
+                ']' if token.ends_with('|') => token.push(c),
+                _ => break,
+            }
+            cursor.next();
+        }
+
+        self.bar_accidentals.clear();
+        let end_repeat = token.starts_with(':');
+        let start_repeat = token.ends_with(':');
+        if end_repeat {
+            let end = self.ending_start.take().unwrap_or(self.elements.len());
+            let repeated = self.elements[self.repeat_start..end].to_vec();
+            self.elements.extend(repeated);
+            self.repeat_start = self.elements.len();
+        }
+        if start_repeat || matches!(token.as_str(), "||" | "|]" | "[|") {
+            self.repeat_start = self.elements.len();
+            self.ending_start = None;
+        }
+
+        match cursor.peek(0) {
+            Some(c) if c.is_ascii_digit() => self.ending(cursor),
+            Some('[') if cursor.peek(1).is_some_and(|c| c.is_ascii_digit()) => {
+                cursor.next();
+                self.ending(cursor);
+            }
+            _ => (),
+        }
+    }
+
+    // Only the first ending needs marking: it is left out when the repeat
+    // is played.
+    fn ending(&mut self, cursor: &mut Cursor) {
+        if cursor.number() == Some(1) {
+            self.ending_start = Some(self.elements.len());
+        }
+        while let Some(c) = cursor.peek(0) {
+            if c != ',' && c != '-' && !c.is_ascii_digit() {
+                break;
+            }
+            cursor.next();
+        }
+    }
+
+    fn into_sequence(self) -> Result<Sequence> {
+        if self.state == State::Preamble {
+            return Err(self.error("Missing tune"));
+        }
+
+        let name = self.title.as_deref().unwrap_or("Tune");
+        let mut track = Track::new(name);
+        let mut tempo = self
+            .tempo
+            .map_or_else(|| Ok(Tempo::default()), Tempo::new)?;
+        let mut position = 0.0;
+        let mut open = Vec::<(Note, f64, f64)>::new();
+
+        let flush = |track: &mut Track, notes: Vec<(Note, f64, f64)>| {
+            for (note, start, length) in notes {
+                track.note(start, length, note, DEFAULT_VELOCITY);
+            }
+        };
+
+        for element in self.elements {
+            match element {
+                Element::Tempo(qpm) => tempo = tempo.set(position, qpm)?,
+                Element::Rest { length } => {
+                    flush(&mut track, mem::take(&mut open));
+                    track.rest(position, length);
+                    position += length;
+                }
+                Element::Notes {
+                    notes,
+                    length,
+                    tied,
+                } => {
+                    let mut sounding = Vec::new();
+                    for note in notes {
+                        match open.iter().position(|(n, _, _)| *n == note) {
+                            Some(idx) => {
+                                let (note, start, held) = open.remove(idx);
+                                sounding.push((note, start, held + length));
+                            }
+                            None => sounding.push((note, position, length)),
+                        }
+                    }
+                    flush(&mut track, mem::take(&mut open));
+                    if tied {
+                        open = sounding;
+                    } else {
+                        flush(&mut track, sounding);
+                    }
+                    position += length;
+                }
+            }
+        }
+        flush(&mut track, open);
+
+        let mut sequence = Sequence::new(tempo, self.meter);
+        sequence.add_track(track);
+        Ok(sequence)
+    }
+}
+
+// Splits "K: G" into the field name and value.
+fn field(line: &str) -> Option<(char, &str)> {
+    let mut chars = line.chars();
+    match (chars.next(), chars.next()) {
+        (Some(name), Some(':')) if name.is_ascii_alphabetic() => {
+            Some((name, line[2..].trim()))
+        }
+        _ => None,
+    }
+}
+
+fn letter(c: char) -> Option<Letter> {
+    match c.to_ascii_uppercase() {
+        'C' => Some(Letter::C),
+        'D' => Some(Letter::D),
+        'E' => Some(Letter::E),
+        'F' => Some(Letter::F),
+        'G' => Some(Letter::G),
+        'A' => Some(Letter::A),
+        'B' => Some(Letter::B),
+        _ => None,
+    }
+}
+
+struct Cursor {
+    chars: Vec<char>,
+    idx: usize,
+}
+
+impl Cursor {
+    fn new(line: &str) -> Cursor {
+        Cursor {
+            chars: line.chars().collect(),
+            idx: 0,
+        }
+    }
+
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.idx + offset).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek(0);
+        if c.is_some() {
+            self.idx += 1;
+        }
+        c
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        let mut number = None;
+        while let Some(digit) = self.peek(0).and_then(|c| c.to_digit(10)) {
+            number = Some(number.unwrap_or(0) * 10 + digit);
+            self.next();
+        }
+        number
+    }
+
+    // Length multiplier such as "2", "3/2", "/" or "//".
+    fn multiplier(&mut self) -> f64 {
+        let numerator = self.number().unwrap_or(1);
+        let mut denominator = 1;
+        while self.peek(0) == Some('/') {
+            self.next();
+            denominator *= self.number().unwrap_or(2);
+        }
+        numerator as f64 / denominator.max(1) as f64
+    }
+
+    // Skips the opening character and everything up to and including
+    // `close`, returning false if the line ends first.
+    fn skip_past(&mut self, close: char) -> bool {
+        self.next();
+        while let Some(c) = self.next() {
+            if c == close {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(symbol: &str) -> Note {
+        Note::parse(symbol).unwrap()
+    }
+
+    fn notes(sequence: &Sequence) -> Vec<(String, f64, f64)> {
+        sequence.tracks()[0]
+            .notes()
+            .iter()
+            .map(|n| (n.note().to_string(), n.start(), n.length()))
+            .collect()
+    }
+
+    fn pitches(sequence: &Sequence) -> Vec<String> {
+        notes(sequence)
+            .into_iter()
+            .map(|(note, _, _)| note)
+            .collect()
+    }
+
+    #[test]
+    fn header_ok() -> Result<()> {
+        let sequence = Sequence::parse_abc(
+            "X:1\nT:Test Tune\nT:Subtitle\nM:6/8\nL:1/8\nQ:3/8=60\nK:G\nGAB f2d|\n",
+        )?;
+        assert_eq!(sequence.tracks()[0].name(), "Test Tune");
+        assert_eq!(sequence.time_signature(), TimeSignature::new(6, 8)?);
+        assert_eq!(sequence.tempo().quarters_per_minute(0.0), 90.0);
+        assert_eq!(pitches(&sequence), ["G4", "A4", "B4", "F#5", "D5"]);
+        assert_eq!(notes(&sequence)[3], ("F#5".to_string(), 1.5, 1.0));
+        Ok(())
+    }
+    #[test]
+    fn default_unit_ok() -> Result<()> {
+        let sequence = Sequence::parse_abc("X:1\nM:2/4\nK:C\nC D|\n")?;
+        assert_eq!(notes(&sequence)[1].1, 0.25);
+        let sequence = Sequence::parse_abc("X:1\nM:C\nK:Ador\nC D|\n")?;
+        assert_eq!(notes(&sequence)[1].1, 0.5);
+        Ok(())
+    }
+    #[test]
+    fn accidentals_and_octaves_ok() -> Result<()> {
+        let sequence =
+            Sequence::parse_abc("X:1\nK:D\n^G G =G _B, c' __e ^^f z F|F f|\n")?;
+        assert_eq!(
+            pitches(&sequence),
+            [
+                "G#4", "G#4", "G4", "Bb3", "C#6", "Ebb5", "F##5", "F#4", "F#4",
+                "F#5"
+            ]
+        );
+        Ok(())
+    }
+    #[test]
+    fn bar_accidentals_reset_ok() -> Result<()> {
+        let sequence = Sequence::parse_abc("X:1\nK:C\n^F F f|F|\n")?;
+        assert_eq!(pitches(&sequence), ["F#4", "F#4", "F5", "F4"]);
+        Ok(())
+    }
+    #[test]
+    fn lengths_ok() -> Result<()> {
+        let sequence =
+            Sequence::parse_abc("X:1\nL:1/4\nK:C\nA2 A/2 A/ A// A3/2 z3 A|\n")?;
+        let lengths: Vec<f64> = notes(&sequence)
+            .iter()
+            .map(|(_, _, length)| *length)
+            .collect();
+        assert_eq!(lengths, [2.0, 0.5, 0.5, 0.25, 1.5, 1.0]);
+        assert_eq!(notes(&sequence)[5].1, 7.75);
+        Ok(())
+    }
+    #[test]
+    fn repeats_and_endings_ok() -> Result<()> {
+        let sequence =
+            Sequence::parse_abc("X:1\nK:C\n|: C D |1 E :|2 F |]\n|:G:|A|\n")?;
+        assert_eq!(
+            pitches(&sequence),
+            ["C4", "D4", "E4", "C4", "D4", "F4", "G4", "G4", "A4"]
+        );
+        let sequence = Sequence::parse_abc("X:1\nK:C\nC D [1 E :|[2 F||\n")?;
+        assert_eq!(pitches(&sequence), ["C4", "D4", "E4", "C4", "D4", "F4"]);
+        Ok(())
+    }
+    #[test]
+    fn tuplets_chords_and_ties_ok() -> Result<()> {
+        let sequence = Sequence::parse_abc(
+            "X:1\nL:1/8\nK:C\n(3CDE [CEG]2 A>B c2-c [C-E]2[CG]|\n",
+        )?;
+        let notes = notes(&sequence);
+        assert!((notes[1].1 - 1.0 / 3.0).abs() < 1e-9);
+        assert!((notes[2].2 - 1.0 / 3.0).abs() < 1e-9);
+        let chord: Vec<&str> = notes[3..6]
+            .iter()
+            .map(|(note, _, _)| note.as_str())
+            .collect();
+        assert_eq!(chord, ["C4", "E4", "G4"]);
+        assert!((notes[3].1 - 1.0).abs() < 1e-9);
+        assert_eq!(notes[6].2, 0.75);
+        assert_eq!(notes[7].2, 0.25);
+        assert_eq!(notes[8], ("C5".to_string(), 3.0, 1.5));
+        assert_eq!(notes[9], ("E4".to_string(), 4.5, 1.0));
+        assert_eq!(notes[10], ("C4".to_string(), 4.5, 1.5));
+        assert_eq!(notes[11], ("G4".to_string(), 5.5, 0.5));
+        Ok(())
+    }
+    #[test]
+    fn inline_fields_and_decorations_ok() -> Result<()> {
+        let sequence = Sequence::parse_abc(
+            "X:1\nK:C\n\"Am\"!trill!~A {g}B [K:G] F |\\\n% comment\nQ:1/4=60\n[L:1/4]F|\n",
+        )?;
+        assert_eq!(pitches(&sequence), ["A4", "B4", "F#4", "F#4"]);
+        assert_eq!(notes(&sequence)[3].2, 1.0);
+        assert_eq!(sequence.tempo().quarters_per_minute(1.5), 60.0);
+        Ok(())
+    }
+    #[test]
+    fn first_tune_only() -> Result<()> {
+        let sequence =
+            Sequence::parse_abc("%abc\n\nX:1\nK:C\nC|\n\nX:2\nK:C\nD|\n")?;
+        assert_eq!(pitches(&sequence), ["C4"]);
+        Ok(())
+    }
+    #[test]
+    fn parse_fail() {
+        let line = |text| Sequence::parse_abc(text).unwrap_err().kind();
+        assert_eq!(line("X:1\nK:C\nA ? B|\n"), Kind::Line(3));
+        assert_eq!(line("X:1\nM:7/6\nK:C\n"), Kind::Line(2));
+        assert_eq!(line("X:1\nK:Hx\n"), Kind::Line(2));
+        assert_eq!(line("X:1\nK:C\n[CE\n"), Kind::Line(3));
+        assert_eq!(line("X:1\nK:C\nc''''''|\n"), Kind::Line(3));
+        assert_eq!(line("X:1\nK:C\n\"Am C|\n"), Kind::Line(3));
+        assert_eq!(line("no tune here\n"), Kind::Line(1));
+    }
+    #[test]
+    fn open_sample_ok() -> Result<()> {
+        let sequence = Sequence::open_abc("data/abc/reel.abc")?;
+        assert_eq!(sequence.tracks()[0].name(), "The Zinnia Reel");
+        let notes = sequence.tracks()[0].notes();
+        assert_eq!(notes[0].note(), note("D4"));
+        assert_eq!(notes.len(), 61);
+        Ok(())
+    }
+}
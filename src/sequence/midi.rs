@@ -0,0 +1,764 @@
+use super::{Event, Parameter, Sequence, Track};
+use crate::{
+    error::{Error, Kind},
+    music::{
+        rhythm::{Tempo, TimeSignature},
+        Note,
+    },
+    Result,
+};
+use std::{collections::BTreeMap, fs, path::Path};
+
+const HEADER_TAG: &[u8; 4] = b"MThd";
+const TRACK_TAG: &[u8; 4] = b"MTrk";
+const HEADER_LENGTH: u32 = 6;
+const MICROSECONDS_PER_MINUTE: f64 = 60_000_000.0;
+const MAX_DATA_VALUE: f32 = 127.0;
+const PAN_CENTRE: f32 = 64.0;
+
+const NOTE_OFF: u8 = 0x80;
+const NOTE_ON: u8 = 0x90;
+const POLY_PRESSURE: u8 = 0xA0;
+const CONTROL_CHANGE: u8 = 0xB0;
+const PROGRAM_CHANGE: u8 = 0xC0;
+const CHANNEL_PRESSURE: u8 = 0xD0;
+const PITCH_BEND: u8 = 0xE0;
+const SYSEX: u8 = 0xF0;
+const SYSEX_ESCAPE: u8 = 0xF7;
+const META: u8 = 0xFF;
+
+const META_TRACK_NAME: u8 = 0x03;
+const META_END_OF_TRACK: u8 = 0x2F;
+const META_TEMPO: u8 = 0x51;
+const META_TIME_SIGNATURE: u8 = 0x58;
+
+const CONTROL_VOLUME: u8 = 7;
+const CONTROL_PAN: u8 = 10;
+
+const DIVISION: u16 = 480;
+const RAMP_STEP: f64 = 0.25;
+const CLOCKS_PER_CLICK: u8 = 24;
+const THIRTY_SECONDS_PER_QUARTER: u8 = 8;
+// Channel 10 is reserved for percussion in General MIDI.
+const MELODIC_CHANNELS: [u8; 15] =
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 10, 11, 12, 13, 14, 15];
+
+impl Sequence {
+    pub fn open_midi<P: AsRef<Path>>(path: P) -> Result<Sequence> {
+        Sequence::parse_midi(&fs::read(path)?)
+    }
+
+    /// Reads a type 0 or type 1 Standard MIDI File. Every channel used in a
+    /// track becomes a track of its own, and tempo changes from any track
+    /// make up the tempo map. Error positions are byte offsets.
+    pub fn parse_midi(data: &[u8]) -> Result<Sequence> {
+        let mut reader = MidiReader::new(data);
+
+        reader.tag(HEADER_TAG)?;
+        if reader.u32()? != HEADER_LENGTH {
+            return Err(reader.error("Invalid header length", 4));
+        }
+        let format = reader.u16()?;
+        if format > 1 {
+            return Err(reader.error("Unsupported MIDI format", 2));
+        }
+        let track_count = reader.u16()?;
+        let division = reader.u16()?;
+        if division == 0 || division & 0x8000 != 0 {
+            return Err(reader.error("Unsupported time division", 2));
+        }
+
+        let mut file = MidiFile {
+            division: division as f64,
+            tracks: Vec::new(),
+            tempos: Vec::new(),
+            time_signature: None,
+        };
+
+        let mut read = 0;
+        while read < track_count && !reader.is_at_end() {
+            let tag = reader.bytes(4)?;
+            let length = reader.u32()? as usize;
+            if tag == TRACK_TAG {
+                file.read_track(&mut reader, length, read)?;
+                read += 1;
+            } else {
+                // Unknown chunks are skipped, as the format requires.
+                reader.bytes(length)?;
+            }
+        }
+        if read < track_count {
+            return Err(reader.error("Missing track", 0));
+        }
+
+        file.into_sequence()
+    }
+
+    pub fn save_midi<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, self.to_midi()?)?;
+        Ok(())
+    }
+
+    /// Writes a type 1 Standard MIDI File. The first track holds the time
+    /// signature and tempo map, with ramps written as a tempo change every
+    /// `RAMP_STEP` quarter notes, and each sequence track follows on its own
+    /// channel.
+    pub fn to_midi(&self) -> Result<Vec<u8>> {
+        let mut data = HEADER_TAG.to_vec();
+        write_u32(&mut data, HEADER_LENGTH);
+        write_u16(&mut data, 1);
+        write_u16(&mut data, self.tracks.len() as u16 + 1);
+        write_u16(&mut data, DIVISION);
+
+        write_chunk(&mut data, &self.conductor_track());
+        for (idx, track) in self.tracks.iter().enumerate() {
+            let channel = MELODIC_CHANNELS[idx % MELODIC_CHANNELS.len()];
+            write_chunk(&mut data, &track.to_midi(channel)?);
+        }
+        Ok(data)
+    }
+
+    fn conductor_track(&self) -> Vec<u8> {
+        let mut events = Vec::new();
+        let signature = self.time_signature;
+        events.push((
+            0,
+            meta_event(
+                META_TIME_SIGNATURE,
+                &[
+                    signature.beats() as u8,
+                    signature.unit().trailing_zeros() as u8,
+                    CLOCKS_PER_CLICK,
+                    THIRTY_SECONDS_PER_QUARTER,
+                ],
+            ),
+        ));
+
+        let mut previous = 0.0;
+        for (position, ramp) in self.tempo.changes() {
+            if ramp {
+                let mut step = previous;
+                while step + RAMP_STEP < position {
+                    let middle = step + RAMP_STEP / 2.0;
+                    events.push((
+                        midi_ticks(step),
+                        self.tempo_event(
+                            self.tempo.quarters_per_minute(middle),
+                        ),
+                    ));
+                    step += RAMP_STEP;
+                }
+            }
+            events.push((
+                midi_ticks(position),
+                self.tempo_event(self.tempo.quarters_per_minute(position)),
+            ));
+            previous = position;
+        }
+
+        track_data(events)
+    }
+
+    fn tempo_event(&self, quarters_per_minute: f64) -> Vec<u8> {
+        let micros =
+            (MICROSECONDS_PER_MINUTE / quarters_per_minute).round() as u32;
+        meta_event(META_TEMPO, &micros.to_be_bytes()[1..])
+    }
+}
+
+impl Track {
+    fn to_midi(&self, channel: u8) -> Result<Vec<u8>> {
+        let mut events =
+            vec![(0, meta_event(META_TRACK_NAME, self.name.as_bytes()))];
+        for timed in &self.events {
+            let bytes = match timed.event {
+                Event::NoteOn { note, velocity } => {
+                    vec![NOTE_ON | channel, note.midi_number()?, velocity]
+                }
+                Event::NoteOff { note } => {
+                    vec![NOTE_OFF | channel, note.midi_number()?, 0]
+                }
+                Event::Parameter(Parameter::Volume(volume)) => vec![
+                    CONTROL_CHANGE | channel,
+                    CONTROL_VOLUME,
+                    (volume.clamp(0.0, 1.0) * MAX_DATA_VALUE).round() as u8,
+                ],
+                Event::Parameter(Parameter::Pan(pan)) => vec![
+                    CONTROL_CHANGE | channel,
+                    CONTROL_PAN,
+                    (PAN_CENTRE
+                        + pan.clamp(-1.0, 1.0) * (MAX_DATA_VALUE - PAN_CENTRE))
+                        .round() as u8,
+                ],
+                Event::Rest { .. } => continue,
+            };
+            events.push((midi_ticks(timed.position), bytes));
+        }
+        Ok(track_data(events))
+    }
+}
+
+fn midi_ticks(quarters: f64) -> u32 {
+    (quarters * DIVISION as f64).round() as u32
+}
+
+fn meta_event(kind: u8, data: &[u8]) -> Vec<u8> {
+    let mut event = vec![META, kind];
+    write_vlq(&mut event, data.len() as u32);
+    event.extend_from_slice(data);
+    event
+}
+
+// Track chunk contents for events at absolute ticks, which must already be
+// in order, finished with an end of track event.
+fn track_data(events: Vec<(u32, Vec<u8>)>) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut last = 0;
+    for (tick, event) in events {
+        write_vlq(&mut data, tick - last);
+        data.extend(event);
+        last = tick;
+    }
+    write_vlq(&mut data, 0);
+    data.extend(meta_event(META_END_OF_TRACK, &[]));
+    data
+}
+
+fn write_chunk(data: &mut Vec<u8>, track: &[u8]) {
+    data.extend_from_slice(TRACK_TAG);
+    write_u32(data, track.len() as u32);
+    data.extend_from_slice(track);
+}
+
+fn write_u16(data: &mut Vec<u8>, value: u16) {
+    data.extend_from_slice(&value.to_be_bytes());
+}
+
+fn write_u32(data: &mut Vec<u8>, value: u32) {
+    data.extend_from_slice(&value.to_be_bytes());
+}
+
+fn write_vlq(data: &mut Vec<u8>, value: u32) {
+    let mut shift = 21;
+    while shift > 0 && value >> shift == 0 {
+        shift -= 7;
+    }
+    while shift > 0 {
+        data.push((value >> shift) as u8 & 0x7F | 0x80);
+        shift -= 7;
+    }
+    data.push(value as u8 & 0x7F);
+}
+
+struct MidiFile {
+    division: f64,
+    tracks: Vec<Track>,
+    tempos: Vec<(f64, f64)>,
+    time_signature: Option<TimeSignature>,
+}
+
+impl MidiFile {
+    fn read_track(
+        &mut self,
+        reader: &mut MidiReader,
+        length: usize,
+        index: u16,
+    ) -> Result<()> {
+        let end = reader.position() + length;
+        let mut tick: u64 = 0;
+        let mut running: Option<u8> = None;
+        let mut name = None;
+        let mut channels = BTreeMap::<u8, Track>::new();
+
+        while reader.position() < end {
+            tick += reader.vlq()? as u64;
+            let position = tick as f64 / self.division;
+
+            let status = match reader.peek()? {
+                byte if byte & 0x80 != 0 => {
+                    reader.byte()?;
+                    byte
+                }
+                _ => running
+                    .ok_or_else(|| reader.error("Missing status byte", 0))?,
+            };
+
+            match status {
+                META => {
+                    running = None;
+                    let kind = reader.byte()?;
+                    let length = reader.vlq()? as usize;
+                    let data = reader.bytes(length)?;
+                    match kind {
+                        META_END_OF_TRACK => break,
+                        META_TRACK_NAME => {
+                            name = Some(String::from_utf8_lossy(data).into())
+                        }
+                        META_TEMPO if length == 3 => {
+                            let micros = (data[0] as u32) << 16
+                                | (data[1] as u32) << 8
+                                | data[2] as u32;
+                            if micros == 0 {
+                                return Err(reader.error("Invalid tempo", 3));
+                            }
+                            self.tempos.push((
+                                position,
+                                MICROSECONDS_PER_MINUTE / micros as f64,
+                            ));
+                        }
+                        // The sequence has a single time signature, so the
+                        // first one wins.
+                        META_TIME_SIGNATURE
+                            if length >= 2 && self.time_signature.is_none() =>
+                        {
+                            self.time_signature = Some(TimeSignature::new(
+                                data[0] as u32,
+                                1u32.checked_shl(data[1] as u32).unwrap_or(0),
+                            )?);
+                        }
+                        _ => (),
+                    }
+                }
+                SYSEX | SYSEX_ESCAPE => {
+                    running = None;
+                    let length = reader.vlq()? as usize;
+                    reader.bytes(length)?;
+                }
+                _ => {
+                    running = Some(status);
+                    let channel = status & 0x0F;
+                    let event = channel_event(reader, status & 0xF0)?;
+                    if let Some(event) = event {
+                        channels
+                            .entry(channel)
+                            .or_insert_with(|| Track::new(""))
+                            .push(position, event);
+                    }
+                }
+            }
+        }
+
+        if reader.position() > end {
+            return Err(reader.error("Track overruns its length", 0));
+        }
+        reader.seek(end)?;
+
+        let name = name.unwrap_or_else(|| format!("Track {}", index + 1));
+        let split = channels.len() > 1;
+        for (channel, mut track) in channels {
+            track.name = if split {
+                format!("{} (channel {})", name, channel + 1)
+            } else {
+                name.clone()
+            };
+            self.tracks.push(track);
+        }
+        Ok(())
+    }
+
+    fn into_sequence(mut self) -> Result<Sequence> {
+        self.tempos.sort_by(|a, b| {
+            a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let tempo = self
+            .tempos
+            .iter()
+            .try_fold(Tempo::default(), |tempo, (position, bpm)| {
+                tempo.set(*position, *bpm)
+            })?;
+
+        let mut sequence =
+            Sequence::new(tempo, self.time_signature.unwrap_or_default());
+        for track in self.tracks {
+            sequence.add_track(track);
+        }
+        Ok(sequence)
+    }
+}
+
+// Reads the data bytes of a channel message with the status `kind`, keeping
+// only the events the sequence model can play.
+fn channel_event(reader: &mut MidiReader, kind: u8) -> Result<Option<Event>> {
+    let event = match kind {
+        NOTE_OFF => {
+            let note = midi_note(reader)?;
+            reader.data_byte()?;
+            Some(Event::NoteOff { note })
+        }
+        NOTE_ON => {
+            let note = midi_note(reader)?;
+            match reader.data_byte()? {
+                0 => Some(Event::NoteOff { note }),
+                velocity => Some(Event::NoteOn { note, velocity }),
+            }
+        }
+        CONTROL_CHANGE => {
+            let control = reader.data_byte()?;
+            let value = reader.data_byte()? as f32;
+            match control {
+                CONTROL_VOLUME => Some(Event::Parameter(Parameter::Volume(
+                    value / MAX_DATA_VALUE,
+                ))),
+                CONTROL_PAN => Some(Event::Parameter(Parameter::Pan(
+                    ((value - PAN_CENTRE) / (MAX_DATA_VALUE - PAN_CENTRE))
+                        .clamp(-1.0, 1.0),
+                ))),
+                _ => None,
+            }
+        }
+        POLY_PRESSURE | PITCH_BEND => {
+            reader.data_byte()?;
+            reader.data_byte()?;
+            None
+        }
+        PROGRAM_CHANGE | CHANNEL_PRESSURE => {
+            reader.data_byte()?;
+            None
+        }
+        _ => return Err(reader.error("Invalid status byte", 1)),
+    };
+    Ok(event)
+}
+
+fn midi_note(reader: &mut MidiReader) -> Result<Note> {
+    let number = reader.data_byte()?;
+    Note::from_midi(number)
+}
+
+struct MidiReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> MidiReader<'a> {
+    fn new(data: &'a [u8]) -> MidiReader<'a> {
+        MidiReader { data, position: 0 }
+    }
+
+    fn position(&self) -> usize {
+        self.position
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    // Error at the byte `back` bytes before the current position.
+    fn error(&self, msg: &'static str, back: usize) -> Error {
+        Error::new(msg, Kind::Parse(self.position.saturating_sub(back)))
+    }
+
+    fn seek(&mut self, position: usize) -> Result<()> {
+        if position > self.data.len() {
+            return Err(self.error("Unexpected end of file", 0));
+        }
+        self.position = position;
+        Ok(())
+    }
+
+    fn peek(&self) -> Result<u8> {
+        self.data
+            .get(self.position)
+            .copied()
+            .ok_or_else(|| self.error("Unexpected end of file", 0))
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        let byte = self.peek()?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn data_byte(&mut self) -> Result<u8> {
+        match self.byte()? {
+            byte if byte & 0x80 == 0 => Ok(byte),
+            _ => Err(self.error("Expected data byte", 1)),
+        }
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8]> {
+        match self.data.get(self.position..self.position + count) {
+            Some(bytes) => {
+                self.position += count;
+                Ok(bytes)
+            }
+            None => Err(self.error("Unexpected end of file", 0)),
+        }
+    }
+
+    fn tag(&mut self, tag: &[u8; 4]) -> Result<()> {
+        if self.bytes(4)? == tag {
+            Ok(())
+        } else {
+            Err(self.error("Unexpected chunk type", 4))
+        }
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.bytes(2)?;
+        Ok((bytes[0] as u16) << 8 | bytes[1] as u16)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.bytes(4)?;
+        Ok(bytes.iter().fold(0, |acc, b| acc << 8 | *b as u32))
+    }
+
+    // Variable-length quantity of at most four bytes, seven bits per byte
+    // with the high bit set on all but the last.
+    fn vlq(&mut self) -> Result<u32> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let byte = self.byte()?;
+            value = value << 7 | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(self.error("Variable-length quantity too long", 4))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(symbol: &str) -> Note {
+        Note::parse(symbol).unwrap()
+    }
+
+    fn header(format: u16, tracks: u16, division: u16) -> Vec<u8> {
+        let mut data = HEADER_TAG.to_vec();
+        data.extend_from_slice(&HEADER_LENGTH.to_be_bytes());
+        data.extend_from_slice(&format.to_be_bytes());
+        data.extend_from_slice(&tracks.to_be_bytes());
+        data.extend_from_slice(&division.to_be_bytes());
+        data
+    }
+
+    fn track(events: &[u8]) -> Vec<u8> {
+        let mut data = TRACK_TAG.to_vec();
+        data.extend_from_slice(&(events.len() as u32).to_be_bytes());
+        data.extend_from_slice(events);
+        data
+    }
+
+    #[test]
+    fn vlq_ok() -> Result<()> {
+        let data = [0x00, 0x7F, 0x81, 0x00, 0xFF, 0x7F, 0x81, 0x80, 0x00];
+        let mut reader = MidiReader::new(&data);
+        assert_eq!(reader.vlq()?, 0);
+        assert_eq!(reader.vlq()?, 0x7F);
+        assert_eq!(reader.vlq()?, 0x80);
+        assert_eq!(reader.vlq()?, 0x3FFF);
+        assert_eq!(reader.vlq()?, 0x4000);
+        assert!(MidiReader::new(&[0x81, 0x81, 0x81, 0x81, 0x01])
+            .vlq()
+            .is_err());
+        Ok(())
+    }
+    #[test]
+    fn type_0_running_status_ok() -> Result<()> {
+        let mut data = header(0, 1, 96);
+        data.extend(track(&[
+            0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, // 120 bpm
+            0x00, 0x90, 60, 100, // C4 on
+            0x60, 64, 90, // E4 on, running status
+            0x00, 60, 0, // C4 off as velocity 0
+            0x60, 0x80, 64, 0, // E4 off
+            0x00, 0xFF, 0x2F, 0x00,
+        ]));
+
+        let sequence = Sequence::parse_midi(&data)?;
+        assert_eq!(sequence.tracks().len(), 1);
+        assert_eq!(sequence.tracks()[0].name(), "Track 1");
+        assert!((sequence.tempo().bpm(0.0) - 120.0).abs() < 1e-6);
+
+        let notes = sequence.tracks()[0].notes();
+        assert_eq!(notes.len(), 2);
+        assert_eq!((notes[0].note(), notes[0].start()), (note("C4"), 0.0));
+        assert_eq!(notes[0].length(), 1.0);
+        assert_eq!((notes[1].note(), notes[1].velocity()), (note("E4"), 90));
+        assert_eq!(notes[1].length(), 1.0);
+        Ok(())
+    }
+    #[test]
+    fn type_1_tempo_map_ok() -> Result<()> {
+        let mut data = header(1, 2, 480);
+        data.extend(track(&[
+            0x00, 0xFF, 0x58, 0x04, 0x03, 0x02, 0x18, 0x08, // 3/4
+            0x00, 0xFF, 0x51, 0x03, 0x0F, 0x42, 0x40, // 60 bpm
+            0x83, 0x60, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, // 120 bpm
+            0x00, 0xFF, 0x2F, 0x00,
+        ]));
+        data.extend(track(&[
+            0x00,
+            0xFF,
+            0x03,
+            0x04,
+            b'L',
+            b'e',
+            b'a',
+            b'd',
+            0x00,
+            0xB1,
+            CONTROL_PAN,
+            127,
+            0x00,
+            0x91,
+            69,
+            127,
+            0x87,
+            0x40,
+            0x81,
+            69,
+            64, // after 960 ticks
+            0x00,
+            0xF0,
+            0x02,
+            0x7E,
+            0xF7, // sysex cancels running status
+            0x00,
+            0xFF,
+            0x2F,
+            0x00,
+        ]));
+
+        let sequence = Sequence::parse_midi(&data)?;
+        assert_eq!(sequence.time_signature(), TimeSignature::new(3, 4)?);
+        assert_eq!(sequence.tempo().bpm(0.5), 60.0);
+        assert_eq!(sequence.tempo().bpm(1.0), 120.0);
+        assert!((sequence.tempo().seconds(2.0) - 1.5).abs() < 1e-9);
+
+        assert_eq!(sequence.tracks().len(), 1);
+        let track = &sequence.tracks()[0];
+        assert_eq!(track.name(), "Lead");
+        let notes = track.notes();
+        assert_eq!(notes[0].note(), note("A4"));
+        assert_eq!(notes[0].length(), 2.0);
+        assert_eq!(notes[0].pan(), 1.0);
+        Ok(())
+    }
+    #[test]
+    fn channels_split_ok() -> Result<()> {
+        let mut data = header(0, 1, 1);
+        data.extend(track(&[
+            0x00, 0x90, 60, 100, 0x00, 0x99, 36, 100, 0x01, 0x80, 60, 0, 0x00,
+            0x89, 36, 0,
+        ]));
+
+        let sequence = Sequence::parse_midi(&data)?;
+        let names: Vec<&str> =
+            sequence.tracks().iter().map(|t| t.name()).collect();
+        assert_eq!(names, ["Track 1 (channel 1)", "Track 1 (channel 10)"]);
+        Ok(())
+    }
+    #[test]
+    fn parse_fail() {
+        let position =
+            |data: &[u8]| Sequence::parse_midi(data).unwrap_err().kind();
+
+        let mut data = header(2, 1, 96);
+        assert_eq!(position(&data), Kind::Parse(8));
+
+        data = header(0, 1, 96);
+        data.extend(track(&[0x00, 60, 100]));
+        assert_eq!(position(&data), Kind::Parse(23));
+
+        data = header(0, 2, 96);
+        data.extend(track(&[0x00, 0xFF, 0x2F, 0x00]));
+        assert!(Sequence::parse_midi(&data).is_err());
+        assert!(Sequence::parse_midi(b"MThx").is_err());
+    }
+    #[test]
+    fn write_vlq_ok() -> Result<()> {
+        let cases: [(u32, &[u8]); 7] = [
+            (0, &[0x00]),
+            (0x7F, &[0x7F]),
+            (0x80, &[0x81, 0x00]),
+            (0x2000, &[0xC0, 0x00]),
+            (0x3FFF, &[0xFF, 0x7F]),
+            (0x4000, &[0x81, 0x80, 0x00]),
+            (0x0FFF_FFFF, &[0xFF, 0xFF, 0xFF, 0x7F]),
+        ];
+        for (value, bytes) in cases.iter() {
+            let mut data = Vec::new();
+            write_vlq(&mut data, *value);
+            assert_eq!(data, *bytes);
+            assert_eq!(MidiReader::new(&data).vlq()?, *value);
+        }
+        Ok(())
+    }
+    #[test]
+    fn to_midi_byte_exact() -> Result<()> {
+        let mut track = Track::new("A");
+        track.note(0.0, 1.0, note("C4"), 100);
+        let mut sequence =
+            Sequence::new(Tempo::new(120.0)?, Default::default());
+        sequence.add_track(track);
+
+        #[rustfmt::skip]
+        let expected: &[u8] = &[
+            b'M', b'T', b'h', b'd', 0x00, 0x00, 0x00, 0x06,
+            0x00, 0x01, 0x00, 0x02, 0x01, 0xE0,
+            b'M', b'T', b'r', b'k', 0x00, 0x00, 0x00, 0x13,
+            0x00, 0xFF, 0x58, 0x04, 0x04, 0x02, 0x18, 0x08,
+            0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20,
+            0x00, 0xFF, 0x2F, 0x00,
+            b'M', b'T', b'r', b'k', 0x00, 0x00, 0x00, 0x12,
+            0x00, 0xFF, 0x03, 0x01, b'A',
+            0x00, 0x90, 0x3C, 0x64,
+            0x83, 0x60, 0x80, 0x3C, 0x00,
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        assert_eq!(sequence.to_midi()?, expected);
+        Ok(())
+    }
+    #[test]
+    fn write_read_round_trip_ok() -> Result<()> {
+        let tempo = Tempo::new(90.0)?.ramp(4.0, 8.0, 150.0)?;
+        let mut sequence = Sequence::new(tempo, TimeSignature::new(6, 8)?);
+        for (idx, name) in ["Melody", "Bass"].iter().enumerate() {
+            let mut track = Track::new(name);
+            track.parameter(0.0, Parameter::Volume(0.5));
+            track.parameter(0.0, Parameter::Pan(-1.0));
+            for step in 0..12 {
+                let symbol = if idx == 0 { "E5" } else { "Bb2" };
+                track.note(step as f64 * 0.75, 0.5, note(symbol), 60 + step);
+            }
+            sequence.add_track(track);
+        }
+
+        let read = Sequence::parse_midi(&sequence.to_midi()?)?;
+        assert_eq!(read.time_signature(), sequence.time_signature());
+        assert_eq!(read.tracks().len(), 2);
+        for (original, read) in sequence.tracks().iter().zip(read.tracks()) {
+            assert_eq!(read.name(), original.name());
+            let expected = original.notes();
+            let notes = read.notes();
+            assert_eq!(notes.len(), expected.len());
+            for (a, b) in notes.iter().zip(&expected) {
+                assert_eq!(a.start(), b.start());
+                assert_eq!(a.length(), b.length());
+                assert_eq!(a.velocity(), b.velocity());
+                assert_eq!(a.note().midi_number()?, b.note().midi_number()?);
+                assert!((a.volume() - 0.5).abs() < 0.01);
+                assert_eq!(a.pan(), -1.0);
+            }
+        }
+
+        let seconds = sequence.tempo().seconds(12.0);
+        assert!((read.tempo().seconds(12.0) - seconds).abs() < 0.01);
+        Ok(())
+    }
+    #[test]
+    fn open_sample_ok() -> Result<()> {
+        let sequence = Sequence::open_midi("data/midi/c_major_scale.mid")?;
+        let notes = sequence.tracks()[0].notes();
+        assert_eq!(notes.len(), 8);
+        assert_eq!(notes[0].note(), note("C4"));
+        assert_eq!(notes[7].note(), note("C5"));
+        assert_eq!(notes[7].start(), 7.0);
+        Ok(())
+    }
+}
@@ -0,0 +1,694 @@
+use super::{Sequence, Track};
+use crate::{
+    error::{Error, Kind},
+    music::{
+        rhythm::{Tempo, TimeSignature},
+        Accidental, Letter, Note, Octave,
+    },
+    Result,
+};
+use std::{collections::BTreeMap, convert::TryFrom, fs, path::Path};
+
+// MusicXML takes forte, the default dynamic, as MIDI velocity 90.
+const DEFAULT_VELOCITY: u8 = 90;
+const DEFAULT_VOICE: &str = "1";
+
+impl Sequence {
+    pub fn open_musicxml<P: AsRef<Path>>(path: P) -> Result<Sequence> {
+        Sequence::parse_musicxml(&fs::read_to_string(path)?)
+    }
+
+    /// Reads an uncompressed partwise MusicXML score. Each voice of each
+    /// part becomes a track, tied notes are joined, and tempo marks from any
+    /// part make up the tempo map. Errors are of kind `Kind::Line`, or
+    /// `Kind::Zinnia` for documents that are well formed but not usable.
+    pub fn parse_musicxml(text: &str) -> Result<Sequence> {
+        let root = XmlParser::new(text).document()?;
+        if root.name != "score-partwise" {
+            return Err(Error::new("Expected a partwise score", Kind::Zinnia));
+        }
+
+        let names: BTreeMap<&str, &str> = root
+            .child("part-list")
+            .map(|list| {
+                list.children("score-part")
+                    .filter_map(|part| {
+                        Some((
+                            part.attribute("id")?,
+                            part.child_text("part-name").unwrap_or(""),
+                        ))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut score = Score {
+            tracks: Vec::new(),
+            tempos: Vec::new(),
+            time_signature: None,
+        };
+        for (idx, part) in root.children("part").enumerate() {
+            let name = part
+                .attribute("id")
+                .and_then(|id| names.get(id))
+                .filter(|name| !name.is_empty())
+                .map_or_else(|| format!("Part {}", idx + 1), |n| n.to_string());
+            score.read_part(part, &name)?;
+        }
+
+        let tempo = score
+            .tempos
+            .iter()
+            .try_fold(Tempo::default(), |tempo, (position, qpm)| {
+                tempo.set(*position, *qpm)
+            })?;
+        let mut sequence =
+            Sequence::new(tempo, score.time_signature.unwrap_or_default());
+        for track in score.tracks {
+            sequence.add_track(track);
+        }
+        Ok(sequence)
+    }
+}
+
+struct Score {
+    tracks: Vec<Track>,
+    tempos: Vec<(f64, f64)>,
+    time_signature: Option<TimeSignature>,
+}
+
+// A voice within a part, with the tied notes still waiting for their end.
+struct Voice {
+    track: Track,
+    ties: Vec<(Note, f64, f64)>,
+}
+
+impl Score {
+    fn read_part(&mut self, part: &XmlElement, name: &str) -> Result<()> {
+        let mut voices = BTreeMap::<String, Voice>::new();
+        let mut divisions = 1.0;
+        let mut position = 0.0;
+        let mut chord_start = 0.0;
+
+        for measure in part.children("measure") {
+            for element in &measure.children {
+                match element.name.as_str() {
+                    "attributes" => {
+                        if let Some(value) = element.child_text("divisions") {
+                            divisions = positive(value, "Invalid divisions")?;
+                        }
+                        if let Some(time) = element.child("time") {
+                            self.time(time)?;
+                        }
+                    }
+                    "backup" => position -= duration(element, divisions)?,
+                    "forward" => position += duration(element, divisions)?,
+                    "sound" => self.sound(element, position)?,
+                    "direction" => {
+                        if let Some(sound) = element.child("sound") {
+                            self.sound(sound, position)?;
+                        }
+                    }
+                    "note" => {
+                        if element.child("grace").is_some()
+                            || element.child("cue").is_some()
+                        {
+                            continue;
+                        }
+                        let length = duration(element, divisions)?;
+                        let start = if element.child("chord").is_some() {
+                            chord_start
+                        } else {
+                            chord_start = position;
+                            position += length;
+                            chord_start
+                        };
+
+                        let voice = voices
+                            .entry(
+                                element
+                                    .child_text("voice")
+                                    .unwrap_or(DEFAULT_VOICE)
+                                    .to_string(),
+                            )
+                            .or_insert_with(|| Voice {
+                                track: Track::new(name),
+                                ties: Vec::new(),
+                            });
+                        voice.note(element, start, length)?;
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        let split = voices.len() > 1;
+        for (number, mut voice) in voices {
+            for (note, start, length) in voice.ties.drain(..) {
+                voice.track.note(start, length, note, DEFAULT_VELOCITY);
+            }
+            if split {
+                voice.track.name = format!("{} (voice {})", name, number);
+            }
+            self.tracks.push(voice.track);
+        }
+        Ok(())
+    }
+
+    fn time(&mut self, time: &XmlElement) -> Result<()> {
+        if self.time_signature.is_some() {
+            return Ok(());
+        }
+        if let (Some(beats), Some(unit)) =
+            (time.child_text("beats"), time.child_text("beat-type"))
+        {
+            let parse = |value: &str| {
+                value.parse::<u32>().map_err(|_| {
+                    Error::new("Invalid time signature", Kind::Zinnia)
+                })
+            };
+            self.time_signature =
+                Some(TimeSignature::new(parse(beats)?, parse(unit)?)?);
+        }
+        Ok(())
+    }
+
+    fn sound(&mut self, sound: &XmlElement, position: f64) -> Result<()> {
+        if let Some(tempo) = sound.attribute("tempo") {
+            self.tempos
+                .push((position, positive(tempo, "Invalid tempo")?));
+        }
+        Ok(())
+    }
+}
+
+impl Voice {
+    fn note(
+        &mut self,
+        element: &XmlElement,
+        start: f64,
+        length: f64,
+    ) -> Result<()> {
+        let pitch = match element.child("pitch") {
+            Some(pitch) => pitch,
+            None => {
+                if element.child("rest").is_some() {
+                    self.track.rest(start, length);
+                }
+                return Ok(());
+            }
+        };
+        let note = pitch_note(pitch)?;
+
+        let ties: Vec<&str> = element
+            .children("tie")
+            .filter_map(|tie| tie.attribute("type"))
+            .collect();
+        let (start, length) = match self.ties.iter().position(|t| t.0 == note) {
+            Some(idx) if ties.contains(&"stop") => {
+                let (_, tied_start, tied_length) = self.ties.remove(idx);
+                (tied_start, tied_length + length)
+            }
+            _ => (start, length),
+        };
+
+        if ties.contains(&"start") {
+            self.ties.push((note, start, length));
+        } else {
+            self.track.note(start, length, note, DEFAULT_VELOCITY);
+        }
+        Ok(())
+    }
+}
+
+fn pitch_note(pitch: &XmlElement) -> Result<Note> {
+    let invalid = || Error::new("Invalid pitch", Kind::Zinnia);
+    let letter = match pitch.child_text("step").ok_or_else(invalid)? {
+        "C" => Letter::C,
+        "D" => Letter::D,
+        "E" => Letter::E,
+        "F" => Letter::F,
+        "G" => Letter::G,
+        "A" => Letter::A,
+        "B" => Letter::B,
+        _ => return Err(invalid()),
+    };
+
+    // Microtonal alterations are rounded to the nearest semitone.
+    let alter = match pitch.child_text("alter") {
+        Some(alter) => alter.parse::<f32>().map_err(|_| invalid())?.round(),
+        None => 0.0,
+    };
+    let accidental = match alter as i32 {
+        -2 => Accidental::DoubleFlat,
+        -1 => Accidental::Flat,
+        0 => Accidental::Natural,
+        1 => Accidental::Sharp,
+        2 => Accidental::DoubleSharp,
+        _ => return Err(invalid()),
+    };
+
+    let octave = pitch
+        .child_text("octave")
+        .and_then(|octave| octave.parse::<i32>().ok())
+        .ok_or_else(invalid)?;
+    Ok(Note::new(letter, accidental, Octave::try_from(octave)?))
+}
+
+// Duration in quarter notes.
+fn duration(element: &XmlElement, divisions: f64) -> Result<f64> {
+    match element.child_text("duration") {
+        Some(value) => match value.parse::<f64>() {
+            Ok(duration) if duration >= 0.0 => Ok(duration / divisions),
+            _ => Err(Error::new("Invalid duration", Kind::Zinnia)),
+        },
+        None => Err(Error::new("Missing duration", Kind::Zinnia)),
+    }
+}
+
+fn positive(value: &str, msg: &'static str) -> Result<f64> {
+    match value.parse::<f64>() {
+        Ok(value) if value > 0.0 => Ok(value),
+        _ => Err(Error::new(msg, Kind::Zinnia)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct XmlElement {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<XmlElement>,
+    text: String,
+}
+
+impl XmlElement {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn child(&self, name: &str) -> Option<&XmlElement> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    fn children<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = &'a XmlElement> + 'a {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    fn child_text(&self, name: &str) -> Option<&str> {
+        self.child(name).map(|child| child.text.trim())
+    }
+}
+
+// Reads just enough XML for MusicXML: elements, attributes, text and the
+// predefined and numeric entities. Declarations, comments, processing
+// instructions and the doctype are skipped.
+struct XmlParser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> XmlParser<'a> {
+    fn new(text: &'a str) -> XmlParser<'a> {
+        XmlParser { text, position: 0 }
+    }
+
+    fn error(&self, msg: &'static str) -> Error {
+        let line = self.text[..self.position].matches('\n').count() + 1;
+        Error::new(msg, Kind::Line(line))
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.position..]
+    }
+
+    fn advance(&mut self, bytes: usize) {
+        self.position = (self.position + bytes).min(self.text.len());
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.advance(rest.len() - rest.trim_start().len());
+    }
+
+    fn skip_past(&mut self, end: &str) -> Result<()> {
+        match self.rest().find(end) {
+            Some(idx) => {
+                self.advance(idx + end.len());
+                Ok(())
+            }
+            None => Err(self.error("Unterminated markup")),
+        }
+    }
+
+    // Skips comments and processing instructions, returning whether any
+    // were found.
+    fn skip_misc(&mut self) -> Result<bool> {
+        if self.rest().starts_with("<!--") {
+            self.skip_past("-->")?;
+        } else if self.rest().starts_with("<?") {
+            self.skip_past("?>")?;
+        } else {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    fn document(&mut self) -> Result<XmlElement> {
+        loop {
+            self.skip_whitespace();
+            if self.skip_misc()? {
+                continue;
+            }
+            if self.rest().starts_with("<!DOCTYPE") {
+                self.doctype()?;
+                continue;
+            }
+            break;
+        }
+
+        let root = self.element()?;
+        loop {
+            self.skip_whitespace();
+            if !self.skip_misc()? {
+                break;
+            }
+        }
+        if self.rest().is_empty() {
+            Ok(root)
+        } else {
+            Err(self.error("Content after the root element"))
+        }
+    }
+
+    // The doctype may hold an internal subset in brackets, which can itself
+    // contain '>'.
+    fn doctype(&mut self) -> Result<()> {
+        let mut depth = 0;
+        for (idx, c) in self.rest().char_indices() {
+            match c {
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                '>' if depth == 0 => {
+                    self.advance(idx + 1);
+                    return Ok(());
+                }
+                _ => (),
+            }
+        }
+        Err(self.error("Unterminated doctype"))
+    }
+
+    fn name(&mut self) -> Result<String> {
+        let rest = self.rest();
+        let end = rest
+            .find(|c: char| c.is_whitespace() || "/>=".contains(c))
+            .unwrap_or(rest.len());
+        if end == 0 {
+            return Err(self.error("Expected a name"));
+        }
+        self.advance(end);
+        Ok(rest[..end].to_string())
+    }
+
+    fn expect(&mut self, token: &str) -> Result<()> {
+        if self.rest().starts_with(token) {
+            self.advance(token.len());
+            Ok(())
+        } else {
+            Err(self.error("Unexpected character"))
+        }
+    }
+
+    fn element(&mut self) -> Result<XmlElement> {
+        self.expect("<")?;
+        let mut element = XmlElement {
+            name: self.name()?,
+            attributes: Vec::new(),
+            children: Vec::new(),
+            text: String::new(),
+        };
+
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.advance(2);
+                return Ok(element);
+            }
+            if self.rest().starts_with('>') {
+                self.advance(1);
+                break;
+            }
+            let key = self.name()?;
+            self.skip_whitespace();
+            self.expect("=")?;
+            self.skip_whitespace();
+            let value = self.quoted()?;
+            element.attributes.push((key, value));
+        }
+
+        loop {
+            let rest = self.rest();
+            if rest.starts_with("</") {
+                self.advance(2);
+                if self.name()? != element.name {
+                    return Err(self.error("Mismatched closing tag"));
+                }
+                self.skip_whitespace();
+                self.expect(">")?;
+                return Ok(element);
+            } else if rest.starts_with("<![CDATA[") {
+                self.advance("<![CDATA[".len());
+                let end = self
+                    .rest()
+                    .find("]]>")
+                    .ok_or_else(|| self.error("Unterminated CDATA"))?;
+                element.text.push_str(&self.rest()[..end]);
+                self.advance(end + "]]>".len());
+            } else if self.skip_misc()? {
+                continue;
+            } else if rest.starts_with('<') {
+                element.children.push(self.element()?);
+            } else if rest.is_empty() {
+                return Err(self.error("Unterminated element"));
+            } else {
+                let end = rest.find('<').unwrap_or(rest.len());
+                element.text.push_str(&self.unescape(&rest[..end])?);
+                self.advance(end);
+            }
+        }
+    }
+
+    fn quoted(&mut self) -> Result<String> {
+        let rest = self.rest();
+        let quote = match rest.chars().next() {
+            Some(quote) if quote == '"' || quote == '\'' => quote,
+            _ => return Err(self.error("Expected a quoted value")),
+        };
+        let end = rest[1..]
+            .find(quote)
+            .ok_or_else(|| self.error("Unterminated value"))?;
+        let value = self.unescape(&rest[1..end + 1])?;
+        self.advance(end + 2);
+        Ok(value)
+    }
+
+    fn unescape(&self, text: &str) -> Result<String> {
+        let mut result = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(amp) = rest.find('&') {
+            result.push_str(&rest[..amp]);
+            let semicolon = rest[amp..]
+                .find(';')
+                .ok_or_else(|| self.error("Unterminated entity"))?;
+            let entity = &rest[amp + 1..amp + semicolon];
+            let c = match entity {
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "amp" => Some('&'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                _ if entity.starts_with("#x") => {
+                    u32::from_str_radix(&entity[2..], 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                }
+                _ if entity.starts_with('#') => {
+                    entity[1..].parse().ok().and_then(char::from_u32)
+                }
+                _ => None,
+            };
+            result.push(c.ok_or_else(|| self.error("Unknown entity"))?);
+            rest = &rest[amp + semicolon + 1..];
+        }
+        result.push_str(rest);
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(symbol: &str) -> Note {
+        Note::parse(symbol).unwrap()
+    }
+
+    fn notes(track: &Track) -> Vec<(String, f64, f64)> {
+        track
+            .notes()
+            .iter()
+            .map(|n| (n.note().to_string(), n.start(), n.length()))
+            .collect()
+    }
+
+    fn score(measures: &str) -> String {
+        format!(
+            "<score-partwise><part-list><score-part id=\"P1\">\
+             <part-name>Flute</part-name></score-part></part-list>\
+             <part id=\"P1\">{}</part></score-partwise>",
+            measures
+        )
+    }
+
+    #[test]
+    fn xml_ok() -> Result<()> {
+        let root = XmlParser::new(
+            "<?xml version=\"1.0\"?>\n<!DOCTYPE a [<!ENTITY x \"y\">]>\n\
+             <!-- c --><a k='v &amp; w'><b/>t&lt;&#65;&#x42;\
+             <![CDATA[<c>]]><b n=\"2\" >u</b ></a>\n",
+        )
+        .document()?;
+        assert_eq!(root.name, "a");
+        assert_eq!(root.attribute("k"), Some("v & w"));
+        assert_eq!(root.text, "t<AB<c>");
+        assert_eq!(root.children("b").count(), 2);
+        assert_eq!(root.children[1].attribute("n"), Some("2"));
+        assert_eq!(root.child_text("b"), Some(""));
+        Ok(())
+    }
+    #[test]
+    fn xml_fail() {
+        let line = |text| XmlParser::new(text).document().unwrap_err().kind();
+        assert_eq!(line("<a>\n<b></a>"), Kind::Line(2));
+        assert_eq!(line("<a>\n\n<b x=1/></a>"), Kind::Line(3));
+        assert_eq!(line("<a>&bogus;</a>"), Kind::Line(1));
+        assert_eq!(line("<a></a>\n<b/>"), Kind::Line(2));
+        assert_eq!(line("<a>\n"), Kind::Line(2));
+    }
+    #[test]
+    fn pitches_and_durations_ok() -> Result<()> {
+        let sequence = Sequence::parse_musicxml(&score(
+            "<measure number=\"1\"><attributes><divisions>2</divisions>\
+             <time><beats>3</beats><beat-type>4</beat-type></time>\
+             </attributes>\
+             <note><pitch><step>F</step><alter>1</alter><octave>4</octave>\
+             </pitch><duration>2</duration></note>\
+             <note><rest/><duration>1</duration></note>\
+             <note><pitch><step>B</step><alter>-1</alter><octave>3</octave>\
+             </pitch><duration>3</duration></note></measure>",
+        ))?;
+        assert_eq!(sequence.time_signature(), TimeSignature::new(3, 4)?);
+        assert_eq!(sequence.tracks()[0].name(), "Flute");
+        assert_eq!(
+            notes(&sequence.tracks()[0]),
+            [("F#4".to_string(), 0.0, 1.0), ("Bb3".to_string(), 1.5, 1.5)]
+        );
+        assert_eq!(sequence.tracks()[0].end(), 3.0);
+        Ok(())
+    }
+    #[test]
+    fn chords_ties_and_tempo_ok() -> Result<()> {
+        let pitch = |step, octave| {
+            format!(
+                "<pitch><step>{}</step><octave>{}</octave></pitch>",
+                step, octave
+            )
+        };
+        let sequence = Sequence::parse_musicxml(&score(&format!(
+            "<measure><attributes><divisions>1</divisions></attributes>\
+             <direction><sound tempo=\"60\"/></direction>\
+             <note>{c}<duration>2</duration><tie type=\"start\"/></note>\
+             <note><chord/>{e}<duration>2</duration></note>\
+             <note><grace/>{e}</note>\
+             <note>{c}<duration>1</duration><tie type=\"stop\"/></note>\
+             <sound tempo=\"120\"/>\
+             <note>{e}<duration>1</duration></note></measure>",
+            c = pitch("C", 4),
+            e = pitch("E", 4)
+        )))?;
+        assert_eq!(
+            notes(&sequence.tracks()[0]),
+            [
+                ("E4".to_string(), 0.0, 2.0),
+                ("C4".to_string(), 0.0, 3.0),
+                ("E4".to_string(), 3.0, 1.0)
+            ]
+        );
+        assert_eq!(sequence.tempo().bpm(2.0), 60.0);
+        assert_eq!(sequence.tempo().bpm(3.0), 120.0);
+        assert!((sequence.tempo().seconds(4.0) - 3.5).abs() < 1e-9);
+        Ok(())
+    }
+    #[test]
+    fn parse_fail() {
+        assert!(Sequence::parse_musicxml("<score-timewise/>").is_err());
+        assert!(Sequence::parse_musicxml(&score(
+            "<measure><note><pitch><step>H</step><octave>4</octave></pitch>\
+             <duration>1</duration></note></measure>"
+        ))
+        .is_err());
+        assert!(Sequence::parse_musicxml(&score(
+            "<measure><note><rest/></note></measure>"
+        ))
+        .is_err());
+    }
+    #[test]
+    fn open_melody_ok() -> Result<()> {
+        let sequence =
+            Sequence::open_musicxml("data/musicxml/melody.musicxml")?;
+        assert_eq!(sequence.tracks().len(), 1);
+        let track = &sequence.tracks()[0];
+        assert_eq!(track.name(), "Violin");
+        assert_eq!(sequence.time_signature(), TimeSignature::new(3, 4)?);
+        assert_eq!(sequence.tempo().bpm(0.0), 96.0);
+
+        let notes = track.notes();
+        let pitches: Vec<Note> = notes.iter().map(|n| n.note()).collect();
+        assert_eq!(
+            pitches,
+            [note("G4"), note("A4"), note("Bb4"), note("C#5"), note("D5")]
+        );
+        assert_eq!(notes[4].start(), 4.0);
+        assert_eq!(notes[4].length(), 5.0);
+        Ok(())
+    }
+    #[test]
+    fn open_duet_ok() -> Result<()> {
+        let sequence = Sequence::open_musicxml("data/musicxml/duet.musicxml")?;
+        let names: Vec<&str> =
+            sequence.tracks().iter().map(|t| t.name()).collect();
+        assert_eq!(
+            names,
+            ["Right Hand (voice 1)", "Right Hand (voice 2)", "Part 2"]
+        );
+
+        let upper = sequence.tracks()[0].notes();
+        let lower = sequence.tracks()[1].notes();
+        assert_eq!(upper.len(), 4);
+        assert_eq!(lower.len(), 2);
+        assert_eq!(lower[1].note(), note("F4"));
+        assert_eq!(lower[1].start(), 2.0);
+
+        let bass = sequence.tracks()[2].notes();
+        assert_eq!(bass.len(), 2);
+        assert_eq!(bass[0].note(), note("C3"));
+        assert_eq!(bass[1].start(), 4.0);
+        Ok(())
+    }
+}
@@ -1,5 +1,12 @@
+pub mod additive;
 pub mod config;
+pub mod envelope;
 pub mod filter;
+pub mod fm;
+pub mod noise;
+pub mod oscillator;
+pub mod string;
+pub mod wavetable;
 
 use crate::hwp::HardwareParams;
 use alsa::pcm::IoFormat;
@@ -8,11 +15,14 @@ use core::f32;
 use filter::{Filter, FilterCollection};
 use lazy_static::lazy_static;
 use std::{
-    f32::consts::PI, fs::File, io::Read, mem, path::Path, time::Duration, usize,
+    f32::consts::PI, fs::File, io::Read, mem, path::Path, time::Duration,
 };
 
 pub type Ticks = u32;
 
+/// Duration for sounds whose length is left to an envelope's release.
+pub const UNTIL_RELEASED: Ticks = Ticks::MAX;
+
 const MAX_PHASE: f32 = 2.0 * PI;
 const MAX_CONCURRENT: u32 = 4;
 const PERIOD_SAMPLE_SIZE: usize = 1000;
@@ -78,10 +88,16 @@ impl Ticker {
         }
     }
 
-    fn is_complete(&self) -> bool {
+    // Past its duration a sound is silent, but keeps running its filters
+    // until their tails have died away so that echoes are not cut off.
+    fn is_ringing(&self) -> bool {
         self.tick_count >= self.duration
     }
 
+    fn is_complete(&self, filters: &FilterCollection) -> bool {
+        self.tick_count >= self.duration.saturating_add(filters.tail())
+    }
+
     fn tick(&mut self) {
         self.tick_count += 1;
     }
@@ -90,6 +106,11 @@ impl Ticker {
 pub trait Sound: Send {
     fn generate(&mut self, channel: u32) -> f32;
     fn tick(&mut self);
+
+    /// Called at note-off. Sounds without a release phase ignore it and
+    /// play for their fixed duration.
+    fn release(&mut self) {}
+
     fn is_complete(&self) -> bool;
 }
 
@@ -111,16 +132,24 @@ impl Sinusoid {
         T: IoFormat,
     {
         let d = duration_to_ticks(duration, hwp.rate());
+        Sinusoid::with_ticks(config, d, hwp.rate())
+    }
 
+    /// Creates a sinusoid lasting exactly `ticks` at the sample rate `rate`.
+    pub fn with_ticks(
+        config: &SoundConfigCollection,
+        ticks: Ticks,
+        rate: Ticks,
+    ) -> Sinusoid {
         Sinusoid {
             phase: config.iter().map_phase(|phase| phase).collect(),
             step: config
                 .iter()
-                .map_freq(|freq| calc_step(freq, hwp.rate()))
+                .map_freq(|freq| calc_step(freq, rate))
                 .collect(),
             amplitude: config.iter().map_amplitude(|amp| amp).collect(),
             filters: FilterCollection::new(),
-            ticker: Ticker::new(d),
+            ticker: Ticker::new(ticks),
         }
     }
 
@@ -131,6 +160,10 @@ impl Sinusoid {
 
 impl Sound for Sinusoid {
     fn generate(&mut self, channel: u32) -> f32 {
+        if self.ticker.is_ringing() {
+            return self.filters.apply(0.0, self.ticker.tick_count, channel);
+        }
+
         let ch = channel as usize;
         let res = self.phase[ch].sin() * self.amplitude[ch];
         self.phase[ch] += self.step[ch];
@@ -138,11 +171,16 @@ impl Sound for Sinusoid {
     }
 
     fn tick(&mut self) {
+        self.filters.tick();
         self.ticker.tick();
     }
 
+    fn release(&mut self) {
+        self.filters.release();
+    }
+
     fn is_complete(&self) -> bool {
-        self.ticker.is_complete()
+        self.ticker.is_complete(&self.filters)
     }
 }
 
@@ -185,11 +223,137 @@ impl Sound for MultiSound {
         }
     }
 
+    fn release(&mut self) {
+        for sound in &mut self.sounds {
+            sound.release();
+        }
+    }
+
     fn is_complete(&self) -> bool {
         self.sounds.iter().all(|s| s.is_complete())
     }
 }
 
+// A sound on a `Timeline`, with the tick it is due to be released at.
+struct Scheduled {
+    release: Option<Ticks>,
+    sound: Box<dyn Sound>,
+}
+
+/// Starts each of its sounds at a fixed tick offset, so that events land on
+/// exact sample positions instead of whenever they reach the mixer.
+pub struct Timeline {
+    // Sorted latest first so that due sounds pop off the end.
+    pending: Vec<(Ticks, Scheduled)>,
+    active: Vec<Scheduled>,
+    tick_count: Ticks,
+}
+
+impl Timeline {
+    pub fn new() -> Timeline {
+        Timeline {
+            pending: Vec::new(),
+            active: Vec::new(),
+            tick_count: 0,
+        }
+    }
+
+    /// Schedules `sound` to start `start` ticks after the timeline does.
+    pub fn schedule(&mut self, start: Ticks, sound: Box<dyn Sound>) {
+        self.schedule_internal(start, None, sound);
+    }
+
+    /// Schedules `sound` to start at `start` and be released at `end`, both
+    /// in ticks from the start of the timeline.
+    pub fn schedule_release(
+        &mut self,
+        start: Ticks,
+        end: Ticks,
+        sound: Box<dyn Sound>,
+    ) {
+        self.schedule_internal(start, Some(end), sound);
+    }
+
+    fn schedule_internal(
+        &mut self,
+        start: Ticks,
+        release: Option<Ticks>,
+        sound: Box<dyn Sound>,
+    ) {
+        let scheduled = Scheduled { release, sound };
+        if start <= self.tick_count {
+            self.active.push(scheduled);
+        } else {
+            let idx = self
+                .pending
+                .iter()
+                .position(|(pending, _)| *pending < start)
+                .unwrap_or(self.pending.len());
+            self.pending.insert(idx, (start, scheduled));
+        }
+        self.release_due();
+    }
+
+    fn start_due(&mut self) {
+        while let Some((start, _)) = self.pending.last() {
+            if *start > self.tick_count {
+                break;
+            }
+            if let Some((_, scheduled)) = self.pending.pop() {
+                self.active.push(scheduled);
+            }
+        }
+    }
+
+    fn release_due(&mut self) {
+        let tick_count = self.tick_count;
+        for scheduled in &mut self.active {
+            if scheduled.release.is_some_and(|end| end <= tick_count) {
+                scheduled.release = None;
+                scheduled.sound.release();
+            }
+        }
+    }
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Timeline::new()
+    }
+}
+
+impl Sound for Timeline {
+    // Sounds are summed rather than averaged so that a voice doesn't change
+    // level as others start and stop around it.
+    fn generate(&mut self, channel: u32) -> f32 {
+        self.active
+            .iter_mut()
+            .fold(0.0f32, |acc, s| acc + s.sound.generate(channel))
+    }
+
+    fn tick(&mut self) {
+        for scheduled in &mut self.active {
+            scheduled.sound.tick();
+        }
+        self.active.retain(|s| !s.sound.is_complete());
+        self.tick_count += 1;
+        self.start_due();
+        self.release_due();
+    }
+
+    fn release(&mut self) {
+        for scheduled in &mut self.active {
+            scheduled.release = None;
+            scheduled.sound.release();
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        self.pending.is_empty()
+            && self.active.iter().all(|s| s.sound.is_complete())
+    }
+}
+
 pub struct InputConfig<'a> {
     data: &'a [f32],
     channels: u32,
@@ -227,6 +391,15 @@ impl<'a> CachedPeriod<'a> {
         T: IoFormat,
     {
         let d = duration_to_ticks(duration, params.rate());
+        CachedPeriod::with_ticks(input_config, sound_config, d, params.rate())
+    }
+
+    pub fn with_ticks(
+        input_config: InputConfig<'a>,
+        sound_config: &SoundConfigCollection,
+        ticks: Ticks,
+        rate: Ticks,
+    ) -> Self {
         let data_size =
             (input_config.data.len() / input_config.channels as usize) as f32;
 
@@ -234,7 +407,7 @@ impl<'a> CachedPeriod<'a> {
             .iter()
             .map_freq(|freq| {
                 let ticks_per_cycle =
-                    params.rate() as f32 / freq * input_config.cycles as f32;
+                    rate as f32 / freq * input_config.cycles as f32;
                 data_size / ticks_per_cycle
             })
             .collect();
@@ -252,9 +425,9 @@ impl<'a> CachedPeriod<'a> {
             amplitude,
             idx,
             idx_step,
-            idx_limit: data_size - std::f32::EPSILON,
+            idx_limit: data_size - f32::EPSILON,
             filters: FilterCollection::new(),
-            ticker: Ticker::new(d),
+            ticker: Ticker::new(ticks),
         }
     }
 
@@ -265,6 +438,10 @@ impl<'a> CachedPeriod<'a> {
 
 impl Sound for CachedPeriod<'_> {
     fn generate(&mut self, channel: u32) -> f32 {
+        if self.ticker.is_ringing() {
+            return self.filters.apply(0.0, self.ticker.tick_count, channel);
+        }
+
         let ch = channel as usize;
         let in_ch = ch % self.input_config.channels as usize;
         let in_chs = self.input_config.channels as usize;
@@ -290,11 +467,16 @@ impl Sound for CachedPeriod<'_> {
     }
 
     fn tick(&mut self) {
+        self.filters.tick();
         self.ticker.tick();
     }
 
+    fn release(&mut self) {
+        self.filters.release();
+    }
+
     fn is_complete(&self) -> bool {
-        self.ticker.is_complete()
+        self.ticker.is_complete(&self.filters)
     }
 }
 
@@ -354,7 +536,7 @@ fn sine_period_n_channels(
 fn c4_2_channel_sound() -> Vec<f32> {
     let filename = Path::new("data/C4.raw");
 
-    let mut input = File::open(&filename).unwrap();
+    let mut input = File::open(filename).unwrap();
 
     let mut buf = Vec::new();
     let bytes_read = input.read_to_end(&mut buf).unwrap();
@@ -365,3 +547,33 @@ fn c4_2_channel_sound() -> Vec<f32> {
     }
     result
 }
+
+// Plays `sound` for `samples` ticks, returning what each channel generated.
+#[cfg(test)]
+pub(crate) fn render_channels(
+    sound: &mut dyn Sound,
+    channels: u32,
+    samples: usize,
+) -> Vec<Vec<f32>> {
+    let mut values = vec![Vec::with_capacity(samples); channels as usize];
+    for _ in 0..samples {
+        for (channel, values) in (0..channels).zip(&mut values) {
+            values.push(sound.generate(channel));
+        }
+        sound.tick();
+    }
+    values
+}
+
+// Plays the first channel of `sound` for `samples` ticks.
+#[cfg(test)]
+pub(crate) fn render(sound: &mut dyn Sound, samples: usize) -> Vec<f32> {
+    render_channels(sound, 1, samples).remove(0)
+}
+
+#[cfg(test)]
+pub(crate) fn assert_close(values: &[f32], expected: impl Fn(usize) -> f32) {
+    for (i, val) in values.iter().enumerate() {
+        assert!((val - expected(i)).abs() < 0.001, "sample {}", i);
+    }
+}
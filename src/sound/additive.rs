@@ -0,0 +1,267 @@
+use super::{
+    config::SoundConfigCollection,
+    duration_to_ticks,
+    envelope::EnvelopeGenerator,
+    filter::{Filter, FilterCollection},
+    Sound, Ticker, Ticks, MAX_PHASE,
+};
+use crate::hwp::HardwareParams;
+use alsa::pcm::IoFormat;
+use std::time::Duration;
+
+/// One sine component of an `Additive` voice.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Partial {
+    ratio: f32,
+    amplitude: f32,
+    phase: f32,
+    inharmonicity: f32,
+    envelope: EnvelopeGenerator,
+}
+
+impl Partial {
+    /// A partial at `ratio` times the voice frequency.
+    pub fn new(ratio: f32) -> Partial {
+        Partial {
+            ratio,
+            amplitude: 1.0,
+            phase: 0.0,
+            inharmonicity: 0.0,
+            envelope: EnvelopeGenerator::constant(1.0),
+        }
+    }
+
+    pub fn amplitude(mut self, amplitude: f32) -> Partial {
+        self.amplitude = amplitude;
+        self
+    }
+
+    /// Phase in radians, added to the phase of the voice.
+    pub fn phase(mut self, phase: f32) -> Partial {
+        self.phase = phase;
+        self
+    }
+
+    /// Stretches the partial as a stiff string would, to
+    /// `ratio * sqrt(1 + inharmonicity * ratio^2)`.
+    pub fn inharmonicity(mut self, inharmonicity: f32) -> Partial {
+        self.inharmonicity = inharmonicity;
+        self
+    }
+
+    /// Shapes the amplitude over the note. The envelope is released along
+    /// with the voice.
+    pub fn envelope(mut self, envelope: EnvelopeGenerator) -> Partial {
+        self.envelope = envelope;
+        self
+    }
+
+    fn stretched_ratio(&self) -> f32 {
+        self.ratio * (1.0 + self.inharmonicity * self.ratio * self.ratio).sqrt()
+    }
+}
+
+// Per channel state of one partial.
+struct PartialState {
+    partial: usize,
+    phase: f32,
+    step: f32,
+}
+
+/// Sum of sine partials, each with its own envelope. Amplitudes are scaled
+/// so that the partials together never exceed the configured amplitude, and
+/// partials at or above Nyquist are left out rather than aliased.
+pub struct Additive {
+    partials: Vec<Partial>,
+    state: Vec<Vec<PartialState>>,
+    amplitude: Vec<f32>,
+    filters: FilterCollection,
+    ticker: Ticker,
+}
+
+impl Additive {
+    pub fn new<T>(
+        partials: Vec<Partial>,
+        config: &SoundConfigCollection,
+        duration: Duration,
+        hwp: &HardwareParams<T>,
+    ) -> Additive
+    where
+        T: IoFormat,
+    {
+        let d = duration_to_ticks(duration, hwp.rate());
+        Additive::with_ticks(partials, config, d, hwp.rate())
+    }
+
+    pub fn with_ticks(
+        partials: Vec<Partial>,
+        config: &SoundConfigCollection,
+        ticks: Ticks,
+        rate: Ticks,
+    ) -> Additive {
+        let nyquist = rate as f32 / 2.0;
+        let state = config
+            .iter()
+            .map_freq(|freq| freq)
+            .zip(config.iter().map_phase(|phase| phase))
+            .map(|(freq, phase)| {
+                partials
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, p)| (idx, p, freq * p.stretched_ratio()))
+                    .filter(|(_, _, freq)| freq.abs() < nyquist)
+                    .map(|(idx, p, freq)| PartialState {
+                        partial: idx,
+                        phase: phase + p.phase,
+                        step: MAX_PHASE * freq / rate as f32,
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let total = partials.iter().map(|p| p.amplitude.abs()).sum::<f32>();
+        let scale = if total > 1.0 { 1.0 / total } else { 1.0 };
+
+        Additive {
+            partials,
+            state,
+            amplitude: config.iter().map_amplitude(|amp| amp * scale).collect(),
+            filters: FilterCollection::new(),
+            ticker: Ticker::new(ticks),
+        }
+    }
+
+    pub fn add_filter(&mut self, filter: Box<dyn Filter>) {
+        self.filters.add_filter(filter);
+    }
+}
+
+impl Sound for Additive {
+    fn generate(&mut self, channel: u32) -> f32 {
+        if self.ticker.is_ringing() {
+            return self.filters.apply(0.0, self.ticker.tick_count, channel);
+        }
+
+        let ch = channel as usize;
+        let tick = self.ticker.tick_count;
+        let partials = &self.partials;
+
+        let sum = self.state[ch]
+            .iter_mut()
+            .map(|s| {
+                let p = &partials[s.partial];
+                let val = s.phase.sin() * p.amplitude * p.envelope.level();
+                s.phase = (s.phase + s.step) % MAX_PHASE;
+                val
+            })
+            .sum::<f32>();
+
+        self.filters.apply(sum * self.amplitude[ch], tick, channel)
+    }
+
+    fn tick(&mut self) {
+        for partial in &mut self.partials {
+            partial.envelope.tick();
+        }
+        self.filters.tick();
+        self.ticker.tick();
+    }
+
+    fn release(&mut self) {
+        for partial in &mut self.partials {
+            partial.envelope.release();
+        }
+        self.filters.release();
+    }
+
+    fn is_complete(&self) -> bool {
+        self.ticker.is_complete(&self.filters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sound::{
+        assert_close,
+        envelope::{Curve, Segment},
+        render,
+    };
+
+    const RATE: Ticks = 8000;
+    const FREQ: f32 = 220.0;
+
+    fn play(partials: Vec<Partial>) -> Vec<f32> {
+        let config = SoundConfigCollection::with_configs(&[(FREQ, 0.0, 1.0)]);
+        render(&mut Additive::with_ticks(partials, &config, 100, RATE), 100)
+    }
+
+    fn partial(i: usize, ratio: f32) -> f32 {
+        (MAX_PHASE * FREQ * ratio * i as f32 / RATE as f32).sin()
+    }
+
+    #[test]
+    fn single_partial_sine() {
+        let values = play(vec![Partial::new(1.0).amplitude(0.5)]);
+        assert_close(&values, |i| 0.5 * partial(i, 1.0));
+    }
+    #[test]
+    fn partials_scaled() {
+        let values = play(vec![
+            Partial::new(1.0),
+            Partial::new(2.0).amplitude(0.5),
+            Partial::new(3.0).amplitude(-0.5),
+        ]);
+        assert_close(&values, |i| {
+            (partial(i, 1.0) + 0.5 * partial(i, 2.0) - 0.5 * partial(i, 3.0))
+                / 2.0
+        });
+    }
+    #[test]
+    fn inharmonicity_ok() {
+        let values = play(vec![Partial::new(2.0).inharmonicity(0.01)]);
+        let ratio = 2.0 * 1.04f32.sqrt();
+        assert_close(&values, |i| partial(i, ratio));
+    }
+    #[test]
+    fn above_nyquist_dropped() {
+        let values = play(vec![
+            Partial::new(1.0).amplitude(0.5),
+            Partial::new(20.0).amplitude(0.5),
+        ]);
+        assert_close(&values, |i| 0.5 * partial(i, 1.0));
+    }
+    #[test]
+    fn envelope_and_phase_ok() {
+        let env = EnvelopeGenerator::one_shot(&[
+            Segment::new(1.0, 0, Curve::Linear),
+            Segment::new(0.0, 50, Curve::Linear),
+        ]);
+        let values = play(vec![Partial::new(1.0)
+            .phase(MAX_PHASE / 4.0)
+            .amplitude(0.5)
+            .envelope(env)]);
+        assert_close(&values, |i| {
+            let level = 1.0 - (i as f32 / 50.0).min(1.0);
+            let angle = MAX_PHASE * FREQ * i as f32 / RATE as f32;
+            0.5 * level * angle.cos()
+        });
+    }
+    #[test]
+    fn release_ok() {
+        let config = SoundConfigCollection::with_configs(&[(FREQ, 0.0, 1.0)]);
+        let env = EnvelopeGenerator::adsr(0, 0, 1.0, 10, Curve::Linear);
+        let mut sound = Additive::with_ticks(
+            vec![Partial::new(1.0).envelope(env)],
+            &config,
+            100,
+            RATE,
+        );
+        render(&mut sound, 10);
+        sound.release();
+        let values = render(&mut sound, 20);
+        assert_close(&values, |i| {
+            (1.0 - (i as f32 / 10.0).min(1.0)) * partial(i + 10, 1.0)
+        });
+    }
+}
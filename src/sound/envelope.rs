@@ -0,0 +1,401 @@
+use super::{Sound, Ticks};
+use crate::{
+    error::{Error, Kind},
+    Result,
+};
+
+/// Piecewise linear envelope through `(tick, level)` breakpoints. The level
+/// holds at the first breakpoint before it and at the last one after it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Envelope {
+    points: Vec<(Ticks, f32)>,
+}
+
+impl Envelope {
+    /// Fails if `points` is empty or its ticks ever decrease.
+    pub fn new(points: &[(Ticks, f32)]) -> Result<Envelope> {
+        if points.is_empty() || points.windows(2).any(|w| w[1].0 < w[0].0) {
+            return Err(Error::new("Invalid envelope points", Kind::Zinnia));
+        }
+
+        Ok(Envelope {
+            points: points.to_vec(),
+        })
+    }
+
+    pub fn constant(level: f32) -> Envelope {
+        Envelope {
+            points: vec![(0, level)],
+        }
+    }
+
+    /// The tick of the last breakpoint, after which the level stays put.
+    pub fn length(&self) -> Ticks {
+        self.points[self.points.len() - 1].0
+    }
+
+    pub fn level(&self, tick: Ticks) -> f32 {
+        let next = self.points.iter().position(|(t, _)| *t > tick);
+        match next {
+            Some(0) => self.points[0].1,
+            Some(i) => {
+                let (start, from) = self.points[i - 1];
+                let (end, to) = self.points[i];
+                let progress = (tick - start) as f32 / (end - start) as f32;
+                from + (to - from) * progress
+            }
+            None => self.points[self.points.len() - 1].1,
+        }
+    }
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Envelope::constant(1.0)
+    }
+}
+
+const CURVE_STEEPNESS: f32 = 5.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Curve {
+    Linear,
+    /// Quick at first and slowing towards the target, like an analog
+    /// envelope.
+    Exponential,
+}
+
+impl Curve {
+    // Fraction of the way to the target after `progress`, from 0 to 1, of a
+    // segment.
+    fn shape(self, progress: f32) -> f32 {
+        match self {
+            Curve::Linear => progress,
+            Curve::Exponential => {
+                (1.0 - (-CURVE_STEEPNESS * progress).exp())
+                    / (1.0 - (-CURVE_STEEPNESS).exp())
+            }
+        }
+    }
+}
+
+/// A move from wherever the level is to `target` over `ticks`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+    target: f32,
+    ticks: Ticks,
+    curve: Curve,
+}
+
+impl Segment {
+    pub fn new(target: f32, ticks: Ticks, curve: Curve) -> Segment {
+        Segment {
+            target,
+            ticks,
+            curve,
+        }
+    }
+}
+
+/// Envelope that follows a note's gate. It runs its stages from 0 and
+/// holds the last level until released, then runs its release segments
+/// from whatever level it had reached.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvelopeGenerator {
+    stages: Vec<Segment>,
+    release: Vec<Segment>,
+    sustain: bool,
+    releasing: bool,
+    stage: usize,
+    tick: Ticks,
+    start: f32,
+    level: f32,
+}
+
+impl EnvelopeGenerator {
+    pub fn new(stages: &[Segment], release: &[Segment]) -> EnvelopeGenerator {
+        let mut envelope = EnvelopeGenerator {
+            stages: stages.to_vec(),
+            release: release.to_vec(),
+            sustain: true,
+            releasing: false,
+            stage: 0,
+            tick: 0,
+            start: 0.0,
+            level: 0.0,
+        };
+        envelope.skip_empty();
+        envelope
+    }
+
+    /// Runs `stages` to the end without waiting for a release, which it
+    /// ignores, as for percussion.
+    pub fn one_shot(stages: &[Segment]) -> EnvelopeGenerator {
+        EnvelopeGenerator {
+            sustain: false,
+            ..EnvelopeGenerator::new(stages, &[])
+        }
+    }
+
+    /// Holds `level` throughout, ignoring the release.
+    pub fn constant(level: f32) -> EnvelopeGenerator {
+        EnvelopeGenerator::one_shot(&[Segment::new(level, 0, Curve::Linear)])
+    }
+
+    /// Rises to 1 over `attack`, falls to `sustain` over `decay` and holds
+    /// there until released, then falls to 0 over `release`.
+    pub fn adsr(
+        attack: Ticks,
+        decay: Ticks,
+        sustain: f32,
+        release: Ticks,
+        curve: Curve,
+    ) -> EnvelopeGenerator {
+        EnvelopeGenerator::new(
+            &[
+                Segment::new(1.0, attack, curve),
+                Segment::new(sustain, decay, curve),
+            ],
+            &[Segment::new(0.0, release, curve)],
+        )
+    }
+
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+
+    pub fn tick(&mut self) {
+        if let Some(segment) = self.segments().get(self.stage).copied() {
+            self.tick += 1;
+            if self.tick >= segment.ticks {
+                self.next_stage(segment.target);
+            } else {
+                let progress = self.tick as f32 / segment.ticks as f32;
+                self.level = self.start
+                    + (segment.target - self.start)
+                        * segment.curve.shape(progress);
+            }
+        }
+    }
+
+    /// Goes back to the start of the stages, as for a new note.
+    pub fn reset(&mut self) {
+        self.releasing = false;
+        self.stage = 0;
+        self.tick = 0;
+        self.start = 0.0;
+        self.level = 0.0;
+        self.skip_empty();
+    }
+
+    pub fn release(&mut self) {
+        if self.sustain && !self.releasing {
+            self.releasing = true;
+            self.stage = 0;
+            self.tick = 0;
+            self.start = self.level;
+            self.skip_empty();
+        }
+    }
+
+    pub fn is_released(&self) -> bool {
+        self.releasing
+    }
+
+    /// Whether the envelope has nothing left to do. One that sustains only
+    /// finishes after being released.
+    pub fn is_finished(&self) -> bool {
+        (self.releasing || !self.sustain) && self.stage >= self.segments().len()
+    }
+
+    fn segments(&self) -> &[Segment] {
+        if self.releasing {
+            &self.release
+        } else {
+            &self.stages
+        }
+    }
+
+    fn next_stage(&mut self, level: f32) {
+        self.level = level;
+        self.start = level;
+        self.stage += 1;
+        self.tick = 0;
+        self.skip_empty();
+    }
+
+    fn skip_empty(&mut self) {
+        if let Some(segment) = self.segments().get(self.stage).copied() {
+            if segment.ticks == 0 {
+                self.next_stage(segment.target);
+            }
+        }
+    }
+}
+
+/// Shapes another sound with an `EnvelopeGenerator`, ending when either
+/// does. Give the wrapped sound a duration of `UNTIL_RELEASED` for its
+/// length to be set by the release alone.
+pub struct Enveloped {
+    sound: Box<dyn Sound>,
+    envelope: EnvelopeGenerator,
+}
+
+impl Enveloped {
+    pub fn new(sound: Box<dyn Sound>, envelope: EnvelopeGenerator) -> Self {
+        Enveloped { sound, envelope }
+    }
+}
+
+impl Sound for Enveloped {
+    fn generate(&mut self, channel: u32) -> f32 {
+        self.sound.generate(channel) * self.envelope.level()
+    }
+
+    fn tick(&mut self) {
+        self.sound.tick();
+        self.envelope.tick();
+    }
+
+    fn release(&mut self) {
+        self.envelope.release();
+        self.sound.release();
+    }
+
+    fn is_complete(&self) -> bool {
+        self.envelope.is_finished() || self.sound.is_complete()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sound::{
+        config::SoundConfigCollection, render, Sinusoid, Timeline,
+        UNTIL_RELEASED,
+    };
+    use std::f32::consts::PI;
+
+    fn levels(envelope: &mut EnvelopeGenerator, ticks: usize) -> Vec<f32> {
+        (0..ticks)
+            .map(|_| {
+                let level = envelope.level();
+                envelope.tick();
+                level
+            })
+            .collect()
+    }
+
+    #[test]
+    fn invalid_points() {
+        assert!(Envelope::new(&[]).is_err());
+        assert!(Envelope::new(&[(10, 1.0), (5, 0.0)]).is_err());
+    }
+    #[test]
+    fn level_ok() -> Result<()> {
+        let env = Envelope::new(&[(10, 0.0), (20, 1.0), (20, 0.5), (40, 0.0)])?;
+        assert_eq!(env.level(0), 0.0);
+        assert_eq!(env.level(15), 0.5);
+        assert_eq!(env.level(20), 0.5);
+        assert_eq!(env.level(30), 0.25);
+        assert_eq!(env.level(100), 0.0);
+        assert_eq!(env.length(), 40);
+        Ok(())
+    }
+    #[test]
+    fn constant_ok() {
+        let env = Envelope::default();
+        assert_eq!(env.level(0), 1.0);
+        assert_eq!(env.level(1000), 1.0);
+        assert_eq!(env.length(), 0);
+    }
+    #[test]
+    fn adsr_ok() {
+        let mut env = EnvelopeGenerator::adsr(4, 2, 0.5, 4, Curve::Linear);
+        assert_eq!(
+            levels(&mut env, 10),
+            vec![0.0, 0.25, 0.5, 0.75, 1.0, 0.75, 0.5, 0.5, 0.5, 0.5]
+        );
+        assert!(!env.is_finished());
+
+        env.release();
+        assert!(env.is_released());
+        assert_eq!(levels(&mut env, 4), vec![0.5, 0.375, 0.25, 0.125]);
+        assert!(env.is_finished());
+        assert_eq!(env.level(), 0.0);
+    }
+    #[test]
+    fn early_release_ok() {
+        let mut env = EnvelopeGenerator::adsr(4, 2, 0.5, 2, Curve::Linear);
+        levels(&mut env, 2);
+        env.release();
+        assert_eq!(levels(&mut env, 3), vec![0.5, 0.25, 0.0]);
+        assert!(env.is_finished());
+    }
+    #[test]
+    fn exponential_ok() {
+        let mut env =
+            EnvelopeGenerator::adsr(10, 0, 1.0, 0, Curve::Exponential);
+        let values = levels(&mut env, 11);
+        assert_eq!(values[0], 0.0);
+        assert!(values[5] > 0.9);
+        assert!(values.windows(2).all(|w| w[1] >= w[0]));
+        assert_eq!(values[10], 1.0);
+
+        env.release();
+        assert!(env.is_finished());
+        assert_eq!(env.level(), 0.0);
+    }
+    #[test]
+    fn one_shot_ok() {
+        let mut env = EnvelopeGenerator::one_shot(&[
+            Segment::new(1.0, 0, Curve::Linear),
+            Segment::new(0.0, 2, Curve::Linear),
+        ]);
+        env.release();
+        assert!(!env.is_released());
+        assert_eq!(levels(&mut env, 2), vec![1.0, 0.5]);
+        assert!(env.is_finished());
+    }
+    #[test]
+    fn enveloped_released() {
+        // A sinusoid stuck at its peak, so only the envelope changes.
+        let config =
+            SoundConfigCollection::with_configs(&[(0.0, PI / 2.0, 1.0)]);
+        let sound = Sinusoid::with_ticks(&config, UNTIL_RELEASED, 8000);
+        let mut sound = Enveloped::new(
+            Box::new(sound),
+            EnvelopeGenerator::adsr(2, 0, 1.0, 2, Curve::Linear),
+        );
+        sound.tick();
+        assert_eq!(sound.generate(0), 0.5);
+        sound.tick();
+        sound.tick();
+        assert_eq!(sound.generate(0), 1.0);
+        assert!(!sound.is_complete());
+
+        sound.release();
+        sound.tick();
+        assert_eq!(sound.generate(0), 0.5);
+        sound.tick();
+        assert!(sound.is_complete());
+    }
+    #[test]
+    fn timeline_releases() {
+        let config =
+            SoundConfigCollection::with_configs(&[(0.0, PI / 2.0, 1.0)]);
+        let sound = Sinusoid::with_ticks(&config, UNTIL_RELEASED, 8000);
+        let sound = Enveloped::new(
+            Box::new(sound),
+            EnvelopeGenerator::adsr(0, 0, 1.0, 2, Curve::Linear),
+        );
+        let mut timeline = Timeline::new();
+        timeline.schedule_release(1, 3, Box::new(sound));
+
+        assert_eq!(
+            render(&mut timeline, 6),
+            vec![0.0, 1.0, 1.0, 1.0, 0.5, 0.0]
+        );
+        assert!(timeline.is_complete());
+    }
+}
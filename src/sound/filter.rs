@@ -1,7 +1,198 @@
-use super::{verify_scale, Ticks};
+pub mod biquad;
+pub mod delay;
+pub mod resonant;
+pub mod reverb;
+
+use super::{
+    envelope::{Envelope, EnvelopeGenerator},
+    oscillator::{self, Waveform},
+    verify_scale, Sound, Ticks, MAX_PHASE,
+};
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
+
+// Time for a smoothed parameter to cover most of a change.
+const SMOOTHING_SECONDS: f32 = 0.01;
+// Keeps cutoffs clear of DC and Nyquist, where filters degenerate.
+const MIN_CUTOFF: f32 = 1.0;
+const MAX_CUTOFF_RATIO: f32 = 0.49;
+// Output level at which a decaying response counts as finished, -60 dB.
+const TAIL_LEVEL: f32 = 0.001;
+// Longest tail a filter reports, so that one ringing on forever, such as a
+// self-oscillating ladder, still lets its sound end.
+const MAX_TAIL_SECONDS: f32 = 10.0;
 
 pub trait Filter: Send {
-    fn apply(&self, val: f32, tick: Ticks, channel: u32) -> f32;
+    /// Filters the sample `val` of `channel` at `tick`. It is called once
+    /// per channel and tick, in order, so filters may keep state between
+    /// calls.
+    fn apply(&mut self, val: f32, tick: Ticks, channel: u32) -> f32;
+
+    /// Called once every channel has been filtered for a tick, to move on
+    /// to the next one.
+    fn tick(&mut self) {}
+
+    /// Called at note-off, for filters whose parameters follow the note.
+    fn release(&mut self) {}
+
+    /// Clears any state so that the filter behaves as if new.
+    fn reset(&mut self) {}
+
+    /// How many ticks the filter keeps producing output after its input
+    /// falls silent. Filters that would ring on for good give a tail of a
+    /// few seconds instead, so that their sounds end.
+    fn tail(&self) -> Ticks {
+        0
+    }
+}
+
+fn max_tail(rate: Ticks) -> Ticks {
+    (MAX_TAIL_SECONDS * rate as f32) as Ticks
+}
+
+/// State kept separately for each channel, created as channels are first
+/// seen.
+#[derive(Debug, Clone, Default)]
+pub struct PerChannel<T> {
+    states: Vec<T>,
+}
+
+impl<T: Default> PerChannel<T> {
+    pub fn new() -> Self {
+        PerChannel { states: Vec::new() }
+    }
+
+    pub fn get_mut(&mut self, channel: u32) -> &mut T {
+        let ch = channel as usize;
+        if ch >= self.states.len() {
+            self.states.resize_with(ch + 1, T::default);
+        }
+        &mut self.states[ch]
+    }
+
+    pub fn reset(&mut self) {
+        self.states.clear();
+    }
+}
+
+/// A filter parameter that can be changed from another thread while the
+/// sound using the filter plays. Clones share the same value.
+#[derive(Debug, Clone)]
+pub struct Control(Arc<AtomicU32>);
+
+impl Control {
+    pub fn new(value: f32) -> Control {
+        Control(Arc::new(AtomicU32::new(value.to_bits())))
+    }
+
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, value: f32) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+// Follows a `Control` with a one pole lag so that changes don't click.
+struct Smoothed {
+    control: Control,
+    value: f32,
+    coefficient: f32,
+}
+
+impl Smoothed {
+    fn new(control: Control, rate: Ticks) -> Smoothed {
+        Smoothed {
+            value: control.get(),
+            control,
+            coefficient: (-1.0 / (SMOOTHING_SECONDS * rate as f32)).exp(),
+        }
+    }
+
+    // Moves one tick towards the control's value, returning whether the
+    // value changed.
+    fn tick(&mut self) -> bool {
+        let target = self.control.get();
+        if self.value == target {
+            return false;
+        }
+        self.value = target + self.coefficient * (self.value - target);
+        if (self.value - target).abs() <= f32::EPSILON.max(target.abs() * 1e-4)
+        {
+            self.value = target;
+        }
+        true
+    }
+
+    fn snap(&mut self) {
+        self.value = self.control.get();
+    }
+}
+
+/// A source that moves a filter parameter, read once per tick.
+pub trait Modulator: Send {
+    fn value(&mut self, tick: Ticks) -> f32;
+
+    /// Starts over from the first tick, when the filter is reset.
+    fn reset(&mut self) {}
+
+    /// Called at note-off, when the filter is released.
+    fn release(&mut self) {}
+}
+
+impl Modulator for Envelope {
+    fn value(&mut self, tick: Ticks) -> f32 {
+        self.level(tick)
+    }
+}
+
+// Expects to be read every tick, as the filter's sound plays.
+impl Modulator for EnvelopeGenerator {
+    fn value(&mut self, _: Ticks) -> f32 {
+        let level = self.level();
+        self.tick();
+        level
+    }
+
+    fn reset(&mut self) {
+        EnvelopeGenerator::reset(self);
+    }
+
+    fn release(&mut self) {
+        EnvelopeGenerator::release(self);
+    }
+}
+
+/// Low frequency oscillator swinging between -1 and 1.
+pub struct Lfo {
+    waveform: Waveform,
+    phase: f32,
+    step: f32,
+}
+
+impl Lfo {
+    pub fn new(waveform: Waveform, freq: f32, rate: Ticks) -> Lfo {
+        Lfo {
+            waveform,
+            phase: 0.0,
+            step: freq / rate as f32,
+        }
+    }
+}
+
+impl Modulator for Lfo {
+    fn value(&mut self, _: Ticks) -> f32 {
+        let val = oscillator::sample(self.waveform, self.phase, self.step);
+        self.phase = (self.phase + self.step).rem_euclid(1.0);
+        val
+    }
+
+    fn reset(&mut self) {
+        self.phase = 0.0;
+    }
 }
 
 pub struct LinearFadeIn {
@@ -19,7 +210,7 @@ impl LinearFadeIn {
 }
 
 impl Filter for LinearFadeIn {
-    fn apply(&self, val: f32, tick: Ticks, _: u32) -> f32 {
+    fn apply(&mut self, val: f32, tick: Ticks, _: u32) -> f32 {
         if tick > self.duration {
             val
         } else {
@@ -45,7 +236,7 @@ impl LinearFadeOut {
 }
 
 impl Filter for LinearFadeOut {
-    fn apply(&self, val: f32, tick: Ticks, _: u32) -> f32 {
+    fn apply(&mut self, val: f32, tick: Ticks, _: u32) -> f32 {
         if tick < self.start || tick > self.end {
             val
         } else {
@@ -89,7 +280,7 @@ impl LeftRightFade {
 }
 
 impl Filter for LeftRightFade {
-    fn apply(&self, val: f32, tick: Ticks, channel: u32) -> f32 {
+    fn apply(&mut self, val: f32, tick: Ticks, channel: u32) -> f32 {
         let (y_intercept, slope) = match self.direction {
             FadeDirection::RightLeft => (self.min_scale, self.slope),
             FadeDirection::LeftRight => (self.max_scale, -self.slope),
@@ -105,6 +296,44 @@ impl Filter for LeftRightFade {
     }
 }
 
+/// One pole lowpass, for smoothing control signals or gently darkening a
+/// sound.
+pub struct OnePole {
+    coefficient: f32,
+    previous: PerChannel<f32>,
+}
+
+impl OnePole {
+    pub fn new(cutoff: f32, rate: Ticks) -> OnePole {
+        OnePole {
+            coefficient: (-MAX_PHASE * cutoff / rate as f32).exp(),
+            previous: PerChannel::new(),
+        }
+    }
+}
+
+impl Filter for OnePole {
+    fn apply(&mut self, val: f32, _: Ticks, channel: u32) -> f32 {
+        let previous = self.previous.get_mut(channel);
+        *previous = val + self.coefficient * (*previous - val);
+        *previous
+    }
+
+    fn reset(&mut self) {
+        self.previous.reset();
+    }
+
+    // Until the output has decayed by 60 dB.
+    fn tail(&self) -> Ticks {
+        if self.coefficient <= 0.0 {
+            0
+        } else {
+            (0.001f32.ln() / self.coefficient.ln()).ceil() as Ticks
+        }
+    }
+}
+
+#[derive(Default)]
 pub struct FilterCollection {
     filters: Option<Vec<Box<dyn Filter>>>,
 }
@@ -124,12 +353,226 @@ impl FilterCollection {
         }
     }
 
-    pub fn apply(&self, value: f32, tick_count: Ticks, channel: u32) -> f32 {
-        match &self.filters {
+    pub fn apply(
+        &mut self,
+        value: f32,
+        tick_count: Ticks,
+        channel: u32,
+    ) -> f32 {
+        match &mut self.filters {
             Some(filters) => filters
-                .iter()
+                .iter_mut()
                 .fold(value, |v, f| f.apply(v, tick_count, channel)),
             None => value,
         }
     }
+
+    pub fn tick(&mut self) {
+        for filter in self.filters.iter_mut().flatten() {
+            filter.tick();
+        }
+    }
+
+    pub fn release(&mut self) {
+        for filter in self.filters.iter_mut().flatten() {
+            filter.release();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for filter in self.filters.iter_mut().flatten() {
+            filter.reset();
+        }
+    }
+
+    /// The longest the chain can ring on once its input stops, which is
+    /// the sum of the filters' tails.
+    pub fn tail(&self) -> Ticks {
+        self.filters
+            .iter()
+            .flatten()
+            .fold(0, |acc: Ticks, f| acc.saturating_add(f.tail()))
+    }
+}
+
+/// Runs a sound through filters of its own, and once the sound is complete
+/// keeps going on silence until their tails have died away, so that echoes
+/// are not cut off. It adds filters to sounds that have none, such as a
+/// `Timeline`.
+pub struct Filtered {
+    sound: Box<dyn Sound>,
+    filters: FilterCollection,
+    tick_count: Ticks,
+    // Ticks left in the tail, counted once the sound is complete.
+    remaining: Option<Ticks>,
+}
+
+impl Filtered {
+    pub fn new(sound: Box<dyn Sound>) -> Filtered {
+        Filtered {
+            sound,
+            filters: FilterCollection::new(),
+            tick_count: 0,
+            remaining: None,
+        }
+    }
+
+    pub fn add_filter(&mut self, filter: Box<dyn Filter>) {
+        self.filters.add_filter(filter);
+    }
+}
+
+impl Sound for Filtered {
+    fn generate(&mut self, channel: u32) -> f32 {
+        let val = if self.remaining.is_some() || self.sound.is_complete() {
+            0.0
+        } else {
+            self.sound.generate(channel)
+        };
+        self.filters.apply(val, self.tick_count, channel)
+    }
+
+    fn tick(&mut self) {
+        self.filters.tick();
+        match &mut self.remaining {
+            Some(remaining) => *remaining = remaining.saturating_sub(1),
+            None => {
+                if !self.sound.is_complete() {
+                    self.sound.tick();
+                }
+                if self.sound.is_complete() {
+                    self.remaining = Some(self.filters.tail());
+                }
+            }
+        }
+        self.tick_count += 1;
+    }
+
+    fn release(&mut self) {
+        self.sound.release();
+        self.filters.release();
+    }
+
+    fn is_complete(&self) -> bool {
+        self.remaining == Some(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sound::{config::SoundConfigCollection, Sinusoid};
+    use std::f32::consts::PI;
+
+    fn run(filter: &mut dyn Filter, input: &[f32]) -> Vec<f32> {
+        input
+            .iter()
+            .enumerate()
+            .map(|(tick, val)| {
+                let val = filter.apply(*val, tick as Ticks, 0);
+                filter.tick();
+                val
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fades_ok() {
+        let mut fade_in = LinearFadeIn::new(4);
+        assert_eq!(
+            run(&mut fade_in, &[1.0; 6]),
+            [0.0, 0.25, 0.5, 0.75, 1.0, 1.0]
+        );
+        let mut fade_out = LinearFadeOut::new(4, 5);
+        assert_eq!(
+            run(&mut fade_out, &[1.0; 6]),
+            [1.0, 1.0, 0.75, 0.5, 0.25, 0.0]
+        );
+        let mut fade =
+            LeftRightFade::new(0.0, 1.0, FadeDirection::LeftRight, 4);
+        assert_eq!(fade.apply(1.0, 1, 0), 0.75);
+        assert_eq!(fade.apply(1.0, 1, 1), 0.25);
+    }
+    #[test]
+    fn one_pole_ok() {
+        let mut filter = OnePole::new(100.0, 8000);
+        let step = run(&mut filter, &[1.0; 200]);
+        assert!(step.windows(2).all(|w| w[1] >= w[0]));
+        assert!(step[0] < 0.1 && step[199] > 0.99);
+        assert_eq!(filter.tail(), 88);
+
+        // Channels are filtered independently.
+        assert_eq!(filter.apply(0.0, 200, 1), 0.0);
+
+        filter.reset();
+        assert_eq!(run(&mut filter, &[1.0; 1]), step[..1]);
+    }
+    #[test]
+    fn collection_ok() {
+        let mut filters = FilterCollection::default();
+        assert_eq!(filters.apply(0.5, 0, 0), 0.5);
+        assert_eq!(filters.tail(), 0);
+
+        filters.add_filter(Box::new(OnePole::new(100.0, 8000)));
+        filters.add_filter(Box::new(OnePole::new(100.0, 8000)));
+        filters.add_filter(Box::new(LinearFadeIn::new(4)));
+        assert_eq!(filters.tail(), 176);
+
+        let first: Vec<f32> =
+            (0..8).map(|tick| filters.apply(1.0, tick, 0)).collect();
+        filters.reset();
+        let second: Vec<f32> =
+            (0..8).map(|tick| filters.apply(1.0, tick, 0)).collect();
+        assert_eq!(first, second);
+    }
+    #[test]
+    fn filtered_rings_on() {
+        let config =
+            SoundConfigCollection::with_configs(&[(0.0, PI / 2.0, 1.0)]);
+        let mut sound =
+            Filtered::new(Box::new(Sinusoid::with_ticks(&config, 2, 8000)));
+        sound.add_filter(Box::new(
+            delay::Delay::with_ticks(delay::Mode::Mono, 3).feedback(0.0),
+        ));
+
+        let mut values = Vec::new();
+        while !sound.is_complete() {
+            values.push(sound.generate(0));
+            sound.tick();
+        }
+        assert_eq!(values, [0.5, 0.5, 0.0, 0.5, 0.5]);
+    }
+    #[test]
+    fn sound_rings_on() {
+        let config =
+            SoundConfigCollection::with_configs(&[(0.0, PI / 2.0, 1.0)]);
+        let mut sound = Sinusoid::with_ticks(&config, 2, 8000);
+        sound.add_filter(Box::new(
+            delay::Delay::with_ticks(delay::Mode::Mono, 3).feedback(0.0),
+        ));
+
+        let mut values = Vec::new();
+        while !sound.is_complete() {
+            values.push(sound.generate(0));
+            sound.tick();
+        }
+        assert_eq!(values, [0.5, 0.5, 0.0, 0.5, 0.5]);
+    }
+    #[test]
+    fn endless_tail_capped() {
+        let config =
+            SoundConfigCollection::with_configs(&[(0.0, PI / 2.0, 1.0)]);
+        let mut sound =
+            Filtered::new(Box::new(Sinusoid::with_ticks(&config, 1, 8000)));
+        sound
+            .add_filter(Box::new(resonant::Ladder::new(500.0, 1.0, 1.0, 8000)));
+
+        let mut ticks = 0;
+        while !sound.is_complete() && ticks <= 100_000 {
+            sound.generate(0);
+            sound.tick();
+            ticks += 1;
+        }
+        assert_eq!(ticks, 1 + 10 * 8000);
+    }
 }
@@ -0,0 +1,385 @@
+use super::{
+    max_tail, Control, Filter, PerChannel, Smoothed, MAX_CUTOFF_RATIO,
+    MAX_PHASE, MIN_CUTOFF, TAIL_LEVEL,
+};
+use crate::sound::Ticks;
+
+const MIN_Q: f32 = 0.01;
+
+/// The filter responses of Robert Bristow-Johnson's Audio EQ Cookbook.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Response {
+    LowPass,
+    HighPass,
+    /// Band-pass with a peak gain of 0 dB.
+    BandPass,
+    Notch,
+    /// Boosts or cuts by the gain around the cutoff.
+    Peaking,
+    /// Boosts or cuts by the gain below the cutoff.
+    LowShelf,
+    /// Boosts or cuts by the gain above the cutoff.
+    HighShelf,
+}
+
+// Normalized so that a0 is 1.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Coefficients {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Coefficients {
+    fn new(
+        response: Response,
+        cutoff: f32,
+        q: f32,
+        gain: f32,
+        rate: Ticks,
+    ) -> Self {
+        let cutoff = cutoff.clamp(MIN_CUTOFF, rate as f32 * MAX_CUTOFF_RATIO);
+        let w0 = MAX_PHASE * cutoff / rate as f32;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q.max(MIN_Q));
+        let a = 10f32.powf(gain / 40.0);
+        let shelf = 2.0 * a.sqrt() * alpha;
+
+        let (b0, b1, b2, a0, a1, a2) = match response {
+            Response::LowPass => (
+                (1.0 - cos) / 2.0,
+                1.0 - cos,
+                (1.0 - cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            Response::HighPass => (
+                (1.0 + cos) / 2.0,
+                -(1.0 + cos),
+                (1.0 + cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            Response::BandPass => {
+                (alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+            }
+            Response::Notch => {
+                (1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+            }
+            Response::Peaking => (
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+            Response::LowShelf => (
+                a * ((a + 1.0) - (a - 1.0) * cos + shelf),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - shelf),
+                (a + 1.0) + (a - 1.0) * cos + shelf,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - shelf,
+            ),
+            Response::HighShelf => (
+                a * ((a + 1.0) + (a - 1.0) * cos + shelf),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - shelf),
+                (a + 1.0) - (a - 1.0) * cos + shelf,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - shelf,
+            ),
+        };
+
+        Coefficients {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    // Magnitude of the slowest decaying pole.
+    fn pole_radius(&self) -> f32 {
+        let discriminant = self.a1 * self.a1 - 4.0 * self.a2;
+        if discriminant < 0.0 {
+            self.a2.sqrt()
+        } else {
+            let root = discriminant.sqrt();
+            ((-self.a1 + root) / 2.0)
+                .abs()
+                .max(((-self.a1 - root) / 2.0).abs())
+        }
+    }
+}
+
+// Direct form I history, which copes better than the transposed forms with
+// coefficients that change from sample to sample.
+#[derive(Debug, Clone, Copy, Default)]
+struct History {
+    x1: f32,
+    x2: f32,
+    y1: f32,
+    y2: f32,
+}
+
+/// Second order filter with per-channel state. Cutoff in hertz, Q and gain
+/// in decibels are `Control`s, so they can be changed while the sound
+/// plays and glide to their new values instead of clicking.
+pub struct Biquad {
+    response: Response,
+    rate: Ticks,
+    cutoff: Smoothed,
+    q: Smoothed,
+    gain: Smoothed,
+    coefficients: Coefficients,
+    history: PerChannel<History>,
+}
+
+impl Biquad {
+    /// A filter with a gain of 0 dB, which only matters for the peaking and
+    /// shelving responses.
+    pub fn new(response: Response, cutoff: f32, q: f32, rate: Ticks) -> Biquad {
+        Biquad::with_gain(response, cutoff, q, 0.0, rate)
+    }
+
+    pub fn with_gain(
+        response: Response,
+        cutoff: f32,
+        q: f32,
+        gain: f32,
+        rate: Ticks,
+    ) -> Biquad {
+        Biquad {
+            response,
+            rate,
+            cutoff: Smoothed::new(Control::new(cutoff), rate),
+            q: Smoothed::new(Control::new(q), rate),
+            gain: Smoothed::new(Control::new(gain), rate),
+            coefficients: Coefficients::new(response, cutoff, q, gain, rate),
+            history: PerChannel::new(),
+        }
+    }
+
+    pub fn cutoff(&self) -> Control {
+        self.cutoff.control.clone()
+    }
+
+    pub fn q(&self) -> Control {
+        self.q.control.clone()
+    }
+
+    pub fn gain(&self) -> Control {
+        self.gain.control.clone()
+    }
+
+    fn update(&mut self) {
+        let cutoff = self.cutoff.tick();
+        let q = self.q.tick();
+        let gain = self.gain.tick();
+        if cutoff || q || gain {
+            self.coefficients = Coefficients::new(
+                self.response,
+                self.cutoff.value,
+                self.q.value,
+                self.gain.value,
+                self.rate,
+            );
+        }
+    }
+}
+
+impl Filter for Biquad {
+    fn apply(&mut self, val: f32, _: Ticks, channel: u32) -> f32 {
+        let c = self.coefficients;
+        let h = self.history.get_mut(channel);
+        let out =
+            c.b0 * val + c.b1 * h.x1 + c.b2 * h.x2 - c.a1 * h.y1 - c.a2 * h.y2;
+        *h = History {
+            x1: val,
+            x2: h.x1,
+            y1: out,
+            y2: h.y1,
+        };
+        out
+    }
+
+    // Parameters move once per tick however many channels there are.
+    fn tick(&mut self) {
+        self.update();
+    }
+
+    fn reset(&mut self) {
+        self.history.reset();
+        self.cutoff.snap();
+        self.q.snap();
+        self.gain.snap();
+        self.coefficients = Coefficients::new(
+            self.response,
+            self.cutoff.value,
+            self.q.value,
+            self.gain.value,
+            self.rate,
+        );
+    }
+
+    fn tail(&self) -> Ticks {
+        let radius = self.coefficients.pole_radius();
+        if radius <= 0.0 {
+            2
+        } else if radius >= 1.0 {
+            max_tail(self.rate)
+        } else {
+            let decay = (TAIL_LEVEL.ln() / radius.ln()).ceil() as Ticks;
+            decay.saturating_add(2).min(max_tail(self.rate))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    const RATE: Ticks = 8000;
+
+    // Steady state gain for a sine at `freq`, in decibels.
+    fn gain_at(filter: &mut Biquad, freq: f32) -> f32 {
+        filter.reset();
+        let samples = 4000;
+        let output: Vec<f32> = (0..samples)
+            .map(|i| {
+                let angle = MAX_PHASE * freq * i as f32 / RATE as f32;
+                let val = filter.apply(angle.cos(), i as Ticks, 0);
+                filter.tick();
+                val
+            })
+            .collect();
+        let rms = |values: &[f32]| {
+            (values.iter().map(|v| v * v).sum::<f32>() / values.len() as f32)
+                .sqrt()
+        };
+        let input_rms = if freq == 0.0 { 1.0 } else { 0.5f32.sqrt() };
+        20.0 * (rms(&output[samples / 2..]) / input_rms).log10()
+    }
+
+    fn assert_db(filter: &mut Biquad, freq: f32, expected: f32) {
+        let gain = gain_at(filter, freq);
+        assert!((gain - expected).abs() < 0.2, "{} Hz: {} dB", freq, gain);
+    }
+
+    #[test]
+    fn pass_responses_ok() {
+        let mut low =
+            Biquad::new(Response::LowPass, 500.0, FRAC_1_SQRT_2, RATE);
+        assert_db(&mut low, 0.0, 0.0);
+        assert_db(&mut low, 500.0, -3.0);
+        assert!(gain_at(&mut low, 3000.0) < -30.0);
+
+        let mut high =
+            Biquad::new(Response::HighPass, 500.0, FRAC_1_SQRT_2, RATE);
+        assert_db(&mut high, 500.0, -3.0);
+        assert_db(&mut high, 3900.0, 0.0);
+        assert!(gain_at(&mut high, 50.0) < -30.0);
+
+        let mut band = Biquad::new(Response::BandPass, 1000.0, 2.0, RATE);
+        assert_db(&mut band, 1000.0, 0.0);
+        assert!(gain_at(&mut band, 0.0) < -60.0);
+
+        let mut notch = Biquad::new(Response::Notch, 1000.0, 2.0, RATE);
+        assert!(gain_at(&mut notch, 1000.0) < -40.0);
+        assert_db(&mut notch, 0.0, 0.0);
+    }
+    #[test]
+    fn gain_responses_ok() {
+        let mut peak =
+            Biquad::with_gain(Response::Peaking, 1000.0, 1.0, 6.0, RATE);
+        assert_db(&mut peak, 1000.0, 6.0);
+        assert_db(&mut peak, 0.0, 0.0);
+
+        let mut low = Biquad::with_gain(
+            Response::LowShelf,
+            500.0,
+            FRAC_1_SQRT_2,
+            -6.0,
+            RATE,
+        );
+        assert_db(&mut low, 0.0, -6.0);
+        assert_db(&mut low, 3900.0, 0.0);
+
+        let mut high = Biquad::with_gain(
+            Response::HighShelf,
+            500.0,
+            FRAC_1_SQRT_2,
+            6.0,
+            RATE,
+        );
+        assert_db(&mut high, 0.0, 0.0);
+        assert_db(&mut high, 3900.0, 6.0);
+    }
+    #[test]
+    fn cutoff_glides() {
+        let mut filter =
+            Biquad::new(Response::LowPass, 500.0, FRAC_1_SQRT_2, RATE);
+        filter.apply(0.0, 0, 0);
+        filter.cutoff().set(1000.0);
+        // Nothing moves until the tick is over.
+        filter.apply(0.0, 0, 1);
+        assert_eq!(filter.cutoff.value, 500.0);
+
+        filter.tick();
+        let first = filter.cutoff.value;
+        assert!(first > 500.0 && first < 600.0);
+
+        for tick in 1..1000 {
+            filter.apply(0.0, tick, 0);
+            filter.tick();
+        }
+        assert_eq!(filter.cutoff.value, 1000.0);
+        assert_eq!(
+            filter.coefficients,
+            Coefficients::new(
+                Response::LowPass,
+                1000.0,
+                FRAC_1_SQRT_2,
+                0.0,
+                RATE
+            )
+        );
+    }
+    #[test]
+    fn channels_independent() {
+        let mut filter =
+            Biquad::new(Response::LowPass, 500.0, FRAC_1_SQRT_2, RATE);
+        let left: Vec<f32> = (0..10)
+            .map(|tick| {
+                let val = filter.apply(1.0, tick, 0);
+                filter.tick();
+                val
+            })
+            .collect();
+        filter.reset();
+        let interleaved: Vec<f32> = (0..10)
+            .map(|tick| {
+                let val = filter.apply(1.0, tick, 0);
+                assert_eq!(filter.apply(-0.5, tick, 1), -0.5 * val);
+                filter.tick();
+                val
+            })
+            .collect();
+        assert_eq!(left, interleaved);
+    }
+    #[test]
+    fn tail_ok() {
+        let gentle = Biquad::new(Response::LowPass, 500.0, FRAC_1_SQRT_2, RATE);
+        let ringing = Biquad::new(Response::LowPass, 500.0, 20.0, RATE);
+        assert!(gentle.tail() > 2);
+        assert!(ringing.tail() > 10 * gentle.tail());
+    }
+}
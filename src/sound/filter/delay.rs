@@ -0,0 +1,236 @@
+use super::{Filter, PerChannel, TAIL_LEVEL};
+use crate::{
+    music::rhythm::{NoteValue, Tempo},
+    sound::Ticks,
+};
+
+const MAX_FEEDBACK: f32 = 0.99;
+// Largest coefficient of the lowpass in the feedback loop, reached at full
+// damping.
+const MAX_DAMPING: f32 = 0.9;
+
+/// How the channels share delay lines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// The channels are mixed into one line, whose echoes come back on
+    /// every channel.
+    Mono,
+    /// Each channel echoes separately.
+    Stereo,
+    /// The channels are mixed into the first line, and each echo moves on
+    /// to the next channel, bouncing from left to right.
+    PingPong,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Line {
+    buffer: Vec<f32>,
+    input: f32,
+    lowpass: f32,
+}
+
+impl Line {
+    fn fit(&mut self, length: usize) {
+        if self.buffer.len() != length {
+            self.buffer.resize(length, 0.0);
+        }
+    }
+}
+
+/// Echo with feedback. Repeats are fed back through a lowpass, so with
+/// damping each one is duller than the last. A sound plays on after its
+/// duration until the echoes have died away.
+pub struct Delay {
+    mode: Mode,
+    length: usize,
+    feedback: f32,
+    mix: f32,
+    damping: f32,
+    lines: PerChannel<Line>,
+    pos: usize,
+}
+
+impl Delay {
+    /// A delay of `millis` milliseconds.
+    pub fn new(mode: Mode, millis: f32, rate: Ticks) -> Delay {
+        let ticks = (millis / 1000.0 * rate as f32).round() as Ticks;
+        Delay::with_ticks(mode, ticks)
+    }
+
+    /// A delay of one `note` at the tempo at the start of `tempo`.
+    pub fn synced(
+        mode: Mode,
+        note: NoteValue,
+        tempo: &Tempo,
+        rate: Ticks,
+    ) -> Delay {
+        Delay::with_ticks(mode, note.ticks(tempo, 0.0, rate))
+    }
+
+    /// A delay of `ticks`, which is at least one.
+    pub fn with_ticks(mode: Mode, ticks: Ticks) -> Delay {
+        Delay {
+            mode,
+            length: ticks.max(1) as usize,
+            feedback: 0.4,
+            mix: 0.5,
+            damping: 0.0,
+            lines: PerChannel::new(),
+            pos: 0,
+        }
+    }
+
+    /// Level of each repeat relative to the one before, below 1.
+    pub fn feedback(mut self, feedback: f32) -> Delay {
+        self.feedback = feedback.clamp(0.0, MAX_FEEDBACK);
+        self
+    }
+
+    /// Share of the output taken by the echoes, from 0 for the dry sound
+    /// alone to 1 for the echoes alone.
+    pub fn mix(mut self, mix: f32) -> Delay {
+        self.mix = mix.clamp(0.0, 1.0);
+        self
+    }
+
+    /// How much high end each repeat loses, from 0 to 1.
+    pub fn damping(mut self, damping: f32) -> Delay {
+        self.damping = damping.clamp(0.0, 1.0) * MAX_DAMPING;
+        self
+    }
+
+    // Writes the inputs of the tick just finished into the lines. It waits
+    // for every channel because the mono and ping-pong lines take all of
+    // them.
+    fn commit(&mut self) {
+        let lines = &mut self.lines.states;
+        let mean = lines.iter().map(|line| line.input).sum::<f32>()
+            / lines.len() as f32;
+
+        let mut previous = lines.last().map_or(0.0, |line| line.lowpass);
+        for (ch, line) in lines.iter_mut().enumerate() {
+            let (input, feedback) = match self.mode {
+                Mode::Mono => (mean, line.lowpass),
+                Mode::Stereo => (line.input, line.lowpass),
+                Mode::PingPong if ch == 0 => (mean, previous),
+                Mode::PingPong => (0.0, previous),
+            };
+            previous = line.lowpass;
+
+            line.fit(self.length);
+            line.buffer[self.pos] = input + self.feedback * feedback;
+        }
+        self.pos = (self.pos + 1) % self.length;
+    }
+}
+
+impl Filter for Delay {
+    fn apply(&mut self, val: f32, _: Ticks, channel: u32) -> f32 {
+        let line = self.lines.get_mut(channel);
+        line.fit(self.length);
+        let wet = line.buffer[self.pos];
+        line.input = val;
+        line.lowpass = wet + self.damping * (line.lowpass - wet);
+        (1.0 - self.mix) * val + self.mix * wet
+    }
+
+    fn tick(&mut self) {
+        self.commit();
+    }
+
+    fn reset(&mut self) {
+        self.lines.reset();
+        self.pos = 0;
+    }
+
+    // Until the repeats have fallen by 60 dB, ignoring damping.
+    fn tail(&self) -> Ticks {
+        let repeats = if self.feedback > 0.0 {
+            1 + (TAIL_LEVEL.ln() / self.feedback.ln()).ceil() as Ticks
+        } else {
+            1
+        };
+        (self.length as Ticks).saturating_mul(repeats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::rhythm::Value;
+
+    // Runs an impulse on `channel` through the delay for `ticks`, returning
+    // the output of each of two channels.
+    fn impulse(delay: &mut Delay, channel: u32, ticks: Ticks) -> Vec<[f32; 2]> {
+        (0..ticks)
+            .map(|tick| {
+                let input =
+                    |ch| if tick == 0 && ch == channel { 1.0 } else { 0.0 };
+                let frame = [
+                    delay.apply(input(0), tick, 0),
+                    delay.apply(input(1), tick, 1),
+                ];
+                delay.tick();
+                frame
+            })
+            .collect()
+    }
+
+    #[test]
+    fn echoes_ok() {
+        let mut delay = Delay::with_ticks(Mode::Stereo, 3).feedback(0.5);
+        let output = impulse(&mut delay, 0, 10);
+        let left: Vec<f32> = output.iter().map(|frame| frame[0]).collect();
+        assert_eq!(left, [0.5, 0.0, 0.0, 0.5, 0.0, 0.0, 0.25, 0.0, 0.0, 0.125]);
+        assert!(output.iter().all(|frame| frame[1] == 0.0));
+    }
+    #[test]
+    fn modes_ok() {
+        let mut mono = Delay::with_ticks(Mode::Mono, 2).feedback(0.5).mix(1.0);
+        let output = impulse(&mut mono, 1, 5);
+        assert_eq!(output[2], [0.5, 0.5]);
+        assert_eq!(output[4], [0.25, 0.25]);
+
+        let mut ping_pong =
+            Delay::with_ticks(Mode::PingPong, 2).feedback(0.5).mix(1.0);
+        let output = impulse(&mut ping_pong, 1, 7);
+        assert_eq!(output[2], [0.5, 0.0]);
+        assert_eq!(output[4], [0.0, 0.25]);
+        assert_eq!(output[6], [0.125, 0.0]);
+    }
+    #[test]
+    fn damping_ok() {
+        let mut delay = Delay::with_ticks(Mode::Stereo, 4)
+            .feedback(0.9)
+            .damping(1.0)
+            .mix(1.0);
+        let output = impulse(&mut delay, 0, 10);
+        // The first echo is untouched, but the lowpass smears the second.
+        assert_eq!(output[4][0], 1.0);
+        assert!(output[8][0] < 0.1);
+        assert!(output[9][0] > 0.0);
+    }
+    #[test]
+    fn time_ok() -> crate::Result<()> {
+        assert_eq!(Delay::new(Mode::Mono, 250.0, 8000).length, 2000);
+        let tempo = Tempo::new(120.0)?;
+        let eighth = NoteValue::new(Value::Eighth);
+        let delay = Delay::synced(Mode::Mono, eighth, &tempo, 8000);
+        assert_eq!(delay.length, 2000);
+        let dotted = Delay::synced(Mode::Mono, eighth.dots(1), &tempo, 8000);
+        assert_eq!(dotted.length, 3000);
+        Ok(())
+    }
+    #[test]
+    fn tail_ok() {
+        assert_eq!(
+            Delay::with_ticks(Mode::Mono, 100).feedback(0.0).tail(),
+            100
+        );
+        // 0.5 to the tenth power is the first repeat below -60 dB.
+        assert_eq!(
+            Delay::with_ticks(Mode::Mono, 100).feedback(0.5).tail(),
+            1100
+        );
+    }
+}
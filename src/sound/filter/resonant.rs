@@ -0,0 +1,472 @@
+use super::{
+    max_tail, Control, Filter, Modulator, PerChannel, Smoothed,
+    MAX_CUTOFF_RATIO, MAX_PHASE, MIN_CUTOFF, TAIL_LEVEL,
+};
+use crate::sound::Ticks;
+use std::f32::consts::{PI, SQRT_2};
+
+const MIN_Q: f32 = 0.1;
+// Feedback of the ladder at full resonance. Oscillation starts at 4, and a
+// little more keeps it going against the saturation.
+const MAX_FEEDBACK: f32 = 4.2;
+
+// A cutoff that follows its control smoothly and is bent, in octaves, by an
+// optional modulator.
+struct Cutoff {
+    base: Smoothed,
+    modulation: Option<(Box<dyn Modulator>, f32)>,
+    rate: Ticks,
+    tick: Ticks,
+    value: f32,
+}
+
+impl Cutoff {
+    fn new(cutoff: f32, rate: Ticks) -> Cutoff {
+        let mut cutoff = Cutoff {
+            base: Smoothed::new(Control::new(cutoff), rate),
+            modulation: None,
+            rate,
+            tick: 0,
+            value: cutoff,
+        };
+        cutoff.update();
+        cutoff
+    }
+
+    fn modulate(&mut self, modulator: Box<dyn Modulator>, octaves: f32) {
+        self.modulation = Some((modulator, octaves));
+        self.update();
+    }
+
+    fn tick(&mut self) {
+        self.tick += 1;
+        self.base.tick();
+        self.update();
+    }
+
+    fn release(&mut self) {
+        if let Some((modulator, _)) = &mut self.modulation {
+            modulator.release();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.tick = 0;
+        self.base.snap();
+        if let Some((modulator, _)) = &mut self.modulation {
+            modulator.reset();
+        }
+        self.update();
+    }
+
+    // Reads the modulator once for the current tick.
+    fn update(&mut self) {
+        let octaves = match &mut self.modulation {
+            Some((modulator, depth)) => modulator.value(self.tick) * *depth,
+            None => 0.0,
+        };
+        self.value = (self.base.value * 2f32.powf(octaves))
+            .clamp(MIN_CUTOFF, self.rate as f32 * MAX_CUTOFF_RATIO);
+    }
+
+    // Prewarped integrator gain of the topology-preserving transform.
+    fn gain(&self) -> f32 {
+        (PI * self.value / self.rate as f32).tan()
+    }
+
+    // The cutoff in radians per tick.
+    fn angular(&self) -> f32 {
+        MAX_PHASE * self.value / self.rate as f32
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    LowPass,
+    HighPass,
+    /// Band-pass with a peak gain of 0 dB.
+    BandPass,
+    Notch,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Integrators {
+    ic1: f32,
+    ic2: f32,
+}
+
+/// Zero delay feedback state variable filter, after Andrew Simper. It is
+/// stable at any cutoff and Q, even when the cutoff moves every tick, which
+/// makes it a good fit for sweeps at low sample rates.
+pub struct StateVariable {
+    mode: Mode,
+    cutoff: Cutoff,
+    q: Smoothed,
+    a1: f32,
+    a2: f32,
+    a3: f32,
+    k: f32,
+    state: PerChannel<Integrators>,
+}
+
+impl StateVariable {
+    pub fn new(mode: Mode, cutoff: f32, q: f32, rate: Ticks) -> StateVariable {
+        let mut filter = StateVariable {
+            mode,
+            cutoff: Cutoff::new(cutoff, rate),
+            q: Smoothed::new(Control::new(q), rate),
+            a1: 0.0,
+            a2: 0.0,
+            a3: 0.0,
+            k: 0.0,
+            state: PerChannel::new(),
+        };
+        filter.update();
+        filter
+    }
+
+    /// Moves the cutoff by `octaves` for each unit of `modulator`'s value.
+    pub fn modulate(
+        mut self,
+        modulator: Box<dyn Modulator>,
+        octaves: f32,
+    ) -> StateVariable {
+        self.cutoff.modulate(modulator, octaves);
+        self.update();
+        self
+    }
+
+    pub fn cutoff(&self) -> Control {
+        self.cutoff.base.control.clone()
+    }
+
+    pub fn q(&self) -> Control {
+        self.q.control.clone()
+    }
+
+    fn update(&mut self) {
+        let g = self.cutoff.gain();
+        self.k = 1.0 / self.q.value.max(MIN_Q);
+        self.a1 = 1.0 / (1.0 + g * (g + self.k));
+        self.a2 = g * self.a1;
+        self.a3 = g * self.a2;
+    }
+}
+
+impl Filter for StateVariable {
+    fn apply(&mut self, val: f32, _: Ticks, channel: u32) -> f32 {
+        let s = self.state.get_mut(channel);
+        let v3 = val - s.ic2;
+        let v1 = self.a1 * s.ic1 + self.a2 * v3;
+        let v2 = s.ic2 + self.a2 * s.ic1 + self.a3 * v3;
+        s.ic1 = 2.0 * v1 - s.ic1;
+        s.ic2 = 2.0 * v2 - s.ic2;
+
+        match self.mode {
+            Mode::LowPass => v2,
+            Mode::HighPass => val - self.k * v1 - v2,
+            Mode::BandPass => self.k * v1,
+            Mode::Notch => val - self.k * v1,
+        }
+    }
+
+    fn tick(&mut self) {
+        self.cutoff.tick();
+        self.q.tick();
+        self.update();
+    }
+
+    fn release(&mut self) {
+        self.cutoff.release();
+    }
+
+    fn reset(&mut self) {
+        self.state.reset();
+        self.cutoff.reset();
+        self.q.snap();
+        self.update();
+    }
+
+    // The poles decay by half the cutoff over Q per tick.
+    fn tail(&self) -> Ticks {
+        let damping = self.cutoff.angular() / (2.0 * self.q.value.max(MIN_Q));
+        let decay = (-TAIL_LEVEL.ln() / damping).ceil() as Ticks;
+        decay.min(max_tail(self.cutoff.rate))
+    }
+}
+
+/// Four pole lowpass modelled on the Moog transistor ladder, solved without
+/// a unit delay in the feedback path so it stays in tune and stable at low
+/// sample rates. Resonance runs from 0 to 1, where the filter starts to
+/// self-oscillate, and the input is saturated as on the original. The
+/// passband level is kept constant as resonance rises.
+pub struct Ladder {
+    cutoff: Cutoff,
+    resonance: Smoothed,
+    headroom: f32,
+    g: f32,
+    k: f32,
+    state: PerChannel<[f32; 4]>,
+}
+
+impl Ladder {
+    /// The saturation squashes levels approaching `headroom`, which is
+    /// usually the full scale of the samples, `max_amplitude`.
+    pub fn new(
+        cutoff: f32,
+        resonance: f32,
+        headroom: f32,
+        rate: Ticks,
+    ) -> Ladder {
+        let mut filter = Ladder {
+            cutoff: Cutoff::new(cutoff, rate),
+            resonance: Smoothed::new(Control::new(resonance), rate),
+            headroom: headroom.abs().max(f32::EPSILON),
+            g: 0.0,
+            k: 0.0,
+            state: PerChannel::new(),
+        };
+        filter.update();
+        filter
+    }
+
+    /// Moves the cutoff by `octaves` for each unit of `modulator`'s value.
+    pub fn modulate(
+        mut self,
+        modulator: Box<dyn Modulator>,
+        octaves: f32,
+    ) -> Ladder {
+        self.cutoff.modulate(modulator, octaves);
+        self.update();
+        self
+    }
+
+    pub fn cutoff(&self) -> Control {
+        self.cutoff.base.control.clone()
+    }
+
+    pub fn resonance(&self) -> Control {
+        self.resonance.control.clone()
+    }
+
+    fn update(&mut self) {
+        let g = self.cutoff.gain();
+        self.g = g / (1.0 + g);
+        self.k = MAX_FEEDBACK * self.resonance.value.clamp(0.0, 1.0);
+    }
+}
+
+impl Filter for Ladder {
+    fn apply(&mut self, val: f32, _: Ticks, channel: u32) -> f32 {
+        let g = self.g;
+        let k = self.k;
+        let headroom = self.headroom;
+        let s = self.state.get_mut(channel);
+
+        // Each stage gives g times its input plus (1 - g) times its state,
+        // which lets the feedback be solved for before running the stages.
+        let beta = 1.0 - g;
+        let states = s
+            .iter()
+            .rev()
+            .fold((0.0, 1.0), |(sum, gain), s| {
+                (sum + gain * beta * s, gain * g)
+            })
+            .0;
+        let g4 = g * g * g * g;
+        let input = val * (1.0 + k);
+        let estimate = (g4 * input + states) / (1.0 + k * g4);
+
+        let driven = headroom * ((input - k * estimate) / headroom).tanh();
+        s.iter_mut().fold(driven, |stage_in, state| {
+            let v = (stage_in - *state) * g;
+            let out = v + *state;
+            *state = out + v;
+            out
+        })
+    }
+
+    fn tick(&mut self) {
+        self.cutoff.tick();
+        self.resonance.tick();
+        self.update();
+    }
+
+    fn release(&mut self) {
+        self.cutoff.release();
+    }
+
+    fn reset(&mut self) {
+        self.state.reset();
+        self.cutoff.reset();
+        self.resonance.snap();
+        self.update();
+    }
+
+    // The resonant poles sit at the cutoff, damped less as the feedback
+    // approaches its maximum.
+    fn tail(&self) -> Ticks {
+        let damping =
+            self.cutoff.angular() * (1.0 - self.k.powf(0.25) / SQRT_2);
+        let limit = max_tail(self.cutoff.rate);
+        if damping <= 0.0 {
+            limit
+        } else {
+            ((-TAIL_LEVEL.ln() / damping).ceil() as Ticks).min(limit)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sound::{
+        envelope::{Curve, Envelope, EnvelopeGenerator},
+        filter::Lfo,
+        max_amplitude,
+        oscillator::Waveform,
+    };
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    const RATE: Ticks = 8000;
+
+    fn run(filter: &mut dyn Filter, input: impl Fn(usize) -> f32) -> Vec<f32> {
+        (0..4000)
+            .map(|i| {
+                let val = filter.apply(input(i), i as Ticks, 0);
+                filter.tick();
+                val
+            })
+            .collect()
+    }
+
+    // Steady state gain for a sine at `freq`, as a ratio.
+    fn gain_at(filter: &mut dyn Filter, freq: f32, level: f32) -> f32 {
+        filter.reset();
+        let output = run(filter, |i| {
+            level * (MAX_PHASE * freq * i as f32 / RATE as f32).cos()
+        });
+        let peak = output[2000..].iter().fold(0.0f32, |a, v| a.max(v.abs()));
+        peak / level
+    }
+
+    #[test]
+    fn state_variable_ok() {
+        let mut low =
+            StateVariable::new(Mode::LowPass, 500.0, FRAC_1_SQRT_2, RATE);
+        assert!((gain_at(&mut low, 0.0, 1.0) - 1.0).abs() < 0.001);
+        assert!(gain_at(&mut low, 3000.0, 1.0) < 0.03);
+
+        let mut high =
+            StateVariable::new(Mode::HighPass, 500.0, FRAC_1_SQRT_2, RATE);
+        assert!(gain_at(&mut high, 0.0, 1.0) < 0.001);
+        assert!((gain_at(&mut high, 3900.0, 1.0) - 1.0).abs() < 0.01);
+
+        let mut band = StateVariable::new(Mode::BandPass, 1000.0, 5.0, RATE);
+        assert!((gain_at(&mut band, 1000.0, 1.0) - 1.0).abs() < 0.01);
+        assert!(gain_at(&mut band, 250.0, 1.0) < 0.1);
+
+        let mut notch = StateVariable::new(Mode::Notch, 1000.0, 2.0, RATE);
+        assert!(gain_at(&mut notch, 1000.0, 1.0) < 0.01);
+    }
+    #[test]
+    fn state_variable_resonates() {
+        let mut low = StateVariable::new(Mode::LowPass, 1000.0, 10.0, RATE);
+        assert!((gain_at(&mut low, 1000.0, 1.0) - 10.0).abs() < 0.1);
+        assert!(low.tail() > 100);
+    }
+    #[test]
+    fn ladder_ok() {
+        for resonance in &[0.0, 0.5] {
+            let mut ladder = Ladder::new(500.0, *resonance, 1.0, RATE);
+            assert!((gain_at(&mut ladder, 0.0, 0.01) - 1.0).abs() < 0.01);
+            // Four poles fall by about 24 dB, a sixteenth, per octave.
+            assert!(gain_at(&mut ladder, 2000.0, 0.01) < 1.0 / 100.0);
+        }
+        let mut ladder = Ladder::new(500.0, 0.9, 1.0, RATE);
+        assert!(gain_at(&mut ladder, 500.0, 0.01) > 2.0);
+    }
+    #[test]
+    fn ladder_full_scale() {
+        // Sounds filter samples in device units, such as 16 bit.
+        let full_scale = max_amplitude::<i16>() as f32;
+        let mut ladder = Ladder::new(2000.0, 0.0, full_scale, RATE);
+        assert!(gain_at(&mut ladder, 100.0, 16000.0) > 0.9);
+        assert!((gain_at(&mut ladder, 100.0, 100.0) - 1.0).abs() < 0.01);
+    }
+    #[test]
+    fn ladder_self_oscillates() {
+        let mut ladder = Ladder::new(500.0, 1.0, 1.0, RATE);
+        let output = run(&mut ladder, |i| if i == 0 { 0.1 } else { 0.0 });
+        let late = output[3000..].iter().fold(0.0f32, |a, v| a.max(v.abs()));
+        assert!(late > 0.1);
+        assert!(output.iter().all(|v| v.abs() < 5.0));
+        assert_eq!(ladder.tail(), 10 * RATE);
+    }
+    #[test]
+    fn modulated_cutoff() -> crate::Result<()> {
+        let envelope = Envelope::new(&[(0, 0.0), (100, 1.0)])?;
+        let mut filter =
+            StateVariable::new(Mode::LowPass, 500.0, FRAC_1_SQRT_2, RATE)
+                .modulate(Box::new(envelope), 2.0);
+        assert_eq!(filter.cutoff.value, 500.0);
+        (0..50).for_each(|_| filter.tick());
+        assert!((filter.cutoff.value - 1000.0).abs() < 0.01);
+        (0..50).for_each(|_| filter.tick());
+        assert_eq!(filter.cutoff.value, 2000.0);
+        // Far beyond Nyquist, so held just below it.
+        filter.cutoff().set(4000.0);
+        filter.reset();
+        assert_eq!(filter.cutoff.value, RATE as f32 * MAX_CUTOFF_RATIO);
+        Ok(())
+    }
+    #[test]
+    fn modulated_reset_ok() {
+        let envelope =
+            EnvelopeGenerator::adsr(100, 100, 0.5, 100, Curve::Linear);
+        let mut svf = StateVariable::new(Mode::LowPass, 500.0, 5.0, RATE)
+            .modulate(Box::new(envelope), 2.0);
+        let lfo = Lfo::new(Waveform::Sine, 3.0, RATE);
+        let mut ladder =
+            Ladder::new(500.0, 0.5, 1.0, RATE).modulate(Box::new(lfo), 1.0);
+
+        let square = |i: usize| {
+            if (i / 20).is_multiple_of(2) {
+                1.0
+            } else {
+                -1.0
+            }
+        };
+        for filter in &mut [&mut svf as &mut dyn Filter, &mut ladder] {
+            let first = run(*filter, square);
+            filter.reset();
+            assert_eq!(run(*filter, square), first);
+        }
+    }
+    #[test]
+    fn modulator_released() {
+        let envelope = EnvelopeGenerator::adsr(0, 0, 1.0, 10, Curve::Linear);
+        let mut filter =
+            StateVariable::new(Mode::LowPass, 500.0, FRAC_1_SQRT_2, RATE)
+                .modulate(Box::new(envelope), 1.0);
+        (0..20).for_each(|_| filter.tick());
+        assert_eq!(filter.cutoff.value, 1000.0);
+        filter.release();
+        (0..20).for_each(|_| filter.tick());
+        assert_eq!(filter.cutoff.value, 500.0);
+    }
+    #[test]
+    fn stable_under_fast_modulation() {
+        let lfo = Lfo::new(Waveform::Square, 200.0, RATE);
+        let mut svf = StateVariable::new(Mode::LowPass, 800.0, 20.0, RATE)
+            .modulate(Box::new(lfo), 2.0);
+        let lfo = Lfo::new(Waveform::Sawtooth, 150.0, RATE);
+        let mut ladder =
+            Ladder::new(800.0, 0.95, 1.0, RATE).modulate(Box::new(lfo), 2.0);
+
+        let square =
+            |i: usize| if (i / 7).is_multiple_of(2) { 1.0 } else { -1.0 };
+        assert!(run(&mut svf, square).iter().all(|v| v.abs() < 50.0));
+        assert!(run(&mut ladder, square).iter().all(|v| v.abs() < 5.0));
+    }
+}
@@ -0,0 +1,294 @@
+use super::{Filter, PerChannel, TAIL_LEVEL};
+use crate::sound::Ticks;
+
+// Jezar's Freeverb tunings, in samples at its original rate.
+const TUNING_RATE: f32 = 44100.0;
+const COMB_LENGTHS: [f32; 8] = [
+    1116.0, 1188.0, 1277.0, 1356.0, 1422.0, 1491.0, 1557.0, 1617.0,
+];
+const ALLPASS_LENGTHS: [f32; 4] = [556.0, 441.0, 341.0, 225.0];
+// Extra length of the right channel's lines, which decorrelates the two.
+const STEREO_SPREAD: f32 = 23.0;
+
+const ALLPASS_FEEDBACK: f32 = 0.5;
+// Keeps the sum of the combs near the level of the input.
+const INPUT_GAIN: f32 = 0.015;
+const WET_GAIN: f32 = 3.0;
+const SCALE_ROOM: f32 = 0.28;
+const OFFSET_ROOM: f32 = 0.7;
+const SCALE_DAMPING: f32 = 0.4;
+
+// Lowpass feedback comb, the source of the reverb's decay.
+struct Comb {
+    buffer: Vec<f32>,
+    pos: usize,
+    lowpass: f32,
+}
+
+impl Comb {
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let out = self.buffer[self.pos];
+        self.lowpass = out + damping * (self.lowpass - out);
+        self.buffer[self.pos] = input + feedback * self.lowpass;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        out
+    }
+}
+
+// Schroeder allpass, which thickens the echoes into a smooth wash.
+struct Allpass {
+    buffer: Vec<f32>,
+    pos: usize,
+}
+
+impl Allpass {
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.buffer[self.pos];
+        self.buffer[self.pos] = input + ALLPASS_FEEDBACK * delayed;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        delayed - input
+    }
+}
+
+// Parallel combs followed by allpasses in series, for one side.
+struct Bank {
+    combs: Vec<Comb>,
+    allpasses: Vec<Allpass>,
+}
+
+impl Bank {
+    fn new(rate: Ticks, spread: f32) -> Bank {
+        let line = |length: f32| {
+            let scaled = (length + spread) * rate as f32 / TUNING_RATE;
+            vec![0.0; scaled.round().max(1.0) as usize]
+        };
+
+        Bank {
+            combs: COMB_LENGTHS
+                .iter()
+                .map(|length| Comb {
+                    buffer: line(*length),
+                    pos: 0,
+                    lowpass: 0.0,
+                })
+                .collect(),
+            allpasses: ALLPASS_LENGTHS
+                .iter()
+                .map(|length| Allpass {
+                    buffer: line(*length),
+                    pos: 0,
+                })
+                .collect(),
+        }
+    }
+
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let sum = self
+            .combs
+            .iter_mut()
+            .map(|comb| comb.process(input, feedback, damping))
+            .sum();
+        self.allpasses
+            .iter_mut()
+            .fold(sum, |val, allpass| allpass.process(val))
+    }
+
+    // Until the longest comb has decayed by 60 dB and left the allpasses.
+    fn tail(&self, feedback: f32) -> Ticks {
+        let longest = self.combs.iter().map(|c| c.buffer.len()).max();
+        let diffusion: usize =
+            self.allpasses.iter().map(|a| a.buffer.len()).sum();
+        let decay =
+            longest.unwrap_or(0) as f32 * TAIL_LEVEL.ln() / feedback.ln();
+        decay.ceil() as Ticks + diffusion as Ticks
+    }
+}
+
+/// Stereo reverb after Freeverb, a Schroeder reverberator of eight
+/// lowpass feedback combs and four allpasses per side. The channels are
+/// mixed into both sides, and even channels play the left side and odd
+/// channels the right. It works per sound, or on the mix of every sound as
+/// a master bus.
+pub struct Reverb {
+    room_size: f32,
+    damping: f32,
+    width: f32,
+    mix: f32,
+    rate: Ticks,
+    banks: [Bank; 2],
+    inputs: PerChannel<f32>,
+    wet: [f32; 2],
+}
+
+impl Reverb {
+    pub fn new(rate: Ticks) -> Reverb {
+        Reverb {
+            room_size: 0.5,
+            damping: 0.5,
+            width: 1.0,
+            mix: 0.25,
+            rate,
+            banks: Reverb::banks(rate),
+            inputs: PerChannel::new(),
+            wet: [0.0; 2],
+        }
+    }
+
+    /// From 0 for a small room to 1 for a hall, setting how long the reverb
+    /// rings.
+    pub fn room_size(mut self, room_size: f32) -> Reverb {
+        self.room_size = room_size.clamp(0.0, 1.0);
+        self
+    }
+
+    /// How quickly the high end dies away relative to the low end, from 0
+    /// for hard walls to 1 for soft ones.
+    pub fn damping(mut self, damping: f32) -> Reverb {
+        self.damping = damping.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Stereo width of the reverb, from 0 for the same on every channel to
+    /// 1 for fully separate sides.
+    pub fn width(mut self, width: f32) -> Reverb {
+        self.width = width.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Share of the output taken by the reverb, from 0 for the dry sound
+    /// alone to 1 for the reverb alone.
+    pub fn mix(mut self, mix: f32) -> Reverb {
+        self.mix = mix.clamp(0.0, 1.0);
+        self
+    }
+
+    fn banks(rate: Ticks) -> [Bank; 2] {
+        [Bank::new(rate, 0.0), Bank::new(rate, STEREO_SPREAD)]
+    }
+
+    fn feedback(&self) -> f32 {
+        self.room_size * SCALE_ROOM + OFFSET_ROOM
+    }
+
+    // Runs the tick just finished through both sides. It waits for every
+    // channel because each side takes all of them, so the reverb comes a
+    // tick late.
+    fn process(&mut self) {
+        let inputs = &self.inputs.states;
+        if inputs.is_empty() {
+            return;
+        }
+        let input = inputs.iter().sum::<f32>() / inputs.len() as f32;
+        let feedback = self.feedback();
+        let damping = self.damping * SCALE_DAMPING;
+
+        for (wet, bank) in self.wet.iter_mut().zip(&mut self.banks) {
+            *wet =
+                WET_GAIN * bank.process(input * INPUT_GAIN, feedback, damping);
+        }
+    }
+}
+
+impl Filter for Reverb {
+    fn apply(&mut self, val: f32, _: Ticks, channel: u32) -> f32 {
+        *self.inputs.get_mut(channel) = val;
+
+        let (own, other) = if channel.is_multiple_of(2) {
+            (self.wet[0], self.wet[1])
+        } else {
+            (self.wet[1], self.wet[0])
+        };
+        let wet =
+            own * (1.0 + self.width) / 2.0 + other * (1.0 - self.width) / 2.0;
+        (1.0 - self.mix) * val + self.mix * wet
+    }
+
+    fn tick(&mut self) {
+        self.process();
+    }
+
+    fn reset(&mut self) {
+        self.banks = Reverb::banks(self.rate);
+        self.inputs.reset();
+        self.wet = [0.0; 2];
+    }
+
+    fn tail(&self) -> Ticks {
+        let feedback = self.feedback();
+        1 + self
+            .banks
+            .iter()
+            .map(|bank| bank.tail(feedback))
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: Ticks = 8000;
+
+    // Runs an impulse on the left channel through the reverb, returning
+    // both channels.
+    fn impulse(reverb: &mut Reverb, ticks: Ticks) -> (Vec<f32>, Vec<f32>) {
+        (0..ticks)
+            .map(|tick| {
+                let input = if tick == 0 { 1.0 } else { 0.0 };
+                let frame =
+                    (reverb.apply(input, tick, 0), reverb.apply(0.0, tick, 1));
+                reverb.tick();
+                frame
+            })
+            .unzip()
+    }
+
+    fn energy(values: &[f32]) -> f32 {
+        values.iter().map(|v| v * v).sum()
+    }
+
+    #[test]
+    fn reverberates() {
+        let mut reverb = Reverb::new(RATE).mix(1.0);
+        let (left, right) = impulse(&mut reverb, 8000);
+        // Nothing comes back before the shortest comb.
+        assert!(left[..200].iter().all(|v| *v == 0.0));
+        assert!(energy(&left[..2000]) > 0.0 && energy(&right[..2000]) > 0.0);
+        assert!(energy(&left[4000..]) < energy(&left[..4000]) / 100.0);
+        assert!(left.iter().chain(&right).all(|v| v.abs() < 1.0));
+    }
+    #[test]
+    fn dry_ok() {
+        let mut reverb = Reverb::new(RATE).mix(0.0);
+        let (left, right) = impulse(&mut reverb, 100);
+        assert_eq!(left[0], 1.0);
+        assert!(left[1..].iter().chain(&right).all(|v| *v == 0.0));
+    }
+    #[test]
+    fn width_ok() {
+        let mut narrow = Reverb::new(RATE).width(0.0).mix(1.0);
+        let (left, right) = impulse(&mut narrow, 2000);
+        assert_eq!(left, right);
+
+        let mut wide = Reverb::new(RATE).width(1.0).mix(1.0);
+        let (left, right) = impulse(&mut wide, 2000);
+        assert_ne!(left, right);
+    }
+    #[test]
+    fn room_size_ok() {
+        let mut small = Reverb::new(RATE).room_size(0.0).mix(1.0);
+        let mut large = Reverb::new(RATE).room_size(1.0).mix(1.0);
+        assert!(large.tail() > small.tail());
+        let (small_out, _) = impulse(&mut small, 8000);
+        let (large_out, _) = impulse(&mut large, 8000);
+        assert!(energy(&large_out[4000..]) > energy(&small_out[4000..]));
+    }
+    #[test]
+    fn reset_ok() {
+        let mut reverb = Reverb::new(RATE).mix(1.0);
+        let first = impulse(&mut reverb, 1000);
+        reverb.reset();
+        assert_eq!(impulse(&mut reverb, 1000), first);
+    }
+}
@@ -0,0 +1,425 @@
+use super::{
+    config::SoundConfigCollection,
+    duration_to_ticks,
+    envelope::EnvelopeGenerator,
+    filter::{Filter, FilterCollection},
+    Sound, Ticker, Ticks, MAX_PHASE,
+};
+use crate::{
+    error::{Error, Kind},
+    hwp::HardwareParams,
+    Result,
+};
+use alsa::pcm::IoFormat;
+use std::time::Duration;
+
+/// One sine oscillator of an FM voice. A carrier's level scales what it adds
+/// to the output, while a modulator's level is its modulation index in
+/// radians.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operator {
+    ratio: f32,
+    detune: f32,
+    level: f32,
+    feedback: f32,
+    envelope: EnvelopeGenerator,
+}
+
+impl Operator {
+    /// An operator running at `ratio` times the voice frequency.
+    pub fn new(ratio: f32) -> Operator {
+        Operator {
+            ratio,
+            detune: 0.0,
+            level: 1.0,
+            feedback: 0.0,
+            envelope: EnvelopeGenerator::constant(1.0),
+        }
+    }
+
+    /// Offset in hertz added after the ratio is applied.
+    pub fn detune(mut self, detune: f32) -> Operator {
+        self.detune = detune;
+        self
+    }
+
+    pub fn level(mut self, level: f32) -> Operator {
+        self.level = level;
+        self
+    }
+
+    /// How strongly the operator modulates itself, as a modulation index.
+    pub fn feedback(mut self, feedback: f32) -> Operator {
+        self.feedback = feedback;
+        self
+    }
+
+    /// Shapes the level over the note. The envelope is released along with
+    /// the voice.
+    pub fn envelope(mut self, envelope: EnvelopeGenerator) -> Operator {
+        self.envelope = envelope;
+        self
+    }
+}
+
+fn invalid() -> Error {
+    Error::new("Invalid FM algorithm", Kind::Zinnia)
+}
+
+fn check_count(operators: usize) -> Result<()> {
+    if operators == 0 {
+        Err(invalid())
+    } else {
+        Ok(())
+    }
+}
+
+/// How the operators of a voice are connected. Following the DX
+/// convention, an operator may only be modulated by operators with a
+/// higher index, so each sample is computed from the last operator down.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Algorithm {
+    carriers: Vec<usize>,
+    modulators: Vec<Vec<usize>>,
+}
+
+impl Algorithm {
+    /// `modulations` are `(modulator, target)` pairs between `operators`
+    /// operators. Fails if a modulator does not have a higher index than its
+    /// target, an index is out of range or there are no carriers.
+    pub fn new(
+        operators: usize,
+        carriers: &[usize],
+        modulations: &[(usize, usize)],
+    ) -> Result<Algorithm> {
+        if carriers.is_empty() || carriers.iter().any(|c| *c >= operators) {
+            return Err(invalid());
+        }
+
+        let mut modulators = vec![Vec::new(); operators];
+        for (from, to) in modulations {
+            if *from >= operators || from <= to {
+                return Err(invalid());
+            }
+            modulators[*to].push(*from);
+        }
+
+        Ok(Algorithm {
+            carriers: carriers.to_vec(),
+            modulators,
+        })
+    }
+
+    /// Operator 0 is the carrier, modulated by operator 1, which is
+    /// modulated by operator 2 and so on. Fails if there are no operators,
+    /// as do the other presets.
+    pub fn stack(operators: usize) -> Result<Algorithm> {
+        check_count(operators)?;
+        Ok(Algorithm {
+            carriers: vec![0],
+            modulators: (0..operators)
+                .map(|op| (op + 1..operators).take(1).collect())
+                .collect(),
+        })
+    }
+
+    /// Every operator is an unmodulated carrier, as in an organ.
+    pub fn parallel(operators: usize) -> Result<Algorithm> {
+        check_count(operators)?;
+        Ok(Algorithm {
+            carriers: (0..operators).collect(),
+            modulators: vec![Vec::new(); operators],
+        })
+    }
+
+    /// Even operators are carriers, each modulated by the operator after it.
+    pub fn pairs(operators: usize) -> Result<Algorithm> {
+        check_count(operators)?;
+        Ok(Algorithm {
+            carriers: (0..operators).step_by(2).collect(),
+            modulators: (0..operators)
+                .map(|op| {
+                    if op % 2 == 0 && op + 1 < operators {
+                        vec![op + 1]
+                    } else {
+                        Vec::new()
+                    }
+                })
+                .collect(),
+        })
+    }
+
+    pub fn operators(&self) -> usize {
+        self.modulators.len()
+    }
+}
+
+// Per channel state of one operator.
+#[derive(Clone)]
+struct OperatorState {
+    phase: f32,
+    step: f32,
+    output: f32,
+    previous: f32,
+}
+
+/// Frequency modulation voice. Modulation is applied to the phase of the
+/// target, as on the DX synthesizers, and the carriers are mixed evenly.
+pub struct FmVoice {
+    operators: Vec<Operator>,
+    algorithm: Algorithm,
+    state: Vec<Vec<OperatorState>>,
+    amplitude: Vec<f32>,
+    filters: FilterCollection,
+    ticker: Ticker,
+}
+
+impl FmVoice {
+    pub fn new<T>(
+        operators: Vec<Operator>,
+        algorithm: Algorithm,
+        config: &SoundConfigCollection,
+        duration: Duration,
+        hwp: &HardwareParams<T>,
+    ) -> Result<FmVoice>
+    where
+        T: IoFormat,
+    {
+        let d = duration_to_ticks(duration, hwp.rate());
+        FmVoice::with_ticks(operators, algorithm, config, d, hwp.rate())
+    }
+
+    /// Fails if `algorithm` is not for as many operators as given.
+    pub fn with_ticks(
+        operators: Vec<Operator>,
+        algorithm: Algorithm,
+        config: &SoundConfigCollection,
+        ticks: Ticks,
+        rate: Ticks,
+    ) -> Result<FmVoice> {
+        if operators.len() != algorithm.operators() {
+            return Err(Error::new(
+                "Operator count does not match algorithm",
+                Kind::Zinnia,
+            ));
+        }
+
+        let state = config
+            .iter()
+            .map_freq(|freq| freq)
+            .zip(config.iter().map_phase(|phase| phase))
+            .map(|(freq, phase)| {
+                operators
+                    .iter()
+                    .map(|op| OperatorState {
+                        phase,
+                        step: MAX_PHASE * (freq * op.ratio + op.detune)
+                            / rate as f32,
+                        output: 0.0,
+                        previous: 0.0,
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Ok(FmVoice {
+            operators,
+            algorithm,
+            state,
+            amplitude: config.iter().map_amplitude(|amp| amp).collect(),
+            filters: FilterCollection::new(),
+            ticker: Ticker::new(ticks),
+        })
+    }
+
+    pub fn add_filter(&mut self, filter: Box<dyn Filter>) {
+        self.filters.add_filter(filter);
+    }
+}
+
+impl Sound for FmVoice {
+    fn generate(&mut self, channel: u32) -> f32 {
+        if self.ticker.is_ringing() {
+            return self.filters.apply(0.0, self.ticker.tick_count, channel);
+        }
+
+        let ch = channel as usize;
+        let tick = self.ticker.tick_count;
+        let state = &mut self.state[ch];
+
+        for (idx, op) in self.operators.iter().enumerate().rev() {
+            let modulation = self.algorithm.modulators[idx]
+                .iter()
+                .map(|m| state[*m].output)
+                .sum::<f32>();
+
+            // Averaging the last two outputs tames the feedback loop.
+            let s = &mut state[idx];
+            let feedback = op.feedback * (s.output + s.previous) / 2.0;
+            let out = (s.phase + modulation + feedback).sin()
+                * op.level
+                * op.envelope.level();
+
+            s.previous = s.output;
+            s.output = out;
+            s.phase = (s.phase + s.step) % MAX_PHASE;
+        }
+
+        let carriers = &self.algorithm.carriers;
+        let res = carriers.iter().map(|c| state[*c].output).sum::<f32>()
+            / carriers.len() as f32
+            * self.amplitude[ch];
+        self.filters.apply(res, tick, channel)
+    }
+
+    fn tick(&mut self) {
+        for op in &mut self.operators {
+            op.envelope.tick();
+        }
+        self.filters.tick();
+        self.ticker.tick();
+    }
+
+    fn release(&mut self) {
+        for op in &mut self.operators {
+            op.envelope.release();
+        }
+        self.filters.release();
+    }
+
+    fn is_complete(&self) -> bool {
+        self.ticker.is_complete(&self.filters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sound::{
+        assert_close,
+        envelope::{Curve, Segment},
+        render,
+    };
+
+    const RATE: Ticks = 8000;
+    const FREQ: f32 = 220.0;
+
+    fn play(operators: Vec<Operator>, algorithm: Algorithm) -> Vec<f32> {
+        let config = SoundConfigCollection::with_configs(&[(FREQ, 0.0, 1.0)]);
+        let mut voice =
+            FmVoice::with_ticks(operators, algorithm, &config, 100, RATE)
+                .unwrap();
+        render(&mut voice, 100)
+    }
+
+    fn angle(i: usize, ratio: f32) -> f32 {
+        MAX_PHASE * FREQ * ratio * i as f32 / RATE as f32
+    }
+
+    #[test]
+    fn algorithm_ok() -> Result<()> {
+        assert_eq!(
+            Algorithm::stack(3)?.modulators,
+            vec![vec![1], vec![2], vec![]]
+        );
+        assert_eq!(Algorithm::pairs(3)?.carriers, vec![0, 2]);
+        assert_eq!(
+            Algorithm::pairs(3)?.modulators,
+            vec![vec![1], vec![], vec![]]
+        );
+        let alg = Algorithm::new(3, &[0], &[(1, 0), (2, 0)])?;
+        assert_eq!(alg.modulators, vec![vec![1, 2], vec![], vec![]]);
+        assert!(Algorithm::new(2, &[0], &[(0, 1)]).is_err());
+        assert!(Algorithm::new(2, &[0], &[(2, 0)]).is_err());
+        assert!(Algorithm::new(2, &[], &[]).is_err());
+        assert!(Algorithm::stack(0).is_err());
+        assert!(Algorithm::parallel(0).is_err());
+        assert!(Algorithm::pairs(0).is_err());
+        Ok(())
+    }
+    #[test]
+    fn operator_count_checked() -> Result<()> {
+        let config = SoundConfigCollection::with_configs(&[(FREQ, 0.0, 1.0)]);
+        let voice = FmVoice::with_ticks(
+            vec![Operator::new(1.0)],
+            Algorithm::stack(2)?,
+            &config,
+            100,
+            RATE,
+        );
+        assert!(voice.is_err());
+        Ok(())
+    }
+    #[test]
+    fn single_operator_sine() -> Result<()> {
+        let values = play(vec![Operator::new(1.0)], Algorithm::stack(1)?);
+        assert_close(&values, |i| angle(i, 1.0).sin());
+        Ok(())
+    }
+    #[test]
+    fn stack_ok() -> Result<()> {
+        let ops = vec![Operator::new(1.0), Operator::new(2.0).level(1.5)];
+        let values = play(ops, Algorithm::stack(2)?);
+        assert_close(&values, |i| {
+            (angle(i, 1.0) + 1.5 * angle(i, 2.0).sin()).sin()
+        });
+        Ok(())
+    }
+    #[test]
+    fn parallel_ok() -> Result<()> {
+        let ops = vec![
+            Operator::new(1.0),
+            Operator::new(3.0).detune(5.0).level(0.5),
+        ];
+        let values = play(ops, Algorithm::parallel(2)?);
+        assert_close(&values, |i| {
+            let detune = MAX_PHASE * 5.0 * i as f32 / RATE as f32;
+            (angle(i, 1.0).sin() + 0.5 * (angle(i, 3.0) + detune).sin()) / 2.0
+        });
+        Ok(())
+    }
+    #[test]
+    fn envelope_ok() -> Result<()> {
+        let env = EnvelopeGenerator::one_shot(&[Segment::new(
+            1.0,
+            50,
+            Curve::Linear,
+        )]);
+        let ops = vec![Operator::new(1.0), Operator::new(1.0).envelope(env)];
+        let values = play(ops, Algorithm::stack(2)?);
+        assert_close(&values, |i| {
+            let index = (i as f32 / 50.0).min(1.0);
+            (angle(i, 1.0) + index * angle(i, 1.0).sin()).sin()
+        });
+        Ok(())
+    }
+    #[test]
+    fn release_ok() -> Result<()> {
+        let config = SoundConfigCollection::with_configs(&[(FREQ, 0.0, 1.0)]);
+        let env = EnvelopeGenerator::adsr(0, 0, 1.0, 10, Curve::Linear);
+        let mut voice = FmVoice::with_ticks(
+            vec![Operator::new(1.0).envelope(env)],
+            Algorithm::stack(1)?,
+            &config,
+            100,
+            RATE,
+        )?;
+        render(&mut voice, 10);
+        voice.release();
+        let values = render(&mut voice, 20);
+        assert_close(&values, |i| {
+            let level = 1.0 - (i as f32 / 10.0).min(1.0);
+            level * angle(i + 10, 1.0).sin()
+        });
+        Ok(())
+    }
+    #[test]
+    fn feedback_ok() -> Result<()> {
+        let plain = play(vec![Operator::new(1.0)], Algorithm::stack(1)?);
+        let fed =
+            play(vec![Operator::new(1.0).feedback(1.2)], Algorithm::stack(1)?);
+        assert_ne!(plain, fed);
+        assert!(fed.iter().all(|v| v.abs() <= 1.0));
+        Ok(())
+    }
+}
@@ -0,0 +1,254 @@
+use super::{
+    duration_to_ticks,
+    filter::{Filter, FilterCollection},
+    Sound, Ticker, Ticks,
+};
+use crate::hwp::HardwareParams;
+use alsa::pcm::IoFormat;
+use std::time::Duration;
+
+// Brings the sum of the pink noise filter outputs roughly back to ±1.
+const PINK_SCALE: f32 = 0.11;
+const BROWN_LEAK: f32 = 0.02;
+const BROWN_SCALE: f32 = 3.5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Color {
+    /// Equal power at every frequency.
+    White,
+    /// Power falling by 3 dB per octave.
+    Pink,
+    /// Power falling by 6 dB per octave.
+    Brown,
+}
+
+// xorshift64* generator, seeded through splitmix64 so that nearby seeds
+// give unrelated sequences.
+pub(super) struct Rng(u64);
+
+impl Rng {
+    pub(super) fn new(seed: u64) -> Rng {
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+
+        // An all zero state would only ever produce zeros.
+        Rng(if z == 0 { 1 } else { z })
+    }
+
+    // Uniform value in [-1, 1).
+    pub(super) fn next(&mut self) -> f32 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        let bits = self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 40;
+        bits as f32 / (1 << 23) as f32 - 1.0
+    }
+}
+
+struct ChannelState {
+    rng: Rng,
+    filter: [f32; 7],
+}
+
+/// Deterministic noise source. Every channel draws from its own generator,
+/// derived from the seed, so channels are uncorrelated but the whole output
+/// is reproducible from the seed alone. Values stay roughly within ±1
+/// before `amplitude` is applied.
+pub struct Noise {
+    color: Color,
+    channels: Vec<ChannelState>,
+    amplitude: Vec<f32>,
+    filters: FilterCollection,
+    ticker: Ticker,
+}
+
+impl Noise {
+    pub fn new<T>(
+        color: Color,
+        seed: u64,
+        amplitude: &[f32],
+        duration: Duration,
+        hwp: &HardwareParams<T>,
+    ) -> Noise
+    where
+        T: IoFormat,
+    {
+        let d = duration_to_ticks(duration, hwp.rate());
+        Noise::with_ticks(color, seed, amplitude, d)
+    }
+
+    /// Creates a noise source lasting exactly `ticks`, with one channel per
+    /// entry of `amplitude`.
+    pub fn with_ticks(
+        color: Color,
+        seed: u64,
+        amplitude: &[f32],
+        ticks: Ticks,
+    ) -> Noise {
+        Noise {
+            color,
+            channels: (0..amplitude.len() as u64)
+                .map(|ch| ChannelState {
+                    rng: Rng::new(seed.wrapping_add(ch)),
+                    filter: [0.0; 7],
+                })
+                .collect(),
+            amplitude: amplitude.to_vec(),
+            filters: FilterCollection::new(),
+            ticker: Ticker::new(ticks),
+        }
+    }
+
+    pub fn add_filter(&mut self, filter: Box<dyn Filter>) {
+        self.filters.add_filter(filter);
+    }
+}
+
+impl Sound for Noise {
+    fn generate(&mut self, channel: u32) -> f32 {
+        if self.ticker.is_ringing() {
+            return self.filters.apply(0.0, self.ticker.tick_count, channel);
+        }
+
+        let ch = channel as usize;
+        let state = &mut self.channels[ch];
+        let white = state.rng.next();
+        let b = &mut state.filter;
+
+        let val = match self.color {
+            Color::White => white,
+            Color::Pink => {
+                // Paul Kellet's refined pink noise filter.
+                b[0] = 0.99886 * b[0] + white * 0.055_517_9;
+                b[1] = 0.99332 * b[1] + white * 0.075_075_9;
+                b[2] = 0.96900 * b[2] + white * 0.153_852;
+                b[3] = 0.86650 * b[3] + white * 0.310_485_6;
+                b[4] = 0.55000 * b[4] + white * 0.532_952_2;
+                b[5] = -0.7616 * b[5] - white * 0.016_898;
+                let pink = b[..6].iter().sum::<f32>() + b[6] + white * 0.5362;
+                b[6] = white * 0.115_926;
+                pink * PINK_SCALE
+            }
+            Color::Brown => {
+                // Leaky integration keeps the random walk from drifting off.
+                b[0] = (b[0] + BROWN_LEAK * white) / (1.0 + BROWN_LEAK);
+                b[0] * BROWN_SCALE
+            }
+        };
+
+        let res = val * self.amplitude[ch];
+        self.filters.apply(res, self.ticker.tick_count, channel)
+    }
+
+    fn tick(&mut self) {
+        self.filters.tick();
+        self.ticker.tick();
+    }
+
+    fn release(&mut self) {
+        self.filters.release();
+    }
+
+    fn is_complete(&self) -> bool {
+        self.ticker.is_complete(&self.filters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sound::{max_amplitude, render, render_channels};
+
+    const SAMPLES: usize = 20000;
+
+    // Both channels of the noise, left then right.
+    fn play(color: Color, seed: u64) -> Vec<Vec<f32>> {
+        let mut sound =
+            Noise::with_ticks(color, seed, &[1.0, 1.0], SAMPLES as Ticks);
+        render_channels(&mut sound, 2, SAMPLES)
+    }
+
+    fn left(color: Color, seed: u64) -> Vec<f32> {
+        play(color, seed).remove(0)
+    }
+
+    fn mean_square(values: impl Iterator<Item = f32>) -> f32 {
+        let (sum, count) =
+            values.fold((0.0, 0), |(sum, count), v| (sum + v * v, count + 1));
+        sum / count as f32
+    }
+
+    // How much of the power sits in sample to sample changes: 2 for white
+    // noise, falling as the spectrum tilts towards low frequencies.
+    fn roughness(values: &[f32]) -> f32 {
+        let diff = values.windows(2).map(|w| w[1] - w[0]);
+        mean_square(diff) / mean_square(values.iter().copied())
+    }
+
+    #[test]
+    fn seed_reproducible() {
+        for color in &[Color::White, Color::Pink, Color::Brown] {
+            assert_eq!(play(*color, 7), play(*color, 7));
+            assert_ne!(play(*color, 7), play(*color, 8));
+        }
+    }
+    #[test]
+    fn channels_uncorrelated() {
+        for color in &[Color::White, Color::Pink, Color::Brown] {
+            let mut values = play(*color, 1);
+            let (right, left) = (values.remove(1), values.remove(0));
+            assert_ne!(left, right);
+
+            let cross =
+                left.iter().zip(&right).map(|(l, r)| l * r).sum::<f32>();
+            let norm = (mean_square(left.into_iter())
+                * mean_square(right.into_iter()))
+            .sqrt();
+            assert!((cross / SAMPLES as f32 / norm).abs() < 0.1);
+        }
+    }
+    #[test]
+    fn white_ok() {
+        let values = left(Color::White, 0);
+        let mean = values.iter().sum::<f32>() / SAMPLES as f32;
+        assert!(mean.abs() < 0.02);
+        assert!(values.iter().all(|v| (-1.0..1.0).contains(v)));
+        // Uniform on [-1, 1) has a mean square of 1/3.
+        assert!((mean_square(values.into_iter()) - 1.0 / 3.0).abs() < 0.02);
+    }
+    #[test]
+    fn spectrum_tilt_ok() {
+        let white = roughness(&left(Color::White, 3));
+        let pink = roughness(&left(Color::Pink, 3));
+        let brown = roughness(&left(Color::Brown, 3));
+        assert!((white - 2.0).abs() < 0.1);
+        assert!(pink < white && brown < pink / 4.0);
+    }
+    #[test]
+    fn level_ok() {
+        for color in &[Color::Pink, Color::Brown] {
+            let values = left(*color, 5);
+            assert!(values.iter().all(|v| v.abs() < 1.5));
+            assert!(mean_square(values.into_iter()) > 0.01);
+        }
+    }
+    #[test]
+    fn amplitude_and_duration_ok() {
+        let mut sound = Noise::with_ticks(Color::White, 0, &[0.0, 0.5], 1);
+        assert_eq!(sound.generate(0), 0.0);
+        assert!(sound.generate(1).abs() <= 0.5);
+        assert!(!sound.is_complete());
+        sound.tick();
+        assert!(sound.is_complete());
+    }
+    #[test]
+    fn sample_units() {
+        let full_scale = max_amplitude::<i16>() as f32;
+        let mut sound = Noise::with_ticks(Color::White, 0, &[full_scale], 100);
+        let values = render(&mut sound, 100);
+        assert!(values.iter().all(|v| v.abs() <= full_scale));
+        assert!(values.iter().any(|v| v.abs() > full_scale / 2.0));
+    }
+}
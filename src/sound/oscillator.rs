@@ -0,0 +1,272 @@
+use super::{
+    config::SoundConfigCollection,
+    duration_to_ticks,
+    filter::{Filter, FilterCollection},
+    Sound, Ticker, Ticks, MAX_PHASE,
+};
+use crate::hwp::HardwareParams;
+use alsa::pcm::IoFormat;
+use std::time::Duration;
+
+const MIN_PULSE_WIDTH: f32 = 0.01;
+const MAX_PULSE_WIDTH: f32 = 0.99;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Waveform {
+    Sine,
+    Square,
+    Sawtooth,
+    Triangle,
+    /// A square wave spending `width` of each period high, from 0.01 to
+    /// 0.99.
+    Pulse(f32),
+}
+
+/// Band-limited oscillator. Discontinuities are smoothed with polynomial
+/// band-limited steps (PolyBLEP) and the corners of the triangle with their
+/// integral (PolyBLAMP), which keeps aliasing low even at low sample rates.
+pub struct Oscillator {
+    waveform: Waveform,
+    phase: Vec<f32>,
+    step: Vec<f32>,
+    amplitude: Vec<f32>,
+    filters: FilterCollection,
+    ticker: Ticker,
+}
+
+impl Oscillator {
+    pub fn new<T>(
+        waveform: Waveform,
+        config: &SoundConfigCollection,
+        duration: Duration,
+        hwp: &HardwareParams<T>,
+    ) -> Oscillator
+    where
+        T: IoFormat,
+    {
+        let d = duration_to_ticks(duration, hwp.rate());
+        Oscillator::with_ticks(waveform, config, d, hwp.rate())
+    }
+
+    pub fn with_ticks(
+        waveform: Waveform,
+        config: &SoundConfigCollection,
+        ticks: Ticks,
+        rate: Ticks,
+    ) -> Oscillator {
+        let waveform = match waveform {
+            Waveform::Pulse(width) => {
+                Waveform::Pulse(width.clamp(MIN_PULSE_WIDTH, MAX_PULSE_WIDTH))
+            }
+            waveform => waveform,
+        };
+
+        // Phase is kept as a fraction of a period rather than in radians.
+        Oscillator {
+            waveform,
+            phase: config
+                .iter()
+                .map_phase(|phase| (phase / MAX_PHASE).rem_euclid(1.0))
+                .collect(),
+            step: config.iter().map_freq(|freq| freq / rate as f32).collect(),
+            amplitude: config.iter().map_amplitude(|amp| amp).collect(),
+            filters: FilterCollection::new(),
+            ticker: Ticker::new(ticks),
+        }
+    }
+
+    pub fn add_filter(&mut self, filter: Box<dyn Filter>) {
+        self.filters.add_filter(filter);
+    }
+}
+
+impl Sound for Oscillator {
+    fn generate(&mut self, channel: u32) -> f32 {
+        if self.ticker.is_ringing() {
+            return self.filters.apply(0.0, self.ticker.tick_count, channel);
+        }
+
+        let ch = channel as usize;
+        let t = self.phase[ch];
+        let dt = self.step[ch];
+
+        let res = sample(self.waveform, t, dt) * self.amplitude[ch];
+
+        self.phase[ch] = (t + dt).rem_euclid(1.0);
+        self.filters.apply(res, self.ticker.tick_count, channel)
+    }
+
+    fn tick(&mut self) {
+        self.filters.tick();
+        self.ticker.tick();
+    }
+
+    fn release(&mut self) {
+        self.filters.release();
+    }
+
+    fn is_complete(&self) -> bool {
+        self.ticker.is_complete(&self.filters)
+    }
+}
+
+// Value of `waveform` at phase `t`, advancing by `dt` each sample.
+pub(super) fn sample(waveform: Waveform, t: f32, dt: f32) -> f32 {
+    match waveform {
+        Waveform::Sine => (t * MAX_PHASE).sin(),
+        Waveform::Sawtooth => 2.0 * t - 1.0 - poly_blep(t, dt),
+        Waveform::Square => pulse(t, dt, 0.5),
+        Waveform::Pulse(width) => pulse(t, dt, width),
+        Waveform::Triangle => {
+            let naive = 1.0 - 4.0 * (t - 0.5).abs();
+            // The slope changes by 8 at each corner, rising at 0 and falling
+            // at half a period.
+            naive
+                + 4.0
+                    * dt
+                    * (poly_blamp(t, dt) - poly_blamp((t + 0.5) % 1.0, dt))
+        }
+    }
+}
+
+fn pulse(t: f32, dt: f32, width: f32) -> f32 {
+    let naive = if t < width { 1.0 } else { -1.0 };
+    naive + poly_blep(t, dt) - poly_blep((t - width).rem_euclid(1.0), dt)
+}
+
+// Difference between a band-limited and a naive rising step of 2 at t = 0,
+// within one sample either side.
+fn poly_blep(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let t = t / dt;
+        2.0 * t - t * t - 1.0
+    } else if t > 1.0 - dt {
+        let t = (t - 1.0) / dt;
+        t * t + 2.0 * t + 1.0
+    } else {
+        0.0
+    }
+}
+
+// Integral of `poly_blep`, correcting a corner where the slope rises by 2
+// per sample.
+fn poly_blamp(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let t = t / dt - 1.0;
+        -t * t * t / 3.0
+    } else if t > 1.0 - dt {
+        let t = (t - 1.0) / dt + 1.0;
+        t * t * t / 3.0
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sound::render;
+    use std::f32::consts::PI;
+
+    const RATE: Ticks = 8000;
+    const SAMPLES: usize = 8000;
+
+    fn oscillator(waveform: Waveform, freq: f32) -> Oscillator {
+        let config = SoundConfigCollection::with_configs(&[(freq, 0.0, 1.0)]);
+        Oscillator::with_ticks(waveform, &config, SAMPLES as Ticks, RATE)
+    }
+
+    fn play(waveform: Waveform, freq: f32) -> Vec<f32> {
+        let mut sound = oscillator(waveform, freq);
+        render(&mut sound, SAMPLES)
+    }
+
+    fn naive(waveform: Waveform, freq: f32) -> Vec<f32> {
+        (0..SAMPLES)
+            .map(|n| {
+                let t = (n as f32 * freq / RATE as f32) % 1.0;
+                match waveform {
+                    Waveform::Sawtooth => 2.0 * t - 1.0,
+                    Waveform::Square if t < 0.5 => 1.0,
+                    Waveform::Square => -1.0,
+                    _ => 1.0 - 4.0 * (t - 0.5).abs(),
+                }
+            })
+            .collect()
+    }
+
+    // Power left after removing DC and every harmonic of `freq` below
+    // Nyquist, which is what folded back from above it. `freq` must be a
+    // whole number of hertz so each harmonic falls exactly on a DFT bin.
+    fn alias_power(values: &[f32], freq: f32) -> f32 {
+        let n = values.len() as f32;
+        let mean = values.iter().sum::<f32>() / n;
+        let total = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>();
+        let harmonics = (1..)
+            .map(|k| k as f32 * freq)
+            .take_while(|f| *f < RATE as f32 / 2.0)
+            .map(|f| {
+                let (re, im) = values.iter().enumerate().fold(
+                    (0.0, 0.0),
+                    |(re, im), (i, v)| {
+                        let angle = 2.0 * PI * f * i as f32 / RATE as f32;
+                        (re + v * angle.cos(), im - v * angle.sin())
+                    },
+                );
+                2.0 * (re * re + im * im) / n
+            })
+            .sum::<f32>();
+        (total - harmonics) / n
+    }
+
+    #[test]
+    fn less_aliasing_than_naive() {
+        let freq = 1234.0;
+        for waveform in
+            &[Waveform::Sawtooth, Waveform::Square, Waveform::Triangle]
+        {
+            let blep = alias_power(&play(*waveform, freq), freq);
+            let naive = alias_power(&naive(*waveform, freq), freq);
+            assert!(blep < naive / 20.0, "{:?}", waveform);
+        }
+    }
+    #[test]
+    fn pulse_width_ok() {
+        for width in &[0.1, 0.25, 0.5, 0.8] {
+            let output = play(Waveform::Pulse(*width), 100.0);
+            let mean = output.iter().sum::<f32>() / SAMPLES as f32;
+            assert!((mean - (2.0 * width - 1.0)).abs() < 0.01);
+        }
+        assert_eq!(
+            oscillator(Waveform::Pulse(2.0), 100.0).waveform,
+            Waveform::Pulse(MAX_PULSE_WIDTH)
+        );
+    }
+    #[test]
+    fn phase_and_amplitude_ok() {
+        let config = SoundConfigCollection::with_configs(&[
+            (100.0, 0.0, 1.0),
+            (100.0, PI, 0.5),
+        ]);
+        let mut sound =
+            Oscillator::with_ticks(Waveform::Triangle, &config, 2, RATE);
+        assert!((sound.generate(0) + 1.0).abs() < 0.05);
+        assert!((sound.generate(1) - 0.5).abs() < 0.01);
+        sound.tick();
+        assert!(!sound.is_complete());
+        sound.tick();
+        assert!(sound.is_complete());
+    }
+    #[test]
+    fn output_bounded() {
+        for waveform in &[
+            Waveform::Sine,
+            Waveform::Sawtooth,
+            Waveform::Square,
+            Waveform::Triangle,
+            Waveform::Pulse(0.3),
+        ] {
+            assert!(play(*waveform, 3000.0).iter().all(|v| v.abs() <= 1.1));
+        }
+    }
+}
@@ -0,0 +1,333 @@
+use super::{
+    config::SoundConfigCollection,
+    duration_to_ticks,
+    filter::{Filter, FilterCollection},
+    noise::Rng,
+    Sound, Ticker, Ticks,
+};
+use crate::hwp::HardwareParams;
+use alsa::pcm::IoFormat;
+use std::time::Duration;
+
+// Smallest fractional delay left to the allpass, which behaves poorly close
+// to zero.
+const MIN_FRACTION: f32 = 0.1;
+// Amplitude reached after the decay time, -60 dB.
+const DECAY_LEVEL: f32 = 0.001;
+
+/// How a `PluckedString` is plucked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pluck {
+    damping: f32,
+    position: f32,
+    decay: f32,
+    seed: u64,
+}
+
+impl Pluck {
+    pub fn new() -> Pluck {
+        Pluck {
+            damping: 0.5,
+            position: 0.0,
+            decay: 2.0,
+            seed: 0,
+        }
+    }
+
+    /// How quickly high harmonics die away relative to the fundamental, from
+    /// 0 for a bright string to 1 for a dull one.
+    pub fn damping(mut self, damping: f32) -> Pluck {
+        self.damping = damping.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Where the string is plucked, as a fraction of its length from the
+    /// bridge. Harmonics with a node at that point are not excited, so 0.5
+    /// has no even harmonics. 0 excites every harmonic.
+    pub fn position(mut self, position: f32) -> Pluck {
+        self.position = position.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Seconds for the fundamental to decay by 60 dB, not counting the
+    /// extra loss from damping.
+    pub fn decay(mut self, decay: f32) -> Pluck {
+        self.decay = decay.max(f32::EPSILON);
+        self
+    }
+
+    /// Seed of the noise burst that excites the string.
+    pub fn seed(mut self, seed: u64) -> Pluck {
+        self.seed = seed;
+        self
+    }
+
+    // Coefficient of the loop's one-zero lowpass, whose delay at low
+    // frequencies is the coefficient itself in samples.
+    fn lowpass(&self) -> f32 {
+        self.damping / 2.0
+    }
+}
+
+impl Default for Pluck {
+    fn default() -> Self {
+        Pluck::new()
+    }
+}
+
+// Delay line of one channel, tuned with a first order allpass for the
+// fractional part of the period.
+struct StringState {
+    delay: Vec<f32>,
+    pos: usize,
+    gain: f32,
+    allpass: f32,
+    lowpass_prev: f32,
+    allpass_in: f32,
+    allpass_out: f32,
+}
+
+impl StringState {
+    fn new(pluck: &Pluck, freq: f32, rate: Ticks, seed: u64) -> StringState {
+        // The loop needs a sample of delay line besides the lowpass and the
+        // allpass, and is held to a second, which also catches a frequency
+        // of 0.
+        let shortest = 1.0 + pluck.lowpass() + MIN_FRACTION;
+        let period = (rate as f32 / freq).min(rate as f32).max(shortest);
+        let length = (period - pluck.lowpass() - MIN_FRACTION).floor();
+        let fraction = (period - pluck.lowpass() - length).max(MIN_FRACTION);
+
+        StringState {
+            delay: excitation(length as usize, pluck.position, seed),
+            pos: 0,
+            gain: DECAY_LEVEL.powf(period / (rate as f32 * pluck.decay)),
+            allpass: (1.0 - fraction) / (1.0 + fraction),
+            lowpass_prev: 0.0,
+            allpass_in: 0.0,
+            allpass_out: 0.0,
+        }
+    }
+
+    fn next(&mut self, lowpass: f32) -> f32 {
+        let out = self.delay[self.pos];
+
+        let lp = (1.0 - lowpass) * out + lowpass * self.lowpass_prev;
+        self.lowpass_prev = out;
+
+        let ap = self.allpass * lp + self.allpass_in
+            - self.allpass * self.allpass_out;
+        self.allpass_in = lp;
+        self.allpass_out = ap;
+
+        self.delay[self.pos] = ap * self.gain;
+        self.pos = (self.pos + 1) % self.delay.len();
+        out
+    }
+}
+
+// Noise burst filling one period, combed to imitate the pick position and
+// scaled to a peak of 1. The comb wraps around the period, which also
+// removes any DC.
+fn excitation(length: usize, position: f32, seed: u64) -> Vec<f32> {
+    let mut rng = Rng::new(seed);
+    let noise: Vec<f32> = (0..length).map(|_| rng.next()).collect();
+
+    let offset = (position * length as f32).round() as usize;
+    let burst: Vec<f32> = if offset == 0 || offset == length {
+        noise
+    } else {
+        (0..length)
+            .map(|i| noise[i] - noise[(i + length - offset) % length])
+            .collect()
+    };
+
+    let peak = burst.iter().fold(0.0f32, |acc, v| acc.max(v.abs()));
+    if peak > 0.0 {
+        burst.iter().map(|v| v / peak).collect()
+    } else {
+        burst
+    }
+}
+
+/// Karplus-Strong plucked string: a noise burst circulating in a delay line
+/// one period long, losing a little of its high end on every trip. Each
+/// channel is a separate string plucked with its own noise burst; the
+/// configured phase is not used. Frequencies are held between 1 Hz and the
+/// highest the delay line can play.
+pub struct PluckedString {
+    strings: Vec<StringState>,
+    lowpass: f32,
+    amplitude: Vec<f32>,
+    filters: FilterCollection,
+    ticker: Ticker,
+}
+
+impl PluckedString {
+    pub fn new<T>(
+        pluck: &Pluck,
+        config: &SoundConfigCollection,
+        duration: Duration,
+        hwp: &HardwareParams<T>,
+    ) -> PluckedString
+    where
+        T: IoFormat,
+    {
+        let d = duration_to_ticks(duration, hwp.rate());
+        PluckedString::with_ticks(pluck, config, d, hwp.rate())
+    }
+
+    pub fn with_ticks(
+        pluck: &Pluck,
+        config: &SoundConfigCollection,
+        ticks: Ticks,
+        rate: Ticks,
+    ) -> PluckedString {
+        PluckedString {
+            strings: config
+                .iter()
+                .map_freq(|freq| freq)
+                .enumerate()
+                .map(|(ch, freq)| {
+                    let seed = pluck.seed.wrapping_add(ch as u64);
+                    StringState::new(pluck, freq, rate, seed)
+                })
+                .collect(),
+            lowpass: pluck.lowpass(),
+            amplitude: config.iter().map_amplitude(|amp| amp).collect(),
+            filters: FilterCollection::new(),
+            ticker: Ticker::new(ticks),
+        }
+    }
+
+    pub fn add_filter(&mut self, filter: Box<dyn Filter>) {
+        self.filters.add_filter(filter);
+    }
+}
+
+impl Sound for PluckedString {
+    fn generate(&mut self, channel: u32) -> f32 {
+        if self.ticker.is_ringing() {
+            return self.filters.apply(0.0, self.ticker.tick_count, channel);
+        }
+
+        let ch = channel as usize;
+        let res = self.strings[ch].next(self.lowpass) * self.amplitude[ch];
+        self.filters.apply(res, self.ticker.tick_count, channel)
+    }
+
+    fn tick(&mut self) {
+        self.filters.tick();
+        self.ticker.tick();
+    }
+
+    fn release(&mut self) {
+        self.filters.release();
+    }
+
+    fn is_complete(&self) -> bool {
+        self.ticker.is_complete(&self.filters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sound::render;
+    use std::f32::consts::PI;
+
+    const RATE: Ticks = 8000;
+
+    fn play(pluck: &Pluck, freq: f32, samples: usize) -> Vec<f32> {
+        let config = SoundConfigCollection::with_configs(&[(freq, 0.0, 1.0)]);
+        let mut sound =
+            PluckedString::with_ticks(pluck, &config, samples as Ticks, RATE);
+        render(&mut sound, samples)
+    }
+
+    // Phase of the component at `freq` over `len` samples from `start`.
+    fn phase(values: &[f32], freq: f32, start: usize, len: usize) -> f32 {
+        let (re, im) = values[start..start + len].iter().enumerate().fold(
+            (0.0, 0.0),
+            |(re, im), (i, v)| {
+                let angle = 2.0 * PI * freq * (start + i) as f32 / RATE as f32;
+                (re + v * angle.cos(), im - v * angle.sin())
+            },
+        );
+        im.atan2(re)
+    }
+
+    fn energy(values: &[f32]) -> f32 {
+        values.iter().map(|v| v * v).sum()
+    }
+
+    #[test]
+    fn tuning_ok() {
+        // 440 Hz needs 18.18 samples per period, so rounding the delay line
+        // alone would be about 8 Hz out.
+        let freq = 440.0;
+        let values = play(&Pluck::new().decay(5.0), freq, 2000);
+        let drift =
+            phase(&values, freq, 1200, 400) - phase(&values, freq, 200, 400);
+        let drift = (drift + PI).rem_euclid(2.0 * PI) - PI;
+        let error = drift / (2.0 * PI) * RATE as f32 / 1000.0;
+        assert!(error.abs() < 1.0, "{} Hz out", error);
+    }
+    #[test]
+    fn near_nyquist_stable() {
+        let values = play(&Pluck::new(), 7000.0, 2000);
+        assert!(values.iter().all(|v| v.abs() <= 1.0));
+        assert!(energy(&values[1000..]) < energy(&values[..1000]));
+    }
+    #[test]
+    fn zero_freq_ok() {
+        let values = play(&Pluck::new(), 0.0, 100);
+        assert!(values.iter().all(|v| v.is_finite()));
+    }
+    #[test]
+    fn seed_reproducible() {
+        let pluck = Pluck::new().seed(3);
+        assert_eq!(play(&pluck, 220.0, 500), play(&pluck, 220.0, 500));
+        assert_ne!(
+            play(&pluck, 220.0, 500),
+            play(&pluck.clone().seed(4), 220.0, 500)
+        );
+    }
+    #[test]
+    fn decay_ok() {
+        let long = play(&Pluck::new().decay(2.0), 220.0, 4000);
+        let short = play(&Pluck::new().decay(0.2), 220.0, 4000);
+        assert!(energy(&long[3000..]) < energy(&long[..1000]));
+        assert!(energy(&short[3000..]) < energy(&long[3000..]) / 100.0);
+    }
+    #[test]
+    fn damping_ok() {
+        // Sample to sample changes carry the high end of the spectrum.
+        let roughness = |values: &[f32]| {
+            let diff: Vec<f32> =
+                values.windows(2).map(|w| w[1] - w[0]).collect();
+            energy(&diff) / energy(values)
+        };
+        let bright = play(&Pluck::new().damping(0.1), 220.0, 2000);
+        let dull = play(&Pluck::new().damping(1.0), 220.0, 2000);
+        assert!(roughness(&dull[1000..]) < roughness(&bright[1000..]));
+    }
+    #[test]
+    fn pick_position_ok() {
+        // A 21 sample period leaves 20 samples in the delay line, so the
+        // first 20 samples are the excitation itself.
+        let values = play(&Pluck::new().position(0.5), RATE as f32 / 21.0, 20);
+        let harmonic = |k: f32| {
+            let (re, im) = values.iter().enumerate().fold(
+                (0.0, 0.0),
+                |(re, im), (i, v)| {
+                    let angle = 2.0 * PI * k * i as f32 / 20.0;
+                    (re + v * angle.cos(), im + v * angle.sin())
+                },
+            );
+            (re * re + im * im).sqrt()
+        };
+        assert!(harmonic(0.0) < 0.001);
+        assert!(harmonic(2.0) < 0.001);
+        assert!(harmonic(1.0) > 0.1);
+    }
+}
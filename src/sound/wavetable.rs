@@ -0,0 +1,346 @@
+use super::{
+    config::SoundConfigCollection,
+    duration_to_ticks,
+    filter::{Filter, FilterCollection},
+    Sound, Ticker, Ticks, MAX_PHASE,
+};
+use crate::{
+    error::{Error, Kind},
+    hwp::HardwareParams,
+    Result,
+};
+use alsa::pcm::IoFormat;
+use std::{sync::Arc, time::Duration};
+
+const TABLE_SIZE: usize = 2048;
+
+// One frame, resynthesised at every mip level. Level 0 keeps every
+// harmonic the frame has, and each further level keeps half as many.
+struct Frame {
+    levels: Vec<Vec<f32>>,
+}
+
+/// A set of single-cycle frames, each stored as band-limited mip levels so
+/// it can be played at any pitch without aliasing. Building the levels is
+/// expensive, so a table is meant to be built once and shared between
+/// voices.
+pub struct Wavetable {
+    frames: Vec<Frame>,
+    harmonics: Vec<usize>,
+}
+
+impl Wavetable {
+    /// Builds a table from single-cycle `frames`, each at least two samples
+    /// long. Frames do not need to be the same length.
+    pub fn new(frames: &[&[f32]]) -> Result<Wavetable> {
+        if frames.is_empty() || frames.iter().any(|f| f.len() < 2) {
+            return Err(Error::new("Invalid wavetable frames", Kind::Zinnia));
+        }
+
+        let max_harmonic = frames
+            .iter()
+            .map(|f| f.len() / 2)
+            .max()
+            .unwrap_or(1)
+            .min(TABLE_SIZE / 2);
+        let harmonics: Vec<usize> = (0..)
+            .map(|level| max_harmonic >> level)
+            .take_while(|h| *h > 0)
+            .collect();
+
+        let sine: Vec<f32> = (0..TABLE_SIZE)
+            .map(|i| (MAX_PHASE * i as f32 / TABLE_SIZE as f32).sin())
+            .collect();
+
+        let frames = frames
+            .iter()
+            .map(|frame| {
+                let spectrum = spectrum(frame, max_harmonic);
+                Frame {
+                    levels: harmonics
+                        .iter()
+                        .map(|h| synthesize(&spectrum[..*h], &sine))
+                        .collect(),
+                }
+            })
+            .collect();
+
+        Ok(Wavetable { frames, harmonics })
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    // The level with the most harmonics that all stay below Nyquist for a
+    // step of `step` cycles per sample.
+    fn level(&self, step: f32) -> usize {
+        let limit = 0.5 / step.abs();
+        self.harmonics
+            .iter()
+            .position(|h| *h as f32 <= limit)
+            .unwrap_or(self.harmonics.len() - 1)
+    }
+
+    // Value of `frame` at `level`, `phase` being a fraction of a period.
+    fn sample(&self, frame: usize, level: usize, phase: f32) -> f32 {
+        let table = &self.frames[frame].levels[level];
+        let idx_f = phase * TABLE_SIZE as f32;
+        let idx = idx_f as usize % TABLE_SIZE;
+        let lower = table[idx];
+        let upper = table[(idx + 1) % TABLE_SIZE];
+        lower + (upper - lower) * idx_f.fract()
+    }
+}
+
+// Cosine and sine amplitudes of the first `harmonics` harmonics of `frame`.
+fn spectrum(frame: &[f32], harmonics: usize) -> Vec<(f32, f32)> {
+    let len = frame.len();
+    let scale = 2.0 / len as f32;
+    (1..=harmonics)
+        .map(|k| {
+            // Nyquist appears once rather than as a conjugate pair.
+            let scale = if 2 * k == len { scale / 2.0 } else { scale };
+            frame
+                .iter()
+                .enumerate()
+                .fold((0.0, 0.0), |(re, im), (i, v)| {
+                    let turns = ((k * i) % len) as f32 / len as f32;
+                    let angle = MAX_PHASE * turns;
+                    (re + v * angle.cos() * scale, im + v * angle.sin() * scale)
+                })
+        })
+        .collect()
+}
+
+fn synthesize(spectrum: &[(f32, f32)], sine: &[f32]) -> Vec<f32> {
+    let quarter = TABLE_SIZE / 4;
+    (0..TABLE_SIZE)
+        .map(|i| {
+            spectrum.iter().enumerate().fold(0.0, |acc, (k, (re, im))| {
+                let idx = (k + 1) * i % TABLE_SIZE;
+                acc + re * sine[(idx + quarter) % TABLE_SIZE] + im * sine[idx]
+            })
+        })
+        .collect()
+}
+
+/// Plays a `Wavetable`, crossfading between neighbouring frames according to
+/// a position from 0 to the last frame index.
+pub struct WavetableOscillator {
+    table: Arc<Wavetable>,
+    phase: Vec<f32>,
+    step: Vec<f32>,
+    level: Vec<usize>,
+    amplitude: Vec<f32>,
+    start_position: f32,
+    end_position: f32,
+    filters: FilterCollection,
+    ticker: Ticker,
+}
+
+impl WavetableOscillator {
+    pub fn new<T>(
+        table: Arc<Wavetable>,
+        config: &SoundConfigCollection,
+        duration: Duration,
+        hwp: &HardwareParams<T>,
+    ) -> WavetableOscillator
+    where
+        T: IoFormat,
+    {
+        let d = duration_to_ticks(duration, hwp.rate());
+        WavetableOscillator::with_ticks(table, config, d, hwp.rate())
+    }
+
+    pub fn with_ticks(
+        table: Arc<Wavetable>,
+        config: &SoundConfigCollection,
+        ticks: Ticks,
+        rate: Ticks,
+    ) -> WavetableOscillator {
+        let step: Vec<f32> =
+            config.iter().map_freq(|freq| freq / rate as f32).collect();
+
+        WavetableOscillator {
+            phase: config
+                .iter()
+                .map_phase(|phase| (phase / MAX_PHASE).rem_euclid(1.0))
+                .collect(),
+            level: step.iter().map(|s| table.level(*s)).collect(),
+            step,
+            amplitude: config.iter().map_amplitude(|amp| amp).collect(),
+            table,
+            start_position: 0.0,
+            end_position: 0.0,
+            filters: FilterCollection::new(),
+            ticker: Ticker::new(ticks),
+        }
+    }
+
+    /// Holds the morph position at `position` for the whole sound.
+    pub fn set_position(&mut self, position: f32) {
+        self.sweep(position, position);
+    }
+
+    /// Moves the morph position linearly from `start` to `end` over the
+    /// duration of the sound.
+    pub fn sweep(&mut self, start: f32, end: f32) {
+        let last = (self.table.frames() - 1) as f32;
+        self.start_position = start.clamp(0.0, last);
+        self.end_position = end.clamp(0.0, last);
+    }
+
+    pub fn add_filter(&mut self, filter: Box<dyn Filter>) {
+        self.filters.add_filter(filter);
+    }
+
+    fn position(&self) -> f32 {
+        let progress = if self.ticker.duration == 0 {
+            0.0
+        } else {
+            self.ticker.tick_count.min(self.ticker.duration) as f32
+                / self.ticker.duration as f32
+        };
+        self.start_position
+            + (self.end_position - self.start_position) * progress
+    }
+}
+
+impl Sound for WavetableOscillator {
+    fn generate(&mut self, channel: u32) -> f32 {
+        if self.ticker.is_ringing() {
+            return self.filters.apply(0.0, self.ticker.tick_count, channel);
+        }
+
+        let ch = channel as usize;
+        let phase = self.phase[ch];
+        let level = self.level[ch];
+
+        let position = self.position();
+        let frame = position.floor() as usize;
+        let mix = position.fract();
+
+        let mut val = self.table.sample(frame, level, phase);
+        if mix > 0.0 {
+            let next = self.table.sample(frame + 1, level, phase);
+            val += (next - val) * mix;
+        }
+
+        self.phase[ch] = (phase + self.step[ch]).rem_euclid(1.0);
+        self.filters.apply(
+            val * self.amplitude[ch],
+            self.ticker.tick_count,
+            channel,
+        )
+    }
+
+    fn tick(&mut self) {
+        self.filters.tick();
+        self.ticker.tick();
+    }
+
+    fn release(&mut self) {
+        self.filters.release();
+    }
+
+    fn is_complete(&self) -> bool {
+        self.ticker.is_complete(&self.filters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sound::render;
+    use std::f32::consts::PI;
+
+    const RATE: Ticks = 8000;
+
+    fn sine(len: usize, sign: f32) -> Vec<f32> {
+        (0..len)
+            .map(|i| sign * (2.0 * PI * i as f32 / len as f32).sin())
+            .collect()
+    }
+
+    fn saw(len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| 2.0 * i as f32 / len as f32 - 1.0)
+            .collect()
+    }
+
+    fn oscillator(table: Wavetable, freq: f32) -> WavetableOscillator {
+        let config = SoundConfigCollection::with_configs(&[(freq, 0.0, 1.0)]);
+        WavetableOscillator::with_ticks(Arc::new(table), &config, 100, RATE)
+    }
+
+    #[test]
+    fn invalid_frames() {
+        let empty: [&[f32]; 0] = [];
+        assert!(Wavetable::new(&empty).is_err());
+        assert!(Wavetable::new(&[&[1.0]]).is_err());
+    }
+    #[test]
+    fn levels_ok() -> Result<()> {
+        let table = Wavetable::new(&[&saw(256)])?;
+        assert_eq!(table.harmonics, vec![128, 64, 32, 16, 8, 4, 2, 1]);
+        assert_eq!(table.level(10.0 / RATE as f32), 0);
+        assert_eq!(table.level(100.0 / RATE as f32), 2);
+        assert_eq!(table.level(1234.0 / RATE as f32), 6);
+        assert_eq!(table.level(0.9), 7);
+        Ok(())
+    }
+    #[test]
+    fn sine_ok() -> Result<()> {
+        let mut sound = oscillator(Wavetable::new(&[&sine(64, 1.0)])?, 100.0);
+        let expected =
+            (0..80).map(|i| (2.0 * PI * 100.0 * i as f32 / 8000.0).sin());
+        for (val, exp) in render(&mut sound, 80).into_iter().zip(expected) {
+            assert!((val - exp).abs() < 0.001);
+        }
+        Ok(())
+    }
+    #[test]
+    fn pitched_up_band_limited() -> Result<()> {
+        let freq = 1234.0;
+        let mut sound = oscillator(Wavetable::new(&[&saw(512)])?, freq);
+
+        // Only the first two harmonics of the sawtooth fit below Nyquist at
+        // the chosen level.
+        let expected = (0..100).map(|i| {
+            let t = freq * i as f32 / RATE as f32;
+            (1..=2)
+                .map(|k| -2.0 / PI * (2.0 * PI * k as f32 * t).sin() / k as f32)
+                .sum::<f32>()
+        });
+        for (val, exp) in render(&mut sound, 100).into_iter().zip(expected) {
+            assert!((val - exp).abs() < 0.01);
+        }
+        Ok(())
+    }
+    #[test]
+    fn morph_ok() -> Result<()> {
+        let table = || Wavetable::new(&[&sine(64, 1.0), &sine(64, -1.0)]);
+        let mut sound = oscillator(table()?, 2000.0);
+        sound.set_position(0.5);
+        assert!(render(&mut sound, 10).iter().all(|v| v.abs() < 0.001));
+
+        let mut sound = oscillator(table()?, 2000.0);
+        sound.set_position(5.0);
+        let values = render(&mut sound, 2);
+        assert!(values[0].abs() < 0.001 && (values[1] + 1.0).abs() < 0.001);
+
+        let mut sound = oscillator(table()?, 2000.0);
+        sound.sweep(0.0, 1.0);
+        // At 2 kHz every fourth sample sits on a peak of the sine.
+        let peaks: Vec<f32> = render(&mut sound, 100)
+            .into_iter()
+            .skip(1)
+            .step_by(4)
+            .collect();
+        assert!((peaks[0] - 0.98).abs() < 0.001);
+        assert!((peaks[12] - 0.02).abs() < 0.001);
+        assert!((peaks[24] + 0.94).abs() < 0.001);
+        Ok(())
+    }
+}